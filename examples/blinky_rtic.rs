@@ -14,6 +14,11 @@ use hal::pwm::*;
 use hal::stm32;
 use hal::syscfg::SysCfgExt;
 
+use dwt_systick_monotonic::{DwtSystick, ExtU32};
+
+use robo_core::button::{Button, Gesture, Timing};
+
+#[derive(Copy, Clone)]
 pub enum PwmDuty {
     Quarter,
     Half,
@@ -21,30 +26,88 @@ pub enum PwmDuty {
     Full,
 }
 
+impl PwmDuty {
+    fn next(&self) -> PwmDuty {
+        match self {
+            PwmDuty::Quarter => PwmDuty::Half,
+            PwmDuty::Half => PwmDuty::ThreeQuarters,
+            PwmDuty::ThreeQuarters => PwmDuty::Full,
+            PwmDuty::Full => PwmDuty::Quarter,
+        }
+    }
+
+    fn previous(&self) -> PwmDuty {
+        match self {
+            PwmDuty::Quarter => PwmDuty::Full,
+            PwmDuty::Half => PwmDuty::Quarter,
+            PwmDuty::ThreeQuarters => PwmDuty::Half,
+            PwmDuty::Full => PwmDuty::ThreeQuarters,
+        }
+    }
+
+    fn quarters(&self) -> u32 {
+        match self {
+            PwmDuty::Quarter => 1,
+            PwmDuty::Half => 2,
+            PwmDuty::ThreeQuarters => 3,
+            PwmDuty::Full => 4,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            PwmDuty::Quarter => "Quarter",
+            PwmDuty::Half => "Half",
+            PwmDuty::ThreeQuarters => "Three Quarters",
+            PwmDuty::Full => "Full",
+        }
+    }
+}
+
+/// PC13 with its gesture state, fed on both edges and at the deadline.
+pub struct UserButton {
+    pin: gpioc::PC13<Input<PullDown>>,
+    button: Button,
+}
+
+impl UserButton {
+    fn update(&mut self, now_ms: u32) -> Option<Gesture> {
+        self.pin.clear_interrupt_pending_bit();
+        let pressed = self.pin.is_high().unwrap_or(false);
+        self.button.update(pressed, now_ms)
+    }
+}
+
 use hal::time::RateExtU32;
 
-#[rtic::app(device = hal::stm32, peripherals = true)]
+#[rtic::app(device = hal::stm32, peripherals = true, dispatchers = [USART1])]
 mod app {
     use super::*;
 
+    // Default system clocked by HSI (16 MHz)
+    const SYSFREQ: u32 = 16_000_000;
+    #[monotonic(binds = SysTick, default = true)]
+    type Mono = DwtSystick<SYSFREQ>;
+
     #[shared]
-    struct Shared {}
+    struct Shared {
+        button: UserButton,
+    }
 
     #[local]
     struct Local {
-        exti: stm32::EXTI,
-        button: gpioc::PC13<Input<PullDown>>,
         pwm: Pwm<stm32::TIM2, C1, ComplementaryImpossible, ActiveHigh, ActiveHigh>,
         pwm_duty: PwmDuty,
     }
 
     #[init]
-    fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
+    fn init(mut ctx: init::Context) -> (Shared, Local, init::Monotonics) {
         info!("Init system");
 
         let mut exti = ctx.device.EXTI;
         let mut rcc = ctx.device.RCC.constrain();
         let mut syscfg = ctx.device.SYSCFG.constrain();
+        let mono = DwtSystick::new(&mut ctx.core.DCB, ctx.core.DWT, ctx.core.SYST, SYSFREQ);
 
         let port_a = ctx.device.GPIOA.split(&mut rcc);
         let port_c = ctx.device.GPIOC.split(&mut rcc);
@@ -56,50 +119,62 @@ mod app {
         pwm.set_duty(pwm.get_max_duty() / 2);
         pwm.enable();
 
+        // both edges, the press and the release make a gesture
         let mut button = port_c.pc13.into_pull_down_input();
         button.make_interrupt_source(&mut syscfg);
-        button.trigger_on_edge(&mut exti, SignalEdge::Rising);
+        button.trigger_on_edge(&mut exti, SignalEdge::RisingFalling);
         button.enable_interrupt(&mut exti);
 
         (
-            Shared {},
+            Shared {
+                button: UserButton {
+                    pin: button,
+                    button: Button::new(Timing::DEFAULT),
+                },
+            },
             Local {
-                exti,
-                button,
                 pwm,
                 pwm_duty: PwmDuty::Half,
             },
-            init::Monotonics(),
+            init::Monotonics(mono),
         )
     }
 
-    #[task(binds = EXTI15_10, local = [exti, button, pwm, pwm_duty])]
-    fn button_click(ctx: button_click::Context) {
-        let new_pwm_duty = match ctx.local.pwm_duty {
-            PwmDuty::Quarter => {
-                info!("Half");
-                ctx.local.pwm.set_duty(ctx.local.pwm.get_max_duty() / 2);
-                PwmDuty::Half
-            }
-            PwmDuty::Half => {
-                info!("Three Quarters");
-                ctx.local.pwm.set_duty(ctx.local.pwm.get_max_duty() * 3 / 4);
-                PwmDuty::ThreeQuarters
-            }
-            PwmDuty::ThreeQuarters => {
-                info!("Full");
-                ctx.local.pwm.set_duty(ctx.local.pwm.get_max_duty());
-                PwmDuty::Full
-            }
-            PwmDuty::Full => {
-                info!("Quarter");
-                ctx.local.pwm.set_duty(ctx.local.pwm.get_max_duty() / 4);
-                PwmDuty::Quarter
-            }
+    #[task(binds = EXTI15_10, shared = [button])]
+    fn button_edge(mut ctx: button_edge::Context) {
+        ctx.shared.button.lock(update_button);
+    }
+
+    #[task(capacity = 4, shared = [button])]
+    fn button_poll(mut ctx: button_poll::Context) {
+        ctx.shared.button.lock(update_button);
+    }
+
+    /// Feeds the button, hands gestures on and polls at the deadline.
+    fn update_button(button: &mut UserButton) {
+        let now = monotonics::now();
+        let now_ms = now.duration_since_epoch().to_millis() as u32;
+        if let Some(gesture) = button.update(now_ms) {
+            button_gesture::spawn(gesture).ok();
+        }
+        if let Some(delay) = button.button.poll_deadline(now_ms) {
+            button_poll::spawn_at(now + delay.millis()).ok();
+        }
+    }
+
+    /// Click steps the duty up, double click down, a long press goes back
+    /// to a quarter and holding on steps up again.
+    #[task(capacity = 4, local = [pwm, pwm_duty])]
+    fn button_gesture(ctx: button_gesture::Context, gesture: Gesture) {
+        let pwm_duty = ctx.local.pwm_duty;
+        *pwm_duty = match gesture {
+            Gesture::Short | Gesture::Repeat => pwm_duty.next(),
+            Gesture::Double => pwm_duty.previous(),
+            Gesture::Long => PwmDuty::Quarter,
         };
-        *ctx.local.pwm_duty = new_pwm_duty;
-        ctx.local.exti.unpend(hal::exti::Event::GPIO10);
-        ctx.local.button.clear_interrupt_pending_bit();
+        info!("{}: {}", gesture.name(), pwm_duty.name());
+        let max_duty = ctx.local.pwm.get_max_duty();
+        ctx.local.pwm.set_duty(max_duty / 4 * pwm_duty.quarters());
     }
 
     #[idle]
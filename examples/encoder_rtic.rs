@@ -0,0 +1,240 @@
+#![no_std]
+#![no_main]
+
+use panic_halt as _;
+use rtic;
+use stm32g4xx_hal as hal;
+
+use defmt::info;
+use defmt_rtt as _;
+
+use hal::gpio::*;
+use hal::prelude::*;
+use hal::serial::{Event::Rxne, FullConfig, Serial};
+use hal::stm32;
+
+use core::f32::consts::PI;
+use core::fmt::Write;
+
+use robo_core::encoder::Quadrature;
+use robo_core::sensor::{self, Error, RotarySensor, Status};
+
+use dwt_systick_monotonic::*;
+
+use hal::time::{ExtU32, RateExtU32};
+
+/// Lines of the encoder disc, counted on every edge of both channels
+const ENCODER_LINES: u32 = 500;
+
+/// Rate the count is extended and the speed sampled at, the 16 bit count
+/// must not move by half its range in between
+const SAMPLE_HZ: u32 = 1_000;
+
+pub type EncoderPins = (
+    gpiob::PB6<Alternate<2>>,
+    gpiob::PB7<Alternate<2>>,
+    gpiob::PB8<Alternate<2>>,
+);
+
+/// TIM4 in encoder mode with A and B on PB6/PB7, the index on PB8 latching
+/// the count in capture channel 3.
+///
+/// A [`RotarySensor`] like the TLE5012, the angle counts from the index.
+pub struct Encoder {
+    tim: stm32::TIM4,
+    quadrature: Quadrature,
+    _pins: EncoderPins,
+}
+
+impl Encoder {
+    pub fn new(tim: stm32::TIM4, pins: EncoderPins, counts_per_rev: u32) -> Self {
+        unsafe {
+            let rcc = &*stm32::RCC::ptr();
+            rcc.apb1enr1.modify(|_, w| w.tim4en().set_bit());
+
+            // both channels as inputs, filtered over 8 clocks
+            tim.ccmr1_input().write(|w| {
+                w.cc1s()
+                    .bits(0b01)
+                    .ic1f()
+                    .bits(0b0011)
+                    .cc2s()
+                    .bits(0b01)
+                    .ic2f()
+                    .bits(0b0011)
+            });
+            tim.ccmr2_input()
+                .write(|w| w.cc3s().bits(0b01).ic3f().bits(0b0011));
+            // counting every edge of both
+            tim.smcr.write(|w| w.sms().bits(0b011));
+            tim.arr.write(|w| w.bits(0xffff));
+        }
+        tim.ccer.write(|w| w.cc3e().set_bit());
+        tim.dier.write(|w| w.cc3ie().set_bit());
+        tim.cr1.write(|w| w.cen().set_bit());
+
+        let raw = tim.cnt.read().bits() as u16;
+        Encoder {
+            tim,
+            quadrature: Quadrature::new(counts_per_rev, raw),
+            _pins: pins,
+        }
+    }
+
+    /// Extends the count and samples the speed.
+    pub fn update(&mut self, now_us: u32) {
+        let raw = self.tim.cnt.read().bits() as u16;
+        self.quadrature.update(raw, now_us);
+    }
+
+    /// Called from the TIM4 interrupt.
+    pub fn on_interrupt(&mut self) {
+        if self.tim.sr.read().cc3if().bit_is_set() {
+            // reading the capture clears the flag
+            let raw = self.tim.ccr3.read().bits() as u16;
+            self.quadrature.on_index(raw);
+        }
+    }
+
+    pub fn quadrature(&self) -> &Quadrature {
+        &self.quadrature
+    }
+}
+
+impl RotarySensor for Encoder {
+    fn angle(&mut self) -> Result<f32, Error> {
+        Ok(sensor::wrap(sensor::radians(self.quadrature.angle())))
+    }
+
+    fn velocity(&mut self) -> Result<f32, Error> {
+        Ok(sensor::radians(self.quadrature.speed()))
+    }
+
+    fn status(&mut self) -> Result<Status, Error> {
+        Ok(match self.quadrature.index_errors() {
+            0 => Status::OK,
+            _ => Status::DATA_LOST,
+        })
+    }
+
+    fn resolution(&self) -> f32 {
+        2.0 * PI / self.quadrature.counts_per_rev() as f32
+    }
+}
+
+#[rtic::app(device = hal::stm32, peripherals = true, dispatchers = [USART1, UART4])]
+mod app {
+    use super::*;
+
+    // Default system clocked by HSI (16 MHz)
+    const SYS_FREQ: u32 = 16_000_000;
+    #[monotonic(binds = SysTick, default = true)]
+    type Mono = DwtSystick<SYS_FREQ>;
+    type Instant = <Mono as rtic::Monotonic>::Instant;
+
+    #[shared]
+    struct Shared {
+        encoder: Encoder,
+    }
+
+    #[local]
+    struct Local {
+        serial: Serial<stm32::USART2, gpioa::PA2<Alternate<7>>, gpioa::PA3<Alternate<7>>>,
+    }
+
+    #[init]
+    fn init(mut ctx: init::Context) -> (Shared, Local, init::Monotonics) {
+        info!("Init system");
+
+        let mut rcc = ctx.device.RCC.constrain();
+        // monotonic timer
+        let mono = DwtSystick::new(&mut ctx.core.DCB, ctx.core.DWT, ctx.core.SYST, SYS_FREQ);
+
+        info!("Init UART");
+
+        let gpio_a = ctx.device.GPIOA.split(&mut rcc);
+        let gpio_b = ctx.device.GPIOB.split(&mut rcc);
+        let tx = gpio_a.pa2.into_alternate();
+        let rx = gpio_a.pa3.into_alternate();
+
+        let mut serial = ctx
+            .device
+            .USART2
+            .usart(tx, rx, FullConfig::default(), &mut rcc)
+            .unwrap();
+        serial.listen(Rxne);
+
+        writeln!(serial, "Quadrature encoder demo\r\n").unwrap();
+
+        info!("Init TIM4");
+
+        let pins = (
+            gpio_b.pb6.into_alternate(),
+            gpio_b.pb7.into_alternate(),
+            gpio_b.pb8.into_alternate(),
+        );
+        let encoder = Encoder::new(ctx.device.TIM4, pins, 4 * ENCODER_LINES);
+
+        sampler::spawn(None).ok();
+        report::spawn().ok();
+
+        (Shared { encoder }, Local { serial }, init::Monotonics(mono))
+    }
+
+    #[task(binds = TIM4, priority = 3, shared = [encoder])]
+    fn encoder_index(mut ctx: encoder_index::Context) {
+        ctx.shared.encoder.lock(|encoder| encoder.on_interrupt());
+    }
+
+    #[task(priority = 2, shared = [encoder])]
+    fn sampler(mut ctx: sampler::Context, scheduled: Option<Instant>) {
+        let now = scheduled.unwrap_or_else(monotonics::now);
+        let now_us = now.duration_since_epoch().to_micros() as u32;
+        ctx.shared.encoder.lock(|encoder| encoder.update(now_us));
+
+        let next = now + (1_000_000 / SAMPLE_HZ).micros();
+        sampler::spawn_at(next, Some(next)).ok();
+    }
+
+    #[task(local = [serial], shared = [encoder])]
+    fn report(mut ctx: report::Context) {
+        let serial = ctx.local.serial;
+        let (reading, referenced, index_errors) = ctx.shared.encoder.lock(|encoder| {
+            (
+                encoder.read(),
+                encoder.quadrature().is_referenced(),
+                encoder.quadrature().index_errors(),
+            )
+        });
+
+        if let Ok(reading) = reading {
+            writeln!(
+                serial,
+                "Angle value is {}\r\n",
+                sensor::degrees(reading.angle)
+            )
+            .unwrap();
+            writeln!(
+                serial,
+                "Angle speed is {}\r\n",
+                sensor::degrees(reading.velocity)
+            )
+            .unwrap();
+        }
+        if !referenced {
+            writeln!(serial, "No index pulse yet\r\n").unwrap();
+        }
+        if index_errors > 0 {
+            writeln!(serial, "Counts lost at {} index pulses\r\n", index_errors).unwrap();
+        }
+
+        report::spawn_after(200.millis()).unwrap();
+    }
+
+    #[idle]
+    fn idle(_: idle::Context) -> ! {
+        loop {
+            rtic::export::nop();
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! The TLE5012 on SPI1 as a `robo_core::sensor::RotarySensor`.
+//!
+//! Same as the motor drive's adapter, without the angle correction: the
+//! alignment finds the electrical offset instead. Every read goes through
+//! `Health`, retried once when the transfer or the check of the reply
+//! fails and counted for the report.
+
+use core::f32::consts::PI;
+
+use stm32g4xx_hal as hal;
+
+use defmt::warn;
+
+use hal::gpio::{gpioa, Alternate, Output, PushPull};
+use hal::spi::Spi;
+use hal::stm32;
+use robo_core::health::{Health, Stat};
+use robo_core::sensor::{Error, RotarySensor, Status};
+use tle5012::{Error as DriverError, Tle5012};
+
+/// Angle value register, 15 bits a turn
+const ANGLE_BITS: u32 = 15;
+
+/// Angle update period with the default FIR_MD, the speed register holds
+/// the change of the angle over two of them
+const UPDATE_S: f32 = 42.7e-6;
+
+pub type SensorSpi = Spi<
+    stm32::SPI1,
+    (
+        gpioa::PA5<Alternate<5>>,
+        gpioa::PA6<Alternate<5>>,
+        gpioa::PA7<Alternate<5>>,
+    ),
+>;
+
+type Driver = Tle5012<SensorSpi, gpioa::PA4<Output<PushPull>>>;
+
+pub struct Tle5012Sensor {
+    /// None when the sensor did not answer at start up
+    driver: Option<Driver>,
+    health: Health,
+}
+
+impl Tle5012Sensor {
+    /// Sets up the sensor, every read of one that does not answer fails
+    /// with `NotReady`.
+    pub fn new(spi: SensorSpi, nss: gpioa::PA4<Output<PushPull>>) -> Self {
+        let driver = probe(spi, nss);
+        if driver.is_none() {
+            warn!("TLE5012 did not answer");
+        }
+        Tle5012Sensor {
+            driver,
+            health: Health::new(),
+        }
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.driver.is_some()
+    }
+
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    /// Runs `read` on the driver through `health`.
+    fn guarded<T>(
+        &mut self,
+        mut read: impl FnMut(&mut Driver) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let Tle5012Sensor { driver, health } = self;
+        health.retry(|| driver.as_mut().map_or(Err(Error::NotReady), &mut read))
+    }
+}
+
+/// The driver takes any bus, a status read tells whether a sensor answers.
+/// It also clears the reset flag latched at power up.
+fn probe(spi: SensorSpi, nss: gpioa::PA4<Output<PushPull>>) -> Option<Driver> {
+    let mut driver = Tle5012::new(spi, nss).ok()?;
+    match checked(driver.read_status()) {
+        Err(Error::Bus | Error::Crc) => None,
+        _ => Some(driver),
+    }
+}
+
+/// Maps the driver errors onto the sensor's.
+fn checked<T, E>(result: Result<T, DriverError<E>>) -> Result<T, Error> {
+    result.map_err(|error| match error {
+        // the transfer failed, or the sensor did not take the command
+        DriverError::Spi(_) | DriverError::InterfaceAccess => Error::Bus,
+        DriverError::Crc => Error::Crc,
+        // the safety word flags no valid angle yet, or STAT flags such as
+        // the reset after power up
+        DriverError::InvalidAngle | DriverError::System => Error::NotReady,
+    })
+}
+
+impl RotarySensor for Tle5012Sensor {
+    fn angle(&mut self) -> Result<f32, Error> {
+        self.guarded(|driver| checked(driver.read_angle_value()))
+            .map(|angle| angle as f32 * self.resolution())
+    }
+
+    fn velocity(&mut self) -> Result<f32, Error> {
+        self.guarded(|driver| checked(driver.read_angle_speed()))
+            .map(|speed| speed as f32 * self.resolution() / (2.0 * UPDATE_S))
+    }
+
+    fn status(&mut self) -> Result<Status, Error> {
+        let stat = self.guarded(|driver| checked(driver.read_status()))?;
+        Ok(Stat::from_register(stat).status())
+    }
+
+    fn resolution(&self) -> f32 {
+        2.0 * PI / (1 << ANGLE_BITS) as f32
+    }
+}
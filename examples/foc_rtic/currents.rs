@@ -0,0 +1,87 @@
+//! Phase currents from ADC1 injected conversions.
+//!
+//! Phase a on PA0 (ADC1_IN1) and phase b on PA1 (ADC1_IN2) are converted
+//! on every rising edge of TIM1 channel 4, while the low side switches the
+//! shunts sit in are on. The end of the sequence raises ADC1_2.
+
+use stm32g4xx_hal as hal;
+
+use hal::gpio::{gpioa, Analog};
+use hal::stm32;
+
+pub type CurrentPins = (gpioa::PA0<Analog>, gpioa::PA1<Analog>);
+
+// injected trigger TIM1_CH4, on its rising edge
+const JEXTSEL_TIM1_CH4: u8 = 1;
+const JEXTEN_RISING: u8 = 0b01;
+
+/// 24.5 ADC clocks, the shunt amplifiers settle within the first of them
+const SAMPLE_TIME: u8 = 0b011;
+
+pub struct Currents {
+    adc: stm32::ADC1,
+    _pins: CurrentPins,
+}
+
+impl Currents {
+    /// Powers up and calibrates ADC1 clocked by the core at `clock_hz`, and
+    /// arms the injected conversions.
+    pub fn new(
+        adc: stm32::ADC1,
+        common: &stm32::ADC12_COMMON,
+        pins: CurrentPins,
+        clock_hz: u32,
+    ) -> Self {
+        unsafe {
+            let rcc = &*stm32::RCC::ptr();
+            rcc.ahb2enr.modify(|_, w| w.adc12en().set_bit());
+            // synchronous, the core clock
+            common.ccr.modify(|_, w| w.ckmode().bits(0b01));
+
+            adc.cr.modify(|_, w| w.deeppwd().clear_bit());
+            adc.cr.modify(|_, w| w.advregen().set_bit());
+            // regulator start up, 20 us
+            cortex_m::asm::delay(clock_hz / 50_000);
+
+            adc.cr.modify(|_, w| w.adcal().set_bit());
+            while adc.cr.read().adcal().bit_is_set() {}
+
+            adc.isr.write(|w| w.adrdy().set_bit());
+            adc.cr.modify(|_, w| w.aden().set_bit());
+            while adc.isr.read().adrdy().bit_is_clear() {}
+
+            adc.smpr1
+                .modify(|_, w| w.smp1().bits(SAMPLE_TIME).smp2().bits(SAMPLE_TIME));
+            // two conversions, a then b
+            adc.jsqr.write(|w| {
+                w.jl()
+                    .bits(1)
+                    .jextsel()
+                    .bits(JEXTSEL_TIM1_CH4)
+                    .jexten()
+                    .bits(JEXTEN_RISING)
+                    .jsq1()
+                    .bits(1)
+                    .jsq2()
+                    .bits(2)
+            });
+        }
+        adc.ier.write(|w| w.jeosie().set_bit());
+        adc.cr.modify(|_, w| w.jadstart().set_bit());
+
+        Currents { adc, _pins: pins }
+    }
+
+    /// Counts of phase a and b once a sequence has ended, called from
+    /// ADC1_2.
+    pub fn take(&mut self) -> Option<[u16; 2]> {
+        if self.adc.isr.read().jeos().bit_is_clear() {
+            return None;
+        }
+        self.adc.isr.write(|w| w.jeos().set_bit().jeoc().set_bit());
+        Some([
+            self.adc.jdr1.read().bits() as u16,
+            self.adc.jdr2.read().bits() as u16,
+        ])
+    }
+}
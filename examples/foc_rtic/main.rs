@@ -0,0 +1,374 @@
+#![no_std]
+#![no_main]
+
+//! Field oriented control of a BLDC motor.
+//!
+//! TIM1 drives the three half bridges, the phase currents are converted at
+//! the middle of every PWM period and the control runs in the ADC
+//! interrupt: currents into the rotor frame with the TLE5012 angle, the
+//! current loop, and the voltage back to the duties.
+//!
+//! At start the current offsets are measured with the bridges idle, then
+//! the rotor is pulled to electrical angle zero to find the offset of the
+//! sensor. Over USART2 '+' and '-' step the torque current, '0' sets it
+//! back to zero. Without a sensor answering the bridges stay off.
+
+mod angle_sensor;
+mod currents;
+mod pwm;
+
+use panic_halt as _;
+use rtic;
+use stm32g4xx_hal as hal;
+
+use defmt::info;
+use defmt_rtt as _;
+
+use hal::gpio::*;
+use hal::prelude::*;
+use hal::serial::{Event::Rxne, FullConfig, Serial};
+use hal::stm32;
+
+use core::fmt::Write;
+
+use robo_core::foc::{self, Abc, CurrentLoop, Dq, ElectricalAngle, SinCos};
+use robo_core::sensor::{self, Error, RotarySensor};
+use tle5012::MODE;
+
+use dwt_systick_monotonic::*;
+
+use hal::time::{ExtU32, RateExtU32};
+
+use angle_sensor::Tle5012Sensor;
+use currents::Currents;
+use pwm::Pwm;
+
+/// Switching and control rate
+const PWM_HZ: u32 = 10_000;
+
+/// Both switches of a leg off between either being on
+const DEAD_TIME_NS: u32 = 1_000;
+
+/// Supply of the bridges
+const BUS_VOLTS: f32 = 12.0;
+
+/// Amps per ADC count of the shunt amplifiers, current into the motor
+/// reads positive
+const AMPS_PER_COUNT: f32 = 3.3 / 4096.0 / (0.33 * 1.53);
+
+/// Counts averaged for the offsets of the shunt amplifiers
+const OFFSET_SAMPLES: u32 = 1024;
+
+// motor
+const POLE_PAIRS: u8 = 7;
+const RESISTANCE: f32 = 1.2;
+const INDUCTANCE: f32 = 0.6e-3;
+
+/// Closed loop bandwidth of the current loop
+const CURRENT_BANDWIDTH_HZ: f32 = 1_000.0;
+
+/// Flux current pulling the rotor to electrical zero, and for how long
+const ALIGN_AMPS: f32 = 1.0;
+const ALIGN_MS: u32 = 500;
+
+/// Torque current steps of a key press, and the most there is
+const STEP_AMPS: f32 = 0.1;
+const MAX_AMPS: f32 = 2.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Mode {
+    /// Bridges idle, summing the counts of phase a and b
+    Offsets {
+        samples: u32,
+        sums: [u32; 2],
+    },
+    /// Flux current along electrical zero for `ticks` more periods
+    Align {
+        ticks: u32,
+    },
+    Run,
+    /// The angle could not be read, the bridges are off
+    Fault(Error),
+}
+
+/// The control state of the ADC interrupt.
+struct Drive<S> {
+    sensor: S,
+    mode: Mode,
+    offsets: [f32; 2],
+    current_loop: CurrentLoop,
+    electrical: ElectricalAngle,
+    /// Torque current asked for
+    target: f32,
+    /// Currents in the rotor frame, as last measured
+    measured: Dq,
+}
+
+impl<S: RotarySensor> Drive<S> {
+    fn new(sensor: S) -> Self {
+        Drive {
+            sensor,
+            mode: Mode::Offsets {
+                samples: 0,
+                sums: [0; 2],
+            },
+            offsets: [0.0; 2],
+            current_loop: CurrentLoop::tuned(
+                RESISTANCE,
+                INDUCTANCE,
+                CURRENT_BANDWIDTH_HZ,
+                PWM_HZ as f32,
+            ),
+            electrical: ElectricalAngle::new(POLE_PAIRS),
+            target: 0.0,
+            measured: Dq::default(),
+        }
+    }
+
+    /// Duties for the next period from the phase counts and the shaft
+    /// angle, None turns the bridges off.
+    fn step(&mut self, counts: [u16; 2]) -> Option<Abc> {
+        const IDLE: Abc = Abc {
+            a: 0.5,
+            b: 0.5,
+            c: 0.5,
+        };
+
+        let angle = match self.mode {
+            Mode::Align { .. } | Mode::Run => self.sensor.angle(),
+            // read once the offsets are known
+            _ => Err(Error::NotReady),
+        };
+
+        let (target, electrical) = match (self.mode, angle) {
+            (Mode::Fault(_), _) => return None,
+            (Mode::Offsets { samples, sums }, _) => {
+                let sums = [sums[0] + counts[0] as u32, sums[1] + counts[1] as u32];
+                let samples = samples + 1;
+                self.mode = if samples < OFFSET_SAMPLES {
+                    Mode::Offsets { samples, sums }
+                } else {
+                    let count = samples as f32;
+                    self.offsets = [sums[0] as f32 / count, sums[1] as f32 / count];
+                    self.current_loop.reset();
+                    Mode::Align {
+                        ticks: ALIGN_MS * PWM_HZ / 1_000,
+                    }
+                };
+                return Some(IDLE);
+            }
+            (_, Err(error)) => {
+                self.mode = Mode::Fault(error);
+                return None;
+            }
+            (Mode::Align { ticks: 0 }, Ok(angle)) => {
+                self.electrical.align(angle);
+                self.current_loop.reset();
+                self.mode = Mode::Run;
+                return Some(IDLE);
+            }
+            (Mode::Align { ticks }, Ok(_)) => {
+                self.mode = Mode::Align { ticks: ticks - 1 };
+                (
+                    Dq {
+                        d: ALIGN_AMPS,
+                        q: 0.0,
+                    },
+                    0.0,
+                )
+            }
+            (Mode::Run, Ok(angle)) => (
+                Dq {
+                    d: 0.0,
+                    q: self.target,
+                },
+                self.electrical.electrical(angle),
+            ),
+        };
+
+        let a = (counts[0] as f32 - self.offsets[0]) * AMPS_PER_COUNT;
+        let b = (counts[1] as f32 - self.offsets[1]) * AMPS_PER_COUNT;
+        let rotor = SinCos::new(electrical);
+        self.measured = foc::park(foc::clarke(a, b), rotor);
+        let voltage = self.current_loop.update(target, self.measured, BUS_VOLTS);
+        Some(foc::svpwm(foc::inverse_park(voltage, rotor), BUS_VOLTS))
+    }
+
+    fn set_target(&mut self, amps: f32) {
+        self.target = amps.clamp(-MAX_AMPS, MAX_AMPS);
+    }
+}
+
+#[rtic::app(device = hal::stm32, peripherals = true, dispatchers = [USART1])]
+mod app {
+    use super::*;
+
+    // Default system clocked by HSI (16 MHz)
+    const SYS_FREQ: u32 = 16_000_000;
+    #[monotonic(binds = SysTick, default = true)]
+    type Mono = DwtSystick<SYS_FREQ>;
+
+    #[shared]
+    struct Shared {
+        drive: Drive<Tle5012Sensor>,
+        #[lock_free]
+        serial: Serial<stm32::USART2, gpioa::PA2<Alternate<7>>, gpioa::PA3<Alternate<7>>>,
+    }
+
+    #[local]
+    struct Local {
+        pwm: Pwm,
+        currents: Currents,
+    }
+
+    #[init]
+    fn init(mut ctx: init::Context) -> (Shared, Local, init::Monotonics) {
+        info!("Init system");
+
+        let mut rcc = ctx.device.RCC.constrain();
+        // monotonic timer
+        let mono = DwtSystick::new(&mut ctx.core.DCB, ctx.core.DWT, ctx.core.SYST, SYS_FREQ);
+
+        info!("Init UART");
+
+        let gpio_a = ctx.device.GPIOA.split(&mut rcc);
+        let gpio_b = ctx.device.GPIOB.split(&mut rcc);
+        let tx = gpio_a.pa2.into_alternate();
+        let rx = gpio_a.pa3.into_alternate();
+
+        let mut serial = ctx
+            .device
+            .USART2
+            .usart(tx, rx, FullConfig::default(), &mut rcc)
+            .unwrap();
+        serial.listen(Rxne);
+
+        writeln!(serial, "FOC demo\r\n").unwrap();
+
+        info!("Init SPI");
+
+        // NSS on PA4, PA9 is a bridge output here
+        let sck = gpio_a.pa5.into_alternate();
+        let miso = gpio_a.pa6.into_alternate();
+        let mosi = gpio_a.pa7.into_alternate();
+        let mut nss = gpio_a.pa4.into_push_pull_output();
+        nss.set_high().ok();
+
+        let spi = ctx
+            .device
+            .SPI1
+            .spi((sck, miso, mosi), MODE, 8.MHz(), &mut rcc);
+        let angle_sensor = Tle5012Sensor::new(spi, nss);
+        let present = angle_sensor.is_present();
+        let mut drive = Drive::new(angle_sensor);
+
+        info!("Init ADC1");
+
+        let pins = (gpio_a.pa0.into_analog(), gpio_a.pa1.into_analog());
+        let currents = Currents::new(ctx.device.ADC1, &ctx.device.ADC12_COMMON, pins, SYS_FREQ);
+
+        info!("Init TIM1");
+
+        let pins = (
+            gpio_a.pa8.into_alternate(),
+            gpio_a.pa9.into_alternate(),
+            gpio_a.pa10.into_alternate(),
+            gpio_b.pb13.into_alternate(),
+            gpio_b.pb14.into_alternate(),
+            gpio_b.pb15.into_alternate(),
+        );
+        let mut pwm = Pwm::new(ctx.device.TIM1, pins, SYS_FREQ, PWM_HZ, DEAD_TIME_NS);
+        if present {
+            pwm.enable();
+        } else {
+            // the outputs stay off, the first ADC interrupt keeps them off
+            drive.mode = Mode::Fault(Error::NotReady);
+        }
+
+        report::spawn().ok();
+
+        (
+            Shared { drive, serial },
+            Local { pwm, currents },
+            init::Monotonics(mono),
+        )
+    }
+
+    #[task(binds = ADC1_2, priority = 3, local = [pwm, currents], shared = [drive])]
+    fn control(mut ctx: control::Context) {
+        let control::LocalResources { pwm, currents } = ctx.local;
+
+        let counts = match currents.take() {
+            Some(counts) => counts,
+            None => return,
+        };
+
+        match ctx.shared.drive.lock(|drive| drive.step(counts)) {
+            Some(duty) => pwm.set_duty(duty),
+            None => pwm.disable(),
+        }
+    }
+
+    #[task(binds = USART2, shared = [drive, serial])]
+    fn keys(mut ctx: keys::Context) {
+        let key = match ctx.shared.serial.read() {
+            Ok(key) => key,
+            Err(_) => return,
+        };
+        ctx.shared.drive.lock(|drive| match key {
+            b'+' => drive.set_target(drive.target + STEP_AMPS),
+            b'-' => drive.set_target(drive.target - STEP_AMPS),
+            b'0' => drive.set_target(0.0),
+            _ => {}
+        });
+    }
+
+    #[task(shared = [drive, serial])]
+    fn report(mut ctx: report::Context) {
+        let serial = ctx.shared.serial;
+        let (mode, target, measured, offset, present, health) = ctx.shared.drive.lock(|drive| {
+            (
+                drive.mode,
+                drive.target,
+                drive.measured,
+                drive.electrical.offset,
+                drive.sensor.is_present(),
+                drive.sensor.health().clone(),
+            )
+        });
+
+        match mode {
+            Mode::Offsets { .. } => writeln!(serial, "Measuring current offsets\r\n").unwrap(),
+            Mode::Align { .. } => writeln!(serial, "Aligning the rotor\r\n").unwrap(),
+            Mode::Run => writeln!(
+                serial,
+                "Iq {} of {} A, Id {} A, electrical offset {}\r\n",
+                measured.q,
+                target,
+                measured.d,
+                sensor::degrees(offset)
+            )
+            .unwrap(),
+            Mode::Fault(_) if !present => {
+                writeln!(serial, "Bridges off, the TLE5012 did not answer\r\n").unwrap()
+            }
+            Mode::Fault(error) => writeln!(
+                serial,
+                "Bridges off, angle read {} after {} reads, {} retried\r\n",
+                error.name(),
+                health.reads(),
+                health.retries()
+            )
+            .unwrap(),
+        }
+
+        report::spawn_after(200.millis()).unwrap();
+    }
+
+    #[idle]
+    fn idle(_: idle::Context) -> ! {
+        loop {
+            rtic::export::nop();
+        }
+    }
+}
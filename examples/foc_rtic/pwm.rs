@@ -0,0 +1,132 @@
+//! TIM1 three phase complementary PWM, centre aligned with dead time.
+//!
+//! High sides on PA8/PA9/PA10, low sides on PB13/PB14/PB15. Channel 4 has
+//! no pin, its reference goes high at the top of the count, the middle of
+//! the low side on time, and triggers the current conversions.
+
+use stm32g4xx_hal as hal;
+
+use hal::gpio::{gpioa, gpiob, Alternate};
+use hal::stm32;
+use robo_core::foc::Abc;
+
+pub type PwmPins = (
+    gpioa::PA8<Alternate<6>>,
+    gpioa::PA9<Alternate<6>>,
+    gpioa::PA10<Alternate<6>>,
+    gpiob::PB13<Alternate<6>>,
+    gpiob::PB14<Alternate<6>>,
+    gpiob::PB15<Alternate<4>>,
+);
+
+// output compare modes
+const PWM_MODE_1: u8 = 0b110;
+const PWM_MODE_2: u8 = 0b111;
+
+pub struct Pwm {
+    tim: stm32::TIM1,
+    /// Top of the count, a full duty
+    period: u16,
+    _pins: PwmPins,
+}
+
+impl Pwm {
+    /// Counts up and down at `clock_hz` for a period of `pwm_hz`, both
+    /// switches of a leg off for `dead_ns` in between. The outputs stay off
+    /// until [`enable`](Self::enable).
+    pub fn new(tim: stm32::TIM1, pins: PwmPins, clock_hz: u32, pwm_hz: u32, dead_ns: u32) -> Self {
+        let period = (clock_hz / pwm_hz / 2) as u16;
+        // dead time in clocks, up to 127
+        let dead = (clock_hz / 1_000 * dead_ns / 1_000_000).min(127) as u8;
+
+        unsafe {
+            let rcc = &*stm32::RCC::ptr();
+            rcc.apb2enr.modify(|_, w| w.tim1en().set_bit());
+
+            tim.psc.write(|w| w.psc().bits(0));
+            tim.arr.write(|w| w.bits(period as u32));
+            // one update per period, at the bottom of the count
+            tim.rcr.write(|w| w.rep().bits(1));
+
+            tim.ccmr1_output().write(|w| {
+                w.oc1m()
+                    .bits(PWM_MODE_1)
+                    .oc1pe()
+                    .set_bit()
+                    .oc2m()
+                    .bits(PWM_MODE_1)
+                    .oc2pe()
+                    .set_bit()
+            });
+            tim.ccmr2_output().write(|w| {
+                w.oc3m()
+                    .bits(PWM_MODE_1)
+                    .oc3pe()
+                    .set_bit()
+                    .oc4m()
+                    .bits(PWM_MODE_2)
+                    .oc4pe()
+                    .set_bit()
+            });
+            tim.ccr4.write(|w| w.bits(period as u32 - 1));
+            tim.bdtr
+                .write(|w| w.dtg().bits(dead).ossr().set_bit().ossi().set_bit());
+        }
+        tim.ccer.write(|w| {
+            w.cc1e()
+                .set_bit()
+                .cc1ne()
+                .set_bit()
+                .cc2e()
+                .set_bit()
+                .cc2ne()
+                .set_bit()
+                .cc3e()
+                .set_bit()
+                .cc3ne()
+                .set_bit()
+                // the ADC trigger, no pin is mapped to channel 4
+                .cc4e()
+                .set_bit()
+        });
+
+        let mut pwm = Pwm {
+            tim,
+            period,
+            _pins: pins,
+        };
+        pwm.set_duty(Abc {
+            a: 0.5,
+            b: 0.5,
+            c: 0.5,
+        });
+        pwm.tim.egr.write(|w| w.ug().set_bit());
+        unsafe {
+            // centre aligned, the compare flags set counting up and down
+            pwm.tim
+                .cr1
+                .write(|w| w.cms().bits(0b01).arpe().set_bit().cen().set_bit());
+        }
+        pwm
+    }
+
+    /// Duties from 0 to 1, taken at the next period.
+    pub fn set_duty(&mut self, duty: Abc) {
+        let period = self.period as f32;
+        let count = |duty: f32| (duty.clamp(0.0, 1.0) * period) as u32;
+        unsafe {
+            self.tim.ccr1.write(|w| w.bits(count(duty.a)));
+            self.tim.ccr2.write(|w| w.bits(count(duty.b)));
+            self.tim.ccr3.write(|w| w.bits(count(duty.c)));
+        }
+    }
+
+    pub fn enable(&mut self) {
+        self.tim.bdtr.modify(|_, w| w.moe().set_bit());
+    }
+
+    /// Both switches of every leg off, the motor coasts.
+    pub fn disable(&mut self) {
+        self.tim.bdtr.modify(|_, w| w.moe().clear_bit());
+    }
+}
@@ -0,0 +1,149 @@
+//! The TLE5012 as a `robo_core::sensor::RotarySensor`.
+//!
+//! The driver reads raw 15 bit counts, they are converted to radians here
+//! so nothing past this module knows which sensor is fitted. Every read goes
+//! through `Health`, retried once when the transfer or the check of the
+//! reply fails and counted by error for the `sensor` command. Angles come
+//! out corrected by the `calibrate` results, from the mechanical zero.
+
+use core::f32::consts::PI;
+
+use stm32g4xx_hal as hal;
+
+use hal::gpio::{gpioa, Output, PushPull};
+use robo_core::calibrate::AngleCorrection;
+use robo_core::health::{Health, Stat};
+use robo_core::sensor::{Error, RotarySensor, Status};
+use tle5012::{Error as DriverError, Tle5012};
+
+use crate::faults::{self, Faults};
+use crate::spi_bus::SensorSpi;
+
+/// Angle value register, 15 bits a turn
+const ANGLE_BITS: u32 = 15;
+
+/// Angle update period with the default FIR_MD, the speed register holds
+/// the change of the angle over two of them
+const UPDATE_S: f32 = 42.7e-6;
+
+type Driver = Tle5012<SensorSpi, gpioa::PA9<Output<PushPull>>>;
+
+pub struct Tle5012Sensor {
+    /// None when the sensor did not answer at start up
+    driver: Option<Driver>,
+    health: Health,
+    /// STAT as last read
+    stat: Stat,
+    correction: AngleCorrection,
+}
+
+impl Tle5012Sensor {
+    /// Sets up the sensor, a sensor that does not answer raises `SENSOR`
+    /// and every read of it fails with `NotReady`.
+    pub fn new(spi: SensorSpi, nss: gpioa::PA9<Output<PushPull>>) -> Self {
+        let driver = probe(spi, nss);
+        if driver.is_none() {
+            faults::raise(Faults::SENSOR);
+            error!(App, "TLE5012 did not answer");
+        }
+        Tle5012Sensor {
+            driver,
+            health: Health::new(),
+            stat: Stat::OK,
+            correction: AngleCorrection::NONE,
+        }
+    }
+
+    pub fn correction(&self) -> &AngleCorrection {
+        &self.correction
+    }
+
+    /// Corrects the angles from now on, `NONE` reads them as they are.
+    pub fn set_correction(&mut self, correction: AngleCorrection) {
+        self.correction = correction;
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.driver.is_some()
+    }
+
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    pub fn stat(&self) -> Stat {
+        self.stat
+    }
+
+    /// Reads the status register and raises `SENSOR` once reads keep
+    /// failing or the sensor flags a lost signal or a failed self test,
+    /// true when it is new.
+    pub fn check(&mut self) -> bool {
+        let status = self.status();
+        let failed = match status {
+            Ok(status) => status.contains(Status::SIGNAL_LOST) || status.contains(Status::FAULT),
+            Err(_) => self.health.is_failing(),
+        };
+        if failed && faults::raise(Faults::SENSOR) {
+            match status {
+                Ok(_) => warn!(App, "angle sensor flags {}", self.stat.bits()),
+                Err(error) => warn!(App, "angle sensor reads failing, {}", error.name()),
+            }
+            return true;
+        }
+        false
+    }
+
+    /// Runs `read` on the driver through `health`.
+    fn guarded<T>(
+        &mut self,
+        mut read: impl FnMut(&mut Driver) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let Tle5012Sensor { driver, health, .. } = self;
+        health.retry(|| driver.as_mut().map_or(Err(Error::NotReady), &mut read))
+    }
+}
+
+/// The driver takes any bus, a status read tells whether a sensor answers.
+/// It also clears the reset flag latched at power up.
+fn probe(spi: SensorSpi, nss: gpioa::PA9<Output<PushPull>>) -> Option<Driver> {
+    let mut driver = Tle5012::new(spi, nss).ok()?;
+    match checked(driver.read_status()) {
+        Err(Error::Bus | Error::Crc) => None,
+        _ => Some(driver),
+    }
+}
+
+/// Maps the driver errors onto the sensor's.
+fn checked<T, E>(result: Result<T, DriverError<E>>) -> Result<T, Error> {
+    result.map_err(|error| match error {
+        // the transfer failed, or the sensor did not take the command
+        DriverError::Spi(_) | DriverError::InterfaceAccess => Error::Bus,
+        DriverError::Crc => Error::Crc,
+        // the safety word flags no valid angle yet, or STAT flags such as
+        // the reset after power up
+        DriverError::InvalidAngle | DriverError::System => Error::NotReady,
+    })
+}
+
+impl RotarySensor for Tle5012Sensor {
+    fn angle(&mut self) -> Result<f32, Error> {
+        self.guarded(|driver| checked(driver.read_angle_value()))
+            .map(|angle| self.correction.apply(angle as f32 * self.resolution()))
+    }
+
+    fn velocity(&mut self) -> Result<f32, Error> {
+        self.guarded(|driver| checked(driver.read_angle_speed()))
+            .map(|speed| speed as f32 * self.resolution() / (2.0 * UPDATE_S))
+    }
+
+    fn status(&mut self) -> Result<Status, Error> {
+        let stat = self.guarded(|driver| checked(driver.read_status()))?;
+        self.stat = Stat::from_register(stat);
+        Ok(self.stat.status())
+    }
+
+    fn resolution(&self) -> f32 {
+        2.0 * PI / (1 << ANGLE_BITS) as f32
+    }
+}
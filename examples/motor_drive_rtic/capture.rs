@@ -0,0 +1,87 @@
+use robo_core::capture;
+use robo_core::telemetry::{ChannelSet, Message, Sample, Snapshot};
+
+use crate::telemetry::{self, Telemetry};
+use crate::CONTROL_HZ;
+
+/// 16K of RAM, one second of four channels at `CONTROL_HZ`
+pub const CAPTURE_LEN: usize = 4096;
+
+pub type Capture = capture::Capture<CAPTURE_LEN>;
+
+/// Capture fed by the sampler, which also paces its replay over telemetry.
+pub struct Scope {
+    pub capture: Capture,
+    /// Channels recorded from the next `arm`
+    pub channels: ChannelSet,
+    // next record to send, `None` before the config message
+    replay: Option<Option<usize>>,
+}
+
+impl Scope {
+    pub const fn new() -> Self {
+        Scope {
+            capture: Capture::new(),
+            channels: telemetry::AVAILABLE,
+            replay: None,
+        }
+    }
+
+    /// Starts sending the finished capture as telemetry samples.
+    ///
+    /// Records go out as samples numbered from 0, stamped relative to the
+    /// sampler clock so the trigger keeps its timestamp.
+    pub fn replay(&mut self) -> bool {
+        if self.capture.state() != capture::State::Done {
+            return false;
+        }
+        self.replay = Some(None);
+        true
+    }
+
+    /// Records sent and the total while a replay is running.
+    pub fn replay_progress(&self) -> Option<(usize, usize)> {
+        self.replay
+            .map(|next| (next.unwrap_or(0), self.capture.depth()))
+    }
+
+    pub fn sample(&mut self, snapshot: &Snapshot, telemetry: &mut Telemetry) {
+        self.capture.sample(snapshot);
+
+        let next = match self.replay {
+            Some(next) => next,
+            None => return,
+        };
+        let message = match next {
+            None => Message::Config {
+                rate_hz: CONTROL_HZ,
+                decimation: 1,
+                channels: self.capture.channels(),
+            },
+            Some(n) => match self.capture.record(n) {
+                Some(record) => {
+                    let period_us = 1_000_000 / CONTROL_HZ;
+                    Message::Sample(Sample {
+                        seq: n as u16,
+                        timestamp_us: self
+                            .capture
+                            .trigger_us()
+                            .wrapping_add((record.offset * period_us as i32) as u32),
+                        channels: self.capture.channels(),
+                        values: record.values.iter().copied().collect(),
+                    })
+                }
+                // done, or the capture was re-armed meanwhile
+                None => {
+                    self.replay = None;
+                    return;
+                }
+            },
+        };
+
+        // one frame per tick, a full sink is retried on the next one
+        if telemetry.try_send(&message) {
+            self.replay = Some(Some(next.map_or(0, |n| n + 1)));
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Panic and HardFault handlers keeping a crash report across the reset.
+//!
+//! Both handlers hard brake the motor, write a [`Report`] into a `.uninit`
+//! RAM slot that the runtime does not zero and reset the MCU. [`take`] reads
+//! it back on the next boot, `init` keeps the last one in the parameter page
+//! so that it survives a power cycle too.
+
+use core::fmt::{self, Write};
+use core::mem::{size_of, MaybeUninit};
+use core::panic::PanicInfo;
+use core::ptr;
+
+use cortex_m::peripheral::SCB;
+use cortex_m::{interrupt, register};
+use cortex_m_rt::{exception, ExceptionFrame};
+
+use crate::Mx1508;
+
+/// Bytes kept of the panic message, location included.
+pub const MESSAGE_LEN: usize = 88;
+
+const MAGIC: u32 = 0x4352_5348;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Kind {
+    Panic = 1,
+    HardFault = 2,
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Panic => "panic",
+            Kind::HardFault => "hard_fault",
+        }
+    }
+}
+
+/// What the MCU was doing when it crashed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct Report {
+    // `Kind` or 0 for none, a plain word so any flash content is valid
+    kind: u32,
+    /// Faulting instruction, the panic handler itself for a panic
+    pub pc: u32,
+    pub lr: u32,
+    /// Configurable fault status
+    pub cfsr: u32,
+    /// HardFault status
+    pub hfsr: u32,
+    /// MemManage fault address, valid if `cfsr` says so
+    pub mmfar: u32,
+    /// BusFault address, valid if `cfsr` says so
+    pub bfar: u32,
+    len: u32,
+    message: [u8; MESSAGE_LEN],
+}
+
+impl Report {
+    pub const NONE: Report = Report {
+        kind: 0,
+        pc: 0,
+        lr: 0,
+        cfsr: 0,
+        hfsr: 0,
+        mmfar: 0,
+        bfar: 0,
+        len: 0,
+        message: [0; MESSAGE_LEN],
+    };
+
+    pub fn kind(&self) -> Option<Kind> {
+        match self.kind {
+            1 => Some(Kind::Panic),
+            2 => Some(Kind::HardFault),
+            _ => None,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        let message = &self.message[..(self.len as usize).min(MESSAGE_LEN)];
+        // cut mid character at worst
+        match core::str::from_utf8(message) {
+            Ok(message) => message,
+            Err(error) => core::str::from_utf8(&message[..error.valid_up_to()]).unwrap_or(""),
+        }
+    }
+
+    fn new(kind: Kind, pc: u32, lr: u32) -> Self {
+        // fault status registers are read-only for the core, no side effects
+        let scb = unsafe { &*SCB::PTR };
+        Report {
+            kind: kind as u32,
+            pc,
+            lr,
+            cfsr: scb.cfsr.read(),
+            hfsr: scb.hfsr.read(),
+            mmfar: scb.mmfar.read(),
+            bfar: scb.bfar.read(),
+            ..Report::NONE
+        }
+    }
+}
+
+impl Write for Report {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if self.len as usize == MESSAGE_LEN {
+                return Err(fmt::Error);
+            }
+            // the location ends with a line break
+            self.message[self.len as usize] = if byte == b'\n' { b' ' } else { byte };
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+#[repr(C)]
+struct Slot {
+    magic: u32,
+    checksum: u32,
+    report: Report,
+}
+
+#[link_section = ".uninit.CRASH"]
+static mut SLOT: MaybeUninit<Slot> = MaybeUninit::uninit();
+
+fn checksum(report: &Report) -> u32 {
+    let words = unsafe {
+        core::slice::from_raw_parts(
+            report as *const Report as *const u32,
+            size_of::<Report>() / 4,
+        )
+    };
+    !words.iter().fold(0u32, |sum, word| sum.wrapping_add(*word))
+}
+
+/// Takes the report left by the crash before this boot.
+///
+/// RAM content after a power-on is random, the checksum tells it apart.
+pub fn take() -> Option<Report> {
+    unsafe {
+        let slot = ptr::addr_of_mut!(SLOT) as *mut Slot;
+        let magic = ptr::read_volatile(ptr::addr_of!((*slot).magic));
+        if magic != MAGIC {
+            return None;
+        }
+        ptr::write_volatile(ptr::addr_of_mut!((*slot).magic), 0);
+        let stored = ptr::read_volatile(ptr::addr_of!((*slot).checksum));
+        let report = ptr::read_volatile(ptr::addr_of!((*slot).report));
+        (stored == checksum(&report) && report.kind().is_some()).then(|| report)
+    }
+}
+
+fn store_and_reset(report: &Report) -> ! {
+    unsafe {
+        let slot = ptr::addr_of_mut!(SLOT) as *mut Slot;
+        ptr::write_volatile(
+            slot,
+            Slot {
+                magic: MAGIC,
+                checksum: checksum(report),
+                report: *report,
+            },
+        );
+    }
+    SCB::sys_reset()
+}
+
+#[inline(never)]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    interrupt::disable();
+    Mx1508::emergency_brake();
+
+    let mut report = Report::new(Kind::Panic, register::pc::read(), register::lr::read());
+    // a too long message is cut, not dropped
+    write!(report, "{}", info).ok();
+    store_and_reset(&report)
+}
+
+#[exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+    interrupt::disable();
+    Mx1508::emergency_brake();
+
+    store_and_reset(&Report::new(Kind::HardFault, frame.pc(), frame.lr()))
+}
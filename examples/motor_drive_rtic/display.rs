@@ -0,0 +1,142 @@
+//! SSD1306 on the shared SPI1 showing `robo_core::dashboard`, and the
+//! battery voltage.
+//!
+//! The dashboard draws into a [`Frame`] of its own and hands it to [`Flush`],
+//! which sends it a page at a time by DMA, started by the sampler right after
+//! the sensor read. Drawing and flushing never wait for each other, a frame
+//! drawn while the previous one is still going out is dropped.
+
+use core::mem;
+
+use embedded_hal::blocking::delay::DelayUs;
+use stm32g4xx_hal as hal;
+
+use hal::adc::{config::SampleTime, Adc, Disabled};
+use hal::gpio::{gpioa, gpiob, Analog, Output, PushPull};
+use hal::prelude::*;
+use hal::stm32::ADC1;
+use robo_core::frame::{Frame, PAGE_COUNT};
+
+use crate::spi_bus::{self, Device};
+use crate::SYS_FREQ;
+
+/// Refresh rate, a frame takes 8 sampler periods at a page per period.
+pub const DASHBOARD_HZ: u32 = 5;
+
+pub type Cs = gpiob::PB12<Output<PushPull>>;
+pub type Dc = gpiob::PB14<Output<PushPull>>;
+
+/// 128x64 with the charge pump on and horizontal addressing, the same
+/// orientation as `DisplayRotation::Rotate0` of the ssd1306 crate.
+const INIT: [u8; 25] = [
+    0xae, // display off
+    0xd5, 0x80, // clock
+    0xa8, 0x3f, // 64 rows
+    0xd3, 0x00, // no offset
+    0x40, // start line 0
+    0x8d, 0x14, // charge pump on
+    0x20, 0x00, // horizontal addressing
+    0xa1, // column 127 at SEG0
+    0xc8, // rows scanned bottom up
+    0xda, 0x12, // alternate COM pins
+    0x81, 0xcf, // contrast
+    0xd9, 0xf1, // precharge
+    0xdb, 0x40, // VCOMH
+    0xa4, // show the RAM
+    0xa6, // not inverted
+    0xaf, // display on
+];
+
+/// The whole screen, the pages then follow each other.
+const WINDOW: [u8; 6] = [0x21, 0, 127, 0x22, 0, PAGE_COUNT as u8 - 1];
+
+pub struct Flush {
+    cs: Cs,
+    dc: Dc,
+    frame: &'static mut Frame,
+    /// Next page to send, `None` with nothing to send
+    next: Option<usize>,
+    in_flight: bool,
+}
+
+impl Flush {
+    /// Sets up the display, blocking, with `frame` as the first one sent.
+    pub fn new(mut cs: Cs, mut dc: Dc, frame: &'static mut Frame) -> Self {
+        dc.set_low().ok();
+        cs.set_low().ok();
+        spi_bus::write(Device::Display, &INIT);
+        cs.set_high().ok();
+        spi_bus::release();
+        Flush {
+            cs,
+            dc,
+            frame,
+            next: Some(0),
+            in_flight: false,
+        }
+    }
+
+    /// Swaps `frame` for the one last sent, unless that is still going out.
+    pub fn submit(&mut self, frame: &mut &'static mut Frame) -> bool {
+        if self.next.is_some() {
+            return false;
+        }
+        mem::swap(&mut self.frame, frame);
+        self.next = Some(0);
+        true
+    }
+
+    /// Starts the next page, called by the sampler once it is off the bus.
+    pub fn service(&mut self) {
+        let page = match self.next {
+            Some(page) if !self.in_flight => page,
+            _ => return,
+        };
+        self.cs.set_low().ok();
+        if page == 0 {
+            self.dc.set_low().ok();
+            spi_bus::write(Device::Display, &WINDOW);
+        }
+        self.dc.set_high().ok();
+        self.in_flight = true;
+        // the frame is only swapped once every page is out
+        unsafe { spi_bus::start_write(Device::Display, self.frame.page(page)) };
+    }
+
+    /// Called from the DMA interrupt once a page is out.
+    pub fn on_page_done(&mut self) {
+        self.cs.set_high().ok();
+        spi_bus::release();
+        self.in_flight = false;
+        self.next = self
+            .next
+            .map(|page| page + 1)
+            .filter(|page| *page < PAGE_COUNT);
+    }
+}
+
+/// Battery sense on PA0 through a 1:3 divider, up to 9.9 V. Read by the
+/// sampler into the battery channel.
+pub struct Battery {
+    pub adc: Adc<ADC1, Disabled>,
+    pub pin: gpioa::PA0<Analog>,
+}
+
+const BATTERY_DIVIDER: f32 = 3.0;
+
+impl Battery {
+    pub fn volts(&mut self) -> f32 {
+        // 6 us at 16 MHz, short enough to take every control period
+        let sample = self.adc.convert(&self.pin, SampleTime::Cycles_92_5);
+        self.adc.sample_to_millivolts(sample) as f32 * BATTERY_DIVIDER / 1000.0
+    }
+}
+
+/// Busy wait on the core clock, SysTick is taken by the monotonic.
+pub struct CycleDelay;
+
+impl DelayUs<u8> for CycleDelay {
+    fn delay_us(&mut self, us: u8) {
+        cortex_m::asm::delay(us as u32 * (SYS_FREQ / 1_000_000));
+    }
+}
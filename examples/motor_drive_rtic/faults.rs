@@ -0,0 +1,43 @@
+//! Latched faults, raised by any task.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use bitflags::bitflags;
+
+bitflags! {
+    pub struct Faults: u32 {
+        /// Stack margin below `mem::STACK_MARGIN_MIN`
+        const STACK_LOW = 1 << 0;
+        /// Angle sensor reads failing or its self test flagged
+        const SENSOR = 1 << 1;
+    }
+}
+
+pub const FAULT_COUNT: usize = 2;
+
+pub const FAULT_NAMES: [(Faults, &str); FAULT_COUNT] =
+    [(Faults::STACK_LOW, "stack_low"), (Faults::SENSOR, "sensor")];
+
+static ACTIVE: AtomicU32 = AtomicU32::new(0);
+
+/// Raises `faults`, true if any of them was not active yet.
+pub fn raise(faults: Faults) -> bool {
+    let previous = ACTIVE.fetch_or(faults.bits(), Ordering::Relaxed);
+    previous & faults.bits() != faults.bits()
+}
+
+pub fn active() -> Faults {
+    Faults::from_bits_truncate(ACTIVE.load(Ordering::Relaxed))
+}
+
+/// Names of `faults`, backed by `names`.
+pub fn names(faults: Faults, names: &mut [&'static str; FAULT_COUNT]) -> &[&'static str] {
+    let mut len = 0;
+    for (fault, name) in FAULT_NAMES.iter() {
+        if faults.contains(*fault) {
+            names[len] = name;
+            len += 1;
+        }
+    }
+    &names[..len]
+}
@@ -0,0 +1,104 @@
+use core::fmt::{self, Write};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Status {
+    Ok = 0,
+    InvalidArgument = 1,
+    UnknownCommand = 2,
+    SensorError = 3,
+    AccessDenied = 4,
+    StorageError = 5,
+}
+
+#[derive(Copy, Clone)]
+pub enum Value<'a> {
+    Bool(bool),
+    U32(u32),
+    I32(i32),
+    F32(f32),
+    Str(&'a str),
+    Fmt(fmt::Arguments<'a>),
+    List(&'a [&'a str]),
+    Floats(&'a [f32]),
+}
+
+/// Writes a single `{"cmd":..,"status":..,...}` line terminated by CRLF.
+pub fn write_line<W: Write>(
+    out: &mut W,
+    cmd: &str,
+    status: Status,
+    fields: &[(&str, Value)],
+) -> fmt::Result {
+    out.write_str("\r\n{\"cmd\":")?;
+    write_string(out, cmd)?;
+    write!(out, ",\"status\":{}", status as u8)?;
+    for (key, value) in fields {
+        out.write_char(',')?;
+        write_string(out, key)?;
+        out.write_char(':')?;
+        write_value(out, value)?;
+    }
+    out.write_str("}\r\n")
+}
+
+fn write_value<W: Write>(out: &mut W, value: &Value) -> fmt::Result {
+    match value {
+        Value::Bool(value) => write!(out, "{}", value),
+        Value::U32(value) => write!(out, "{}", value),
+        Value::I32(value) => write!(out, "{}", value),
+        // JSON has no representation for NaN or infinity
+        Value::F32(value) if !value.is_finite() => out.write_str("null"),
+        Value::F32(value) => write!(out, "{}", value),
+        Value::Str(value) => write_string(out, value),
+        Value::Fmt(args) => {
+            out.write_char('"')?;
+            Escaper(out).write_fmt(*args)?;
+            out.write_char('"')
+        }
+        Value::Floats(items) => {
+            out.write_char('[')?;
+            for (n, item) in items.iter().enumerate() {
+                if n != 0 {
+                    out.write_char(',')?;
+                }
+                write_value(out, &Value::F32(*item))?;
+            }
+            out.write_char(']')
+        }
+        Value::List(items) => {
+            out.write_char('[')?;
+            for (n, item) in items.iter().enumerate() {
+                if n != 0 {
+                    out.write_char(',')?;
+                }
+                write_string(out, item)?;
+            }
+            out.write_char(']')
+        }
+    }
+}
+
+fn write_string<W: Write>(out: &mut W, value: &str) -> fmt::Result {
+    out.write_char('"')?;
+    Escaper(out).write_str(value)?;
+    out.write_char('"')
+}
+
+struct Escaper<'a, W: Write>(&'a mut W);
+
+impl<W: Write> Write for Escaper<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match c {
+                '"' => self.0.write_str("\\\"")?,
+                '\\' => self.0.write_str("\\\\")?,
+                '\r' => self.0.write_str("\\r")?,
+                '\n' => self.0.write_str("\\n")?,
+                '\t' => self.0.write_str("\\t")?,
+                c if (c as u32) < 0x20 => write!(self.0, "\\u{:04x}", c as u32)?,
+                c => self.0.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+//! Logging facade over defmt with a runtime level per module.
+//!
+//! `error!`, `warn!`, `info!`, `debug!` and `trace!` take the module first,
+//! like `warn!(Params, "stored record invalid")`. Messages below the level
+//! of their module are skipped before defmt encodes them, `DEFMT_LOG` in
+//! `.cargo/config` keeps every level compiled in.
+//!
+//! Warnings and errors can be mirrored to the shell. Their format string and
+//! arguments then have to work with `core::fmt` as well, so stick to `{}` and
+//! `{:?}` in them.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use cortex_m::interrupt;
+
+use crate::uart::Ring;
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub enum Level {
+    Off = 0,
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+pub const LEVELS: [Level; 6] = [
+    Level::Off,
+    Level::Error,
+    Level::Warn,
+    Level::Info,
+    Level::Debug,
+    Level::Trace,
+];
+
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Off => "off",
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Level> {
+        LEVELS.iter().copied().find(|level| level.name() == name)
+    }
+
+    fn from_u8(value: u8) -> Level {
+        LEVELS.get(value as usize).copied().unwrap_or(Level::Off)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Module {
+    App = 0,
+    Uart = 1,
+    Shell = 2,
+    Telemetry = 3,
+    Params = 4,
+    Motor = 5,
+}
+
+pub const MODULE_COUNT: usize = 6;
+
+pub const MODULES: [Module; MODULE_COUNT] = [
+    Module::App,
+    Module::Uart,
+    Module::Shell,
+    Module::Telemetry,
+    Module::Params,
+    Module::Motor,
+];
+
+impl Module {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Module::App => "app",
+            Module::Uart => "uart",
+            Module::Shell => "shell",
+            Module::Telemetry => "telemetry",
+            Module::Params => "params",
+            Module::Motor => "motor",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Module> {
+        MODULES.iter().copied().find(|module| module.name() == name)
+    }
+
+    fn from_u8(value: u8) -> Module {
+        MODULES.get(value as usize).copied().unwrap_or(Module::App)
+    }
+}
+
+const DEFAULT_LEVEL: Level = Level::Info;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const DEFAULT: AtomicU8 = AtomicU8::new(DEFAULT_LEVEL as u8);
+static MODULE_LEVELS: [AtomicU8; MODULE_COUNT] = [DEFAULT; MODULE_COUNT];
+
+static MIRROR: AtomicBool = AtomicBool::new(false);
+static MIRRORED: Ring<512> = Ring::new();
+
+/// Longest mirrored message with its level and module bytes and a newline.
+pub const LINE_MAX: usize = 96;
+
+pub fn level(module: Module) -> Level {
+    Level::from_u8(MODULE_LEVELS[module as usize].load(Ordering::Relaxed))
+}
+
+pub fn set_level(module: Module, level: Level) {
+    MODULE_LEVELS[module as usize].store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(module: Module, level: Level) -> bool {
+    level != Level::Off && level <= self::level(module)
+}
+
+pub fn mirror_enabled() -> bool {
+    MIRROR.load(Ordering::Relaxed)
+}
+
+pub fn set_mirror(enabled: bool) {
+    MIRROR.store(enabled, Ordering::Relaxed);
+}
+
+/// Queues an enabled warning or error for the shell, dropped when full.
+pub fn mirror(module: Module, level: Level, args: fmt::Arguments) {
+    if !mirror_enabled() || !enabled(module, level) {
+        return;
+    }
+
+    let mut line = Line {
+        buffer: [0; LINE_MAX],
+        len: 2,
+    };
+    line.buffer[0] = level as u8;
+    line.buffer[1] = module as u8;
+    // a too long message is cut, not dropped
+    line.write_fmt(args).ok();
+    let len = line.len;
+    line.buffer[len] = b'\n';
+
+    // producers run at any priority
+    interrupt::free(|_| {
+        if MIRRORED.capacity() - MIRRORED.len() > len {
+            for byte in &line.buffer[..=len] {
+                MIRRORED.push(*byte);
+            }
+        }
+    });
+}
+
+/// Takes the oldest mirrored message, the text goes into `text`.
+pub fn take_mirrored(text: &mut [u8; LINE_MAX]) -> Option<(Level, Module, &str)> {
+    let level = Level::from_u8(MIRRORED.pop()?);
+    let module = Module::from_u8(MIRRORED.pop()?);
+    let mut len = 0;
+    while let Some(byte) = MIRRORED.pop() {
+        if byte == b'\n' {
+            break;
+        }
+        text[len] = byte;
+        len += 1;
+    }
+    // cut mid character at worst
+    let text = match core::str::from_utf8(&text[..len]) {
+        Ok(text) => text,
+        Err(error) => core::str::from_utf8(&text[..error.valid_up_to()]).unwrap_or(""),
+    };
+    Some((level, module, text))
+}
+
+struct Line {
+    buffer: [u8; LINE_MAX],
+    len: usize,
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if self.len == LINE_MAX - 1 {
+                return Err(fmt::Error);
+            }
+            // one message per line
+            self.buffer[self.len] = if byte == b'\n' || byte == b'\r' {
+                b' '
+            } else {
+                byte
+            };
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+macro_rules! log {
+    ($level:ident, $defmt:ident, $module:ident, $($arg:tt)+) => {
+        if $crate::log::enabled($crate::log::Module::$module, $crate::log::Level::$level) {
+            defmt::$defmt!($($arg)+);
+        }
+    };
+}
+
+macro_rules! error {
+    ($module:ident, $($arg:tt)+) => {{
+        log!(Error, error, $module, $($arg)+);
+        $crate::log::mirror(
+            $crate::log::Module::$module,
+            $crate::log::Level::Error,
+            format_args!($($arg)+),
+        );
+    }};
+}
+
+macro_rules! warn {
+    ($module:ident, $($arg:tt)+) => {{
+        log!(Warn, warn, $module, $($arg)+);
+        $crate::log::mirror(
+            $crate::log::Module::$module,
+            $crate::log::Level::Warn,
+            format_args!($($arg)+),
+        );
+    }};
+}
+
+macro_rules! info {
+    ($module:ident, $($arg:tt)+) => {
+        log!(Info, info, $module, $($arg)+)
+    };
+}
+
+#[allow(unused_macros)]
+macro_rules! debug {
+    ($module:ident, $($arg:tt)+) => {
+        log!(Debug, debug, $module, $($arg)+)
+    };
+}
+
+#[allow(unused_macros)]
+macro_rules! trace {
+    ($module:ident, $($arg:tt)+) => {
+        log!(Trace, trace, $module, $($arg)+)
+    };
+}
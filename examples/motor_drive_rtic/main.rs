@@ -1,29 +1,67 @@
 #![no_std]
 #![no_main]
 
+#[macro_use]
+mod log;
+
+mod angle_sensor;
+mod capture;
+mod crash;
+mod display;
+mod faults;
+mod json;
+mod mem;
+mod panel;
+mod params;
+mod profile;
+#[cfg(feature = "rtt-shell")]
+mod rtt;
 mod shell;
+mod spi_bus;
+mod step;
+mod telemetry;
+mod uart;
+
+#[cfg(all(feature = "rtt-shell", feature = "defmt-rtt"))]
+compile_error!("`rtt-shell` brings its own defmt logger, build it with `--no-default-features`");
 
-use panic_halt as _;
 use rtic;
 use stm32g4xx_hal as hal;
 
-use defmt::info;
+#[cfg(feature = "defmt-rtt")]
 use defmt_rtt as _;
 
+use hal::adc::{AdcClaim, ClockSource};
 use hal::gpio::*;
 use hal::prelude::*;
 use hal::pwm::*;
 use hal::serial::{Event::Rxne, FullConfig};
-use hal::spi::Spi;
-use hal::stm32::{SPI1, TIM2};
+use hal::stm32::TIM2;
+use hal::syscfg::SysCfgExt;
 
-use dwt_systick_monotonic::DwtSystick;
+use dwt_systick_monotonic::{DwtSystick, ExtU32};
 
 use core::fmt::Write;
 
+use angle_sensor::Tle5012Sensor;
+use capture::Scope;
+use display::{Battery, CycleDelay, Flush, DASHBOARD_HZ};
+use panel::Panel;
+use params::ParamStore;
+use profile::Task;
 use shell::*;
-
-use tle5012::{self, Tle5012, MODE};
+use step::StepTest;
+use telemetry::Telemetry;
+
+use robo_core::calibrate::Calibration;
+use robo_core::characterize::{Sweep, SweepStep};
+use robo_core::dashboard::{self as screen, Status};
+use robo_core::frame::Frame;
+use robo_core::menu::Screen;
+use robo_core::plot::Plot;
+use robo_core::sensor::{self, RotarySensor};
+use robo_core::telemetry::{Channel, Snapshot};
+use robo_core::velocity::{Estimator, Method};
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum MotorState {
@@ -34,6 +72,25 @@ pub enum MotorState {
     Ccw(u32),
 }
 
+impl MotorState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MotorState::HardBrake => "hard_brake",
+            MotorState::Brake(_) => "brake",
+            MotorState::Release => "release",
+            MotorState::Cw(_) => "cw",
+            MotorState::Ccw(_) => "ccw",
+        }
+    }
+
+    pub fn duty(&self) -> Option<u32> {
+        match *self {
+            MotorState::Brake(duty) | MotorState::Cw(duty) | MotorState::Ccw(duty) => Some(duty),
+            _ => None,
+        }
+    }
+}
+
 pub struct Mx1508 {
     pwm1: Pwm<TIM2, C2, ComplementaryImpossible, ActiveHigh, ActiveHigh>,
     pwm2: Pwm<TIM2, C3, ComplementaryImpossible, ActiveHigh, ActiveHigh>,
@@ -71,6 +128,55 @@ impl Mx1508 {
         self.motor_state = MotorState::Ccw(duty);
     }
 
+    /// Signed duty fraction, clockwise is positive and zero releases.
+    pub fn drive(&mut self, fraction: f32) {
+        let magnitude = if fraction < 0.0 { -fraction } else { fraction };
+        let duty = (magnitude.min(1.0) * self.get_max_duty() as f32) as u32;
+        if duty == 0 {
+            self.release();
+        } else if fraction > 0.0 {
+            self.cw(duty);
+        } else {
+            self.ccw(duty);
+        }
+    }
+
+    pub fn set_state(&mut self, state: MotorState) {
+        match state {
+            MotorState::HardBrake => self.hard_brake(),
+            MotorState::Brake(duty) => self.brake(duty),
+            MotorState::Release => self.release(),
+            MotorState::Cw(duty) => self.cw(duty),
+            MotorState::Ccw(duty) => self.ccw(duty),
+        }
+    }
+
+    /// Hard brakes from a fault handler, where the driver is out of reach.
+    pub fn emergency_brake() {
+        // CCR above ARR keeps the output active for the whole period
+        let tim = unsafe { &*hal::stm32::TIM2::ptr() };
+        let high = tim.arr.read().bits().saturating_add(1);
+        tim.ccr2.write(|w| unsafe { w.bits(high) });
+        tim.ccr3.write(|w| unsafe { w.bits(high) });
+    }
+
+    /// Changes the PWM frequency, keeping the duty fraction of the state.
+    pub fn set_frequency(&mut self, hz: u32) {
+        let previous = self.get_max_duty();
+        let tim = unsafe { &*hal::stm32::TIM2::ptr() };
+        // 32-bit counter, no prescaler needed down to 1 Hz
+        tim.psc.write(|w| unsafe { w.bits(0) });
+        tim.arr.write(|w| unsafe { w.bits(SYS_FREQ / hz - 1) });
+        let max = self.get_max_duty();
+        let scale = |duty: u32| (duty as u64 * max as u64 / previous as u64) as u32;
+        self.set_state(match self.motor_state {
+            MotorState::Brake(duty) => MotorState::Brake(scale(duty)),
+            MotorState::Cw(duty) => MotorState::Cw(scale(duty)),
+            MotorState::Ccw(duty) => MotorState::Ccw(scale(duty)),
+            state => state,
+        });
+    }
+
     pub fn get_max_duty(&self) -> u32 {
         self.pwm1.get_max_duty()
     }
@@ -80,51 +186,111 @@ impl Mx1508 {
     }
 }
 
-type AngleSensor = Tle5012<
-    Spi<
-        SPI1,
-        (
-            gpioa::PA5<Alternate<5>>,
-            gpioa::PA6<Alternate<5>>,
-            gpioa::PA7<Alternate<5>>,
-        ),
-    >,
-    gpioa::PA9<Output<PushPull>>,
->;
-
-#[rtic::app(device = hal::stm32, peripherals = true, dispatchers = [USART1])]
+type AngleSensor = Tle5012Sensor;
+
+// Default system clocked by HSI (16 MHz)
+pub const SYS_FREQ: u32 = 16_000_000;
+
+/// Motor PWM frequency at reset
+const PWM_HZ: u32 = 500;
+
+/// Rate of the sampler task feeding telemetry
+pub const CONTROL_HZ: u32 = 1_000;
+
+/// Time per duty step of `characterize`, 42 steps take 21 s
+const SWEEP_SETTLE_MS: u32 = 300;
+const SWEEP_MEASURE_MS: u32 = 200;
+
+/// Bandwidth of the speed estimate at reset
+const VELOCITY_BANDWIDTH_HZ: f32 = 20.0;
+
+/// Spin up or down time of `calibrate`, and the time a turn may take
+const CALIBRATE_SETTLE_MS: u32 = 500;
+const CALIBRATE_TURN_MS: u32 = 20_000;
+
+#[rtic::app(device = hal::stm32, peripherals = true, dispatchers = [USART1, UART4, UART5])]
 mod app {
     use super::*;
 
-    // Default system clocked by HSI (16 MHz)
-    const SYS_FREQ: u32 = 16_000_000;
     #[monotonic(binds = SysTick, default = true)]
     type Mono = DwtSystick<SYS_FREQ>;
+    type Instant = <Mono as rtic::Monotonic>::Instant;
 
     #[shared]
     struct Shared {
         motor: Mx1508,
         angle_sensor: AngleSensor,
+        params: ParamStore,
+        telemetry: Telemetry,
+        scope: Scope,
+        step: StepTest,
+        sweep: Sweep,
+        calibration: Calibration,
+        /// Speed channel source, picked by `velocity`
+        velocity: Estimator,
+        /// Latest sampler reading, for the dashboard
+        snapshot: Snapshot,
+        panel: Panel,
+        /// Fed by the sampler, drawn instead of the dashboard when enabled
+        plot: Plot,
+        /// Display pages started by the sampler, finished by `spi_dma`
+        flush: Flush,
+        #[lock_free]
+        sessions: Sessions,
     }
 
     #[local]
     struct Local {
-        shell: Shell,
+        serial: uart::Usart2,
+        shells: Shells,
+        frame: &'static mut Frame,
+        battery: Battery,
     }
 
-    #[init]
+    #[init(local = [front: Frame = Frame::new(), back: Frame = Frame::new()])]
     fn init(mut ctx: init::Context) -> (Shared, Local, init::Monotonics) {
-        info!("Init system");
+        mem::paint();
+        // taken and stored first, a panic further down would overwrite it
+        let mut params = ParamStore::new(ctx.device.FLASH);
+        let crashed = crash::take();
+        if let Some(report) = crashed {
+            // a crash loop must not wear out the page
+            if params.get().crash != report {
+                params.update(|params| params.crash = report).ok();
+            }
+        }
 
+        #[cfg(feature = "rtt-shell")]
+        let (rtt_serial, rtt_telemetry) = rtt::init();
+
+        // logged once RTT is up, `rtt-shell` builds log over its up channel 0
+        if let Some(report) = crashed {
+            let kind = report.kind().map_or("", |kind| kind.name());
+            error!(
+                App,
+                "rebooted after {} at pc={:#x} lr={:#x} cfsr={:#x}: {}",
+                kind,
+                report.pc,
+                report.lr,
+                report.cfsr,
+                report.message()
+            );
+        }
+
+        info!(App, "Init system");
+
+        // syscfg
+        let mut syscfg = ctx.device.SYSCFG.constrain();
         // clocks
         let mut rcc = ctx.device.RCC.constrain();
         // monotonic timer
         let mono = DwtSystick::new(&mut ctx.core.DCB, ctx.core.DWT, ctx.core.SYST, SYS_FREQ);
 
-        info!("Init UART");
+        info!(App, "Init UART");
 
         let gpio_a = ctx.device.GPIOA.split(&mut rcc);
         let gpio_b = ctx.device.GPIOB.split(&mut rcc);
+        let gpio_c = ctx.device.GPIOC.split(&mut rcc);
 
         // serial
         let tx = gpio_a.pa2.into_alternate();
@@ -136,16 +302,31 @@ mod app {
             .usart(tx, rx, FullConfig::default(), &mut rcc)
             .unwrap();
         serial.listen(Rxne);
+        uart::init_dma(&ctx.device.DMA1, &ctx.device.DMAMUX);
 
         // shell
-        let mut shell = UShell::new(serial, AUTOCOMPLETE, LRUHistory::default());
+        let mut shell = UShell::new(uart::BufferedSerial, AUTOCOMPLETE, History::default());
 
         writeln!(shell, "\r\nSystem shell at USART2\r\n").unwrap();
 
+        #[cfg(feature = "rtt-shell")]
+        let rtt_shell = {
+            let mut shell = UShell::new(rtt_serial, AUTOCOMPLETE, History::default());
+            writeln!(shell, "\r\nSystem shell at RTT\r\n").unwrap();
+            rtt_poll::spawn().ok();
+            shell
+        };
+
+        let shells = Shells {
+            uart: shell,
+            #[cfg(feature = "rtt-shell")]
+            rtt: rtt_shell,
+        };
+
         // motor
         let (mut pwm1, mut pwm2) = ctx.device.TIM2.pwm(
             (gpio_b.pb3.into_alternate(), gpio_b.pb10.into_alternate()),
-            500.hz(),
+            PWM_HZ.hz(),
             &mut rcc,
         );
         pwm1.set_duty(pwm1.get_max_duty());
@@ -162,49 +343,324 @@ mod app {
             motor_state,
         };
 
-        // Setup spi i/o
-        let sck = gpio_a.pa5.into_alternate();
-        let miso = gpio_a.pa6.into_alternate();
-        let mosi = gpio_a.pa7.into_alternate();
+        // SPI1, shared by the sensor and the display
+        let pins = (
+            gpio_a.pa5.into_alternate(),
+            gpio_a.pa6.into_alternate(),
+            gpio_a.pa7.into_alternate(),
+        );
+        let spi = spi_bus::init(ctx.device.SPI1, pins, &ctx.device.DMA1, &ctx.device.DMAMUX);
+
         let mut nss = gpio_a.pa9.into_push_pull_output();
         nss.set_high().ok();
+        let mut angle_sensor = Tle5012Sensor::new(spi, nss);
+
+        let mut cs = gpio_b.pb12.into_push_pull_output();
+        cs.set_high().ok();
+        let dc = gpio_b.pb14.into_push_pull_output();
+        let flush = Flush::new(cs, dc, ctx.local.front);
+
+        // front panel button, both edges
+        let mut exti = ctx.device.EXTI;
+        let mut button = gpio_c.pc13.into_pull_down_input();
+        button.make_interrupt_source(&mut syscfg);
+        button.trigger_on_edge(&mut exti, SignalEdge::RisingFalling);
+        button.enable_interrupt(&mut exti);
+
+        let battery = Battery {
+            adc: ctx
+                .device
+                .ADC1
+                .claim(ClockSource::SystemClock, &rcc, &mut CycleDelay, true),
+            pin: gpio_a.pa0.into_analog(),
+        };
 
-        let spi = ctx
-            .device
-            .SPI1
-            .spi((sck, miso, mosi), MODE, 500.khz(), &mut rcc);
+        angle_sensor.set_correction(params.get().angle);
 
-        let angle_sensor = Tle5012::new(spi, nss).unwrap();
+        let telemetry = Telemetry::new(
+            #[cfg(feature = "rtt-shell")]
+            rtt_telemetry,
+        );
+
+        session_tick::spawn().ok();
+        sampler::spawn(None).ok();
+        dashboard::spawn(None).ok();
 
         (
             Shared {
                 // Initialization of shared resources go here
                 motor,
                 angle_sensor,
+                params,
+                telemetry,
+                scope: Scope::new(),
+                snapshot: Snapshot::default(),
+                panel: Panel::new(button, PWM_HZ),
+                plot: Plot::new(CONTROL_HZ),
+                flush,
+                step: StepTest::new(),
+                sweep: Sweep::new(
+                    SWEEP_SETTLE_MS * CONTROL_HZ / 1000,
+                    SWEEP_MEASURE_MS * CONTROL_HZ / 1000,
+                ),
+                calibration: Calibration::new(
+                    CALIBRATE_SETTLE_MS * CONTROL_HZ / 1000,
+                    CALIBRATE_TURN_MS * CONTROL_HZ / 1000,
+                ),
+                velocity: Estimator::new(Method::Sensor, CONTROL_HZ, VELOCITY_BANDWIDTH_HZ),
+                sessions: Sessions::new(),
             },
             Local {
                 // Initialization of local resources go here
-                shell,
+                serial,
+                shells,
+                frame: ctx.local.back,
+                battery,
             },
             init::Monotonics(mono),
         )
     }
 
-    #[task(binds = USART2, priority = 1)]
-    fn serial_callback(_: serial_callback::Context) {
-        env::spawn(EnvSignal::Shell).ok();
+    #[task(binds = USART2, priority = 3, local = [serial])]
+    fn serial_callback(ctx: serial_callback::Context) {
+        let _span = profile::span(Task::SerialRx);
+        uart::on_rx_interrupt(ctx.local.serial);
+        signal(EnvSignal::Shell);
+    }
+
+    #[task(binds = EXTI15_10, priority = 2, shared = [panel])]
+    fn button_edge(mut ctx: button_edge::Context) {
+        let _span = profile::span(Task::Panel);
+        ctx.shared.panel.lock(update_panel);
+    }
+
+    #[task(priority = 2, capacity = 4, shared = [panel])]
+    fn button_poll(mut ctx: button_poll::Context) {
+        let _span = profile::span(Task::Panel);
+        ctx.shared.panel.lock(update_panel);
+    }
+
+    /// Feeds the button, hands actions to `env` and polls at the deadline.
+    fn update_panel(panel: &mut Panel) {
+        let now = monotonics::now();
+        let now_ms = now.duration_since_epoch().to_millis() as u32;
+        if let Some(action) = panel.update(now_ms) {
+            signal(EnvSignal::Panel(action));
+        }
+        if let Some(delay) = panel.poll_deadline(now_ms) {
+            button_poll::spawn_at(now + delay.millis()).ok();
+        }
+    }
+
+    #[task(binds = DMA1_CH1, priority = 4)]
+    fn uart_dma(_: uart_dma::Context) {
+        let _span = profile::span(Task::UartDma);
+        uart::on_dma_interrupt();
+    }
+
+    #[task(binds = DMA1_CH2, priority = 4, shared = [flush])]
+    fn spi_dma(mut ctx: spi_dma::Context) {
+        let _span = profile::span(Task::SpiDma);
+        if spi_bus::on_dma_interrupt() {
+            ctx.shared.flush.lock(|flush| flush.on_page_done());
+        }
+    }
+
+    // RTT has no receive interrupt, so the down channel is polled
+    #[cfg(feature = "rtt-shell")]
+    #[task(priority = 2)]
+    fn rtt_poll(_: rtt_poll::Context) {
+        let _span = profile::span(Task::RttPoll);
+        signal(EnvSignal::RttShell);
+        rtt_poll::spawn_after(10.millis()).ok();
     }
 
-    #[task(priority = 2, capacity = 8, local = [shell], shared = [motor, angle_sensor])]
+    #[task(
+        priority = 3,
+        local = [battery, timestamp_us: u32 = 0],
+        shared = [
+            motor,
+            angle_sensor,
+            flush,
+            telemetry,
+            scope,
+            step,
+            sweep,
+            calibration,
+            velocity,
+            snapshot,
+            plot
+        ]
+    )]
+    fn sampler(ctx: sampler::Context, scheduled: Option<Instant>) {
+        let _span = profile::span(Task::Sampler);
+        if let Some(scheduled) = scheduled {
+            // monotonic ticks are core cycles
+            profile::sampler_latency((monotonics::now() - scheduled).ticks() as u32);
+        }
+        let sampler::SharedResources {
+            mut motor,
+            mut angle_sensor,
+            mut flush,
+            telemetry,
+            scope,
+            mut step,
+            mut sweep,
+            mut calibration,
+            mut velocity,
+            snapshot: mut latest,
+            mut plot,
+        } = ctx.shared;
+
+        let reading = angle_sensor.lock(|angle_sensor| angle_sensor.read());
+        let velocity = velocity.lock(|velocity| velocity.update(reading));
+        // a display page goes out while the rest of the period runs
+        flush.lock(|flush| flush.service());
+        let (state, max_duty) = motor.lock(|motor| (motor.get_state(), motor.get_max_duty()));
+        let duty = telemetry::duty_fraction(state, max_duty);
+
+        let mut snapshot = Snapshot {
+            timestamp_us: *ctx.local.timestamp_us,
+            ..Snapshot::default()
+        };
+        // telemetry keeps to degrees
+        let (angle, speed) = reading.map_or((f32::NAN, f32::NAN), |reading| {
+            (sensor::degrees(reading.angle), sensor::degrees(velocity))
+        });
+        snapshot.set(Channel::Angle, angle);
+        snapshot.set(Channel::Speed, speed);
+        snapshot.set(Channel::Duty, duty);
+        snapshot.set(Channel::Current, f32::NAN);
+        snapshot.set(Channel::Setpoint, duty);
+        snapshot.set(Channel::Battery, ctx.local.battery.volts());
+
+        latest.lock(|latest| *latest = snapshot);
+        plot.lock(|plot| plot.sample(&snapshot));
+        (telemetry, scope).lock(|telemetry, scope| {
+            telemetry.sample(&snapshot);
+            scope.sample(&snapshot, telemetry);
+        });
+
+        // TLE5012 speed is in degrees per second
+        let rpm = snapshot.get(Channel::Speed) / 6.0;
+        if step.lock(|step| step.recorder.push(rpm)) {
+            signal(EnvSignal::StepDone);
+        }
+        match sweep.lock(|sweep| sweep.tick(rpm)) {
+            SweepStep::Hold => {}
+            SweepStep::Duty(duty) => motor.lock(|motor| motor.drive(duty)),
+            SweepStep::Done => {
+                motor.lock(|motor| motor.hard_brake());
+                signal(EnvSignal::SweepDone);
+            }
+        }
+        // uncorrected while running, `calibrate` clears the correction
+        let angle = reading.map_or(f32::NAN, |reading| reading.angle);
+        match calibration.lock(|calibration| calibration.tick(angle)) {
+            SweepStep::Hold => {}
+            SweepStep::Duty(duty) => motor.lock(|motor| motor.drive(duty)),
+            SweepStep::Done => {
+                motor.lock(|motor| motor.hard_brake());
+                signal(EnvSignal::CalibrationDone);
+            }
+        }
+
+        // spawn_at keeps the period free of drift
+        let next = scheduled.unwrap_or_else(monotonics::now) + (1_000_000 / CONTROL_HZ).micros();
+        *ctx.local.timestamp_us = ctx.local.timestamp_us.wrapping_add(1_000_000 / CONTROL_HZ);
+        sampler::spawn_at(next, Some(next)).ok();
+    }
+
+    #[task(
+        priority = 1,
+        local = [frame],
+        shared = [motor, snapshot, panel, plot, flush]
+    )]
+    fn dashboard(mut ctx: dashboard::Context, scheduled: Option<Instant>) {
+        let _span = profile::span(Task::Dashboard);
+        let (state, max_duty) = ctx
+            .shared
+            .motor
+            .lock(|motor| (motor.get_state(), motor.get_max_duty()));
+        let snapshot = ctx.shared.snapshot.lock(|snapshot| *snapshot);
+        let mut names = [""; faults::FAULT_COUNT];
+
+        let status = Status {
+            state: state.name(),
+            duty: telemetry::duty_fraction(state, max_duty),
+            speed: snapshot.get(Channel::Speed) / 6.0,
+            angle: snapshot.get(Channel::Angle),
+            battery: snapshot.get(Channel::Battery),
+            faults: faults::names(faults::active(), &mut names),
+        };
+        let (menu, settings) = ctx
+            .shared
+            .panel
+            .lock(|panel| (panel.menu.screen(), panel.settings));
+        let frame = ctx.local.frame;
+        // copied out, drawing under the lock would hold up the sampler
+        let plot = ctx
+            .shared
+            .plot
+            .lock(|plot| plot.is_enabled().then(|| plot.clone()));
+        match (menu, plot) {
+            (Screen::Status, Some(plot)) => plot.draw(*frame).ok(),
+            (Screen::Status, None) => screen::draw(*frame, &status).ok(),
+            (menu, _) => screen::draw_menu(*frame, menu, &settings).ok(),
+        };
+        // sent by the sampler, a page per period
+        ctx.shared.flush.lock(|flush| flush.submit(frame));
+
+        let next = scheduled.unwrap_or_else(monotonics::now) + (1_000 / DASHBOARD_HZ).millis();
+        dashboard::spawn_at(next, Some(next)).ok();
+    }
+
+    #[task(priority = 2, shared = [angle_sensor])]
+    fn session_tick(mut ctx: session_tick::Context) {
+        let _span = profile::span(Task::SessionTick);
+        profile::roll();
+        mem::check();
+        ctx.shared
+            .angle_sensor
+            .lock(|angle_sensor| angle_sensor.check());
+        signal(EnvSignal::Tick);
+        session_tick::spawn_after(1.secs()).ok();
+    }
+
+    #[task(
+        priority = 2,
+        // mem::ENV_CAPACITY
+        capacity = 8,
+        local = [shells],
+        shared = [
+            motor,
+            angle_sensor,
+            params,
+            telemetry,
+            scope,
+            step,
+            sweep,
+            calibration,
+            velocity,
+            panel,
+            plot,
+            sessions
+        ]
+    )]
     fn env(ctx: env::Context, sig: EnvSignal) {
+        let _span = profile::span(Task::Env);
+        mem::ENV_QUEUE.received();
         let mut env = ctx.shared;
-        env.on_signal(ctx.local.shell, sig).ok();
+        env.on_signal(ctx.local.shells, sig);
+    }
+
+    /// Spawns `env`, counting the queue occupancy for `mem`.
+    fn signal(sig: EnvSignal) {
+        mem::ENV_QUEUE.spawned(env::spawn(sig));
     }
 
     #[idle]
     fn idle(_: idle::Context) -> ! {
-        loop {
-            rtic::export::nop();
-        }
+        profile::idle()
     }
 }
@@ -0,0 +1,143 @@
+//! RAM usage: stack painting, static size and the `env` queue.
+//!
+//! `init` fills the free stack with a pattern, the deepest word no longer
+//! holding it is the high-water mark. The stack grows down from the top of
+//! RAM towards the statics, with nothing in between to catch an overflow.
+
+use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::register;
+
+use crate::faults::{self, Faults};
+
+/// Smallest stack margin in bytes before `STACK_LOW` is raised.
+pub const STACK_MARGIN_MIN: u32 = 1024;
+
+/// `capacity` of the `env` task, keep both in sync.
+pub const ENV_CAPACITY: u32 = 8;
+
+const PAINT: u32 = 0xcccc_cccc;
+// Left below the stack pointer while painting, for the painting itself
+const PAINT_MARGIN: usize = 64;
+
+extern "C" {
+    // provided by the cortex-m-rt linker script
+    static mut __sdata: u32;
+    static mut __sheap: u32;
+    static mut _stack_start: u32;
+}
+
+fn ram_start() -> usize {
+    unsafe { ptr::addr_of!(__sdata) as usize }
+}
+
+fn stack_bottom() -> usize {
+    unsafe { ptr::addr_of!(__sheap) as usize }
+}
+
+fn stack_top() -> usize {
+    unsafe { ptr::addr_of!(_stack_start) as usize }
+}
+
+/// Paints the unused stack, first thing in `init` with interrupts disabled.
+pub fn paint() {
+    let end = register::msp::read() as usize - PAINT_MARGIN;
+    let mut word = stack_bottom() as *mut u32;
+    while (word as usize) < end {
+        unsafe {
+            ptr::write_volatile(word, PAINT);
+            word = word.add(1);
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Usage {
+    /// Data, bss and uninit sections in bytes
+    pub statics: u32,
+    pub stack_size: u32,
+    /// Deepest stack use since boot in bytes
+    pub stack_peak: u32,
+}
+
+impl Usage {
+    pub fn margin(&self) -> u32 {
+        self.stack_size - self.stack_peak
+    }
+}
+
+/// Scans the painted stack, a few thousand words at worst.
+pub fn usage() -> Usage {
+    let bottom = stack_bottom();
+    let top = stack_top();
+    let mut word = bottom as *const u32;
+    while (word as usize) < top && unsafe { ptr::read_volatile(word) } == PAINT {
+        word = unsafe { word.add(1) };
+    }
+    Usage {
+        statics: (bottom - ram_start()) as u32,
+        stack_size: (top - bottom) as u32,
+        stack_peak: (top - word as usize) as u32,
+    }
+}
+
+/// Raises `STACK_LOW` once the margin gets too small, true when it is new.
+pub fn check() -> bool {
+    let usage = usage();
+    if usage.margin() < STACK_MARGIN_MIN && faults::raise(Faults::STACK_LOW) {
+        warn!(App, "stack margin down to {} bytes", usage.margin());
+        return true;
+    }
+    false
+}
+
+/// Occupancy of a spawn queue, counted by the spawner and the task.
+pub struct Queue {
+    pending: AtomicU32,
+    peak: AtomicU32,
+    full: AtomicU32,
+}
+
+impl Queue {
+    pub const fn new() -> Self {
+        Queue {
+            pending: AtomicU32::new(0),
+            peak: AtomicU32::new(0),
+            full: AtomicU32::new(0),
+        }
+    }
+
+    /// Counts the result of a spawn.
+    pub fn spawned<T>(&self, result: Result<(), T>) {
+        match result {
+            Ok(()) => {
+                let pending = self.pending.fetch_add(1, Ordering::Relaxed) + 1;
+                self.peak.fetch_max(pending, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.full.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Counts the start of the task.
+    pub fn received(&self) {
+        self.pending
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |pending| {
+                pending.checked_sub(1)
+            })
+            .ok();
+    }
+
+    /// Messages waiting, the most ever waiting and the ones lost to a full queue.
+    pub fn stats(&self) -> (u32, u32, u32) {
+        (
+            self.pending.load(Ordering::Relaxed),
+            self.peak.load(Ordering::Relaxed),
+            self.full.load(Ordering::Relaxed),
+        )
+    }
+}
+
+pub static ENV_QUEUE: Queue = Queue::new();
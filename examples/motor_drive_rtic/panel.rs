@@ -0,0 +1,51 @@
+//! Front panel: the PC13 user button driving the menu on the dashboard.
+//!
+//! The button is fed on every edge interrupt and again at its deadline,
+//! gestures go through the menu and the resulting actions to `env`.
+
+use stm32g4xx_hal as hal;
+
+use hal::gpio::{gpioc, ExtiPin, Input, PullDown};
+use hal::prelude::*;
+use robo_core::button::{Button, Timing};
+use robo_core::menu::{Action, Input as MenuInput, Menu, Settings};
+
+pub type ButtonPin = gpioc::PC13<Input<PullDown>>;
+
+pub struct Panel {
+    pin: ButtonPin,
+    button: Button,
+    pub menu: Menu,
+    pub settings: Settings,
+}
+
+impl Panel {
+    pub fn new(pin: ButtonPin, pwm_hz: u32) -> Self {
+        Panel {
+            pin,
+            button: Button::new(Timing::DEFAULT),
+            menu: Menu::new(),
+            settings: Settings {
+                setpoint_rpm: 0,
+                pwm_hz,
+                armed: false,
+            },
+        }
+    }
+
+    /// Samples the button, returns what a gesture asks for.
+    pub fn update(&mut self, now_ms: u32) -> Option<Action> {
+        // cleared before reading, an edge after that interrupts again
+        self.pin.clear_interrupt_pending_bit();
+        let pressed = self.pin.is_high().unwrap_or(false);
+        let gesture = self.button.update(pressed, now_ms)?;
+        debug!(App, "button {}", gesture.name());
+        let input = MenuInput::from_gesture(gesture)?;
+        self.menu.input(input, &self.settings)
+    }
+
+    /// Milliseconds to the next poll, `None` when none is needed.
+    pub fn poll_deadline(&mut self, now_ms: u32) -> Option<u32> {
+        self.button.poll_deadline(now_ms)
+    }
+}
@@ -0,0 +1,224 @@
+//! Parameter store kept in the `PARAMS` flash page reserved in `memory.x`.
+
+use core::mem::{size_of, MaybeUninit};
+use core::ptr;
+
+use robo_core::calibrate::AngleCorrection;
+use robo_core::characterize::Compensation;
+use stm32g4xx_hal as hal;
+
+use hal::stm32;
+
+use crate::crash;
+
+// Last 2K page of the 128K used by the firmware (bank 1, dual bank mode)
+const PAGE_ADDR: u32 = 0x0801_F800;
+const PAGE_NUMBER: u8 = 63;
+
+// Bumped whenever `Params` layout or defaults change, stale records fall
+// back to defaults
+const MAGIC: u32 = 0x5052_4d05;
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xcdef_89ab;
+
+/// `unlock_pin` until the `pin` command sets one, nothing unlocks then
+pub const NO_PIN: u32 = u32::MAX;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct Params {
+    pub unlock_pin: u32,
+    /// Written by `characterize`, used by `rpm`
+    pub compensation: Compensation,
+    /// Written by `calibrate`, applied to every angle read
+    pub angle: AngleCorrection,
+    /// Last crash, written on the boot after it
+    pub crash: crash::Report,
+}
+
+impl Params {
+    pub const DEFAULT: Params = Params {
+        unlock_pin: NO_PIN,
+        compensation: Compensation::NONE,
+        angle: AngleCorrection::NONE,
+        crash: crash::Report::NONE,
+    };
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, defmt::Format)]
+pub enum FlashError {
+    /// Raw `FLASH_SR` error flags
+    Program(u32),
+    Verify,
+}
+
+#[repr(C, align(8))]
+struct Record {
+    magic: u32,
+    checksum: u32,
+    params: Params,
+}
+
+impl Record {
+    fn new(params: Params) -> Self {
+        // zeroed so the tail padding is programmed deterministically
+        let mut record: Record = unsafe { MaybeUninit::zeroed().assume_init() };
+        record.magic = MAGIC;
+        record.checksum = checksum(&params);
+        record.params = params;
+        record
+    }
+
+    fn is_valid(&self) -> bool {
+        self.magic == MAGIC && self.checksum == checksum(&self.params)
+    }
+}
+
+fn checksum(params: &Params) -> u32 {
+    let words = unsafe {
+        core::slice::from_raw_parts(
+            params as *const Params as *const u32,
+            size_of::<Params>() / 4,
+        )
+    };
+    !words.iter().fold(0u32, |sum, word| sum.wrapping_add(*word))
+}
+
+pub struct ParamStore {
+    flash: stm32::FLASH,
+    params: Params,
+    /// No change made with `set` is waiting for `store`
+    saved: bool,
+}
+
+impl ParamStore {
+    pub fn new(flash: stm32::FLASH) -> Self {
+        let record = unsafe { ptr::read_volatile(PAGE_ADDR as *const Record) };
+        let params = if record.is_valid() {
+            record.params
+        } else {
+            warn!(Params, "no valid record, using defaults");
+            Params::DEFAULT
+        };
+        ParamStore {
+            flash,
+            params,
+            saved: true,
+        }
+    }
+
+    pub fn get(&self) -> &Params {
+        &self.params
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.saved
+    }
+
+    /// Applies `f` and writes the result to flash, the parameters in use
+    /// only change once that succeeded. Changes made with [`set`](Self::set)
+    /// are written along.
+    ///
+    /// The CPU stalls while the page is erased, so this is for shell commands only.
+    pub fn update<F: FnOnce(&mut Params)>(&mut self, f: F) -> Result<(), FlashError> {
+        let mut params = self.params;
+        f(&mut params);
+        self.save(&params)?;
+        self.params = params;
+        self.saved = true;
+        Ok(())
+    }
+
+    /// Applies `f` to the parameters in use only, [`store`](Self::store)
+    /// writes them.
+    pub fn set<F: FnOnce(&mut Params)>(&mut self, f: F) {
+        f(&mut self.params);
+        self.saved = false;
+    }
+
+    /// Writes the parameters in use to flash, a shell command only like
+    /// [`update`](Self::update).
+    pub fn store(&mut self) -> Result<(), FlashError> {
+        let params = self.params;
+        self.save(&params)?;
+        self.saved = true;
+        Ok(())
+    }
+
+    fn save(&mut self, params: &Params) -> Result<(), FlashError> {
+        let record = Record::new(*params);
+        let words = unsafe {
+            core::slice::from_raw_parts(
+                &record as *const Record as *const u32,
+                size_of::<Record>() / 4,
+            )
+        };
+
+        self.unlock();
+        let result = self.erase().and_then(|_| self.program(words));
+        self.flash.cr.modify(|_, w| w.lock().set_bit());
+        if let Err(error) = result {
+            error!(Params, "flash write failed: {:?}", error);
+            return Err(error);
+        }
+
+        let stored = unsafe { ptr::read_volatile(PAGE_ADDR as *const Record) };
+        if stored.is_valid() && stored.params == *params {
+            info!(Params, "stored");
+            Ok(())
+        } else {
+            error!(Params, "flash verify failed");
+            Err(FlashError::Verify)
+        }
+    }
+
+    fn unlock(&mut self) {
+        if self.flash.cr.read().lock().bit_is_set() {
+            self.flash.keyr.write(|w| unsafe { w.keyr().bits(KEY1) });
+            self.flash.keyr.write(|w| unsafe { w.keyr().bits(KEY2) });
+        }
+    }
+
+    fn erase(&mut self) -> Result<(), FlashError> {
+        self.flash
+            .cr
+            .modify(|_, w| unsafe { w.per().set_bit().pnb().bits(PAGE_NUMBER) });
+        self.flash.cr.modify(|_, w| w.strt().set_bit());
+        let result = self.wait();
+        self.flash.cr.modify(|_, w| w.per().clear_bit());
+        result
+    }
+
+    // `Record` is 8 byte aligned, so words always come in double word pairs
+    fn program(&mut self, words: &[u32]) -> Result<(), FlashError> {
+        self.flash.cr.modify(|_, w| w.pg().set_bit());
+        let mut result = Ok(());
+        for (n, pair) in words.chunks(2).enumerate() {
+            let addr = (PAGE_ADDR + n as u32 * 8) as *mut u32;
+            unsafe {
+                ptr::write_volatile(addr, pair[0]);
+                ptr::write_volatile(addr.add(1), pair[1]);
+            }
+            result = self.wait();
+            if result.is_err() {
+                break;
+            }
+        }
+        self.flash.cr.modify(|_, w| w.pg().clear_bit());
+        result
+    }
+
+    fn wait(&mut self) -> Result<(), FlashError> {
+        while self.flash.sr.read().bsy().bit_is_set() {}
+
+        // all error flags of FLASH_SR, EOP is bit 0
+        let errors = self.flash.sr.read().bits() & 0xc3fa;
+        self.flash.sr.write(|w| unsafe { w.bits(errors | 1) });
+        if errors == 0 {
+            Ok(())
+        } else {
+            Err(FlashError::Program(errors))
+        }
+    }
+}
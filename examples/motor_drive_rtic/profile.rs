@@ -0,0 +1,219 @@
+//! Task timing and CPU load from the DWT cycle counter.
+//!
+//! Every task holds a [`span`] while it runs. Spans nest like the tasks
+//! preempting each other, so a task is only charged the cycles it ran
+//! itself. The idle loop counts the cycles it was not interrupted for,
+//! [`roll`] closes the window once a second for the `top` command.
+
+use core::cell::Cell;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::interrupt::{self, Mutex};
+use cortex_m::peripheral::DWT;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Task {
+    Sampler = 0,
+    SerialRx = 1,
+    UartDma = 2,
+    Env = 3,
+    SessionTick = 4,
+    RttPoll = 5,
+    Dashboard = 6,
+    Panel = 7,
+    SpiDma = 8,
+}
+
+pub const TASK_COUNT: usize = 9;
+
+pub const TASKS: [Task; TASK_COUNT] = [
+    Task::Sampler,
+    Task::SerialRx,
+    Task::UartDma,
+    Task::Env,
+    Task::SessionTick,
+    Task::RttPoll,
+    Task::Dashboard,
+    Task::Panel,
+    Task::SpiDma,
+];
+
+impl Task {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Task::Sampler => "sampler",
+            Task::SerialRx => "serial_rx",
+            Task::UartDma => "uart_dma",
+            Task::Env => "env",
+            Task::SessionTick => "session_tick",
+            Task::RttPoll => "rtt_poll",
+            Task::Dashboard => "dashboard",
+            Task::Panel => "panel",
+            Task::SpiDma => "spi_dma",
+        }
+    }
+}
+
+/// Cycle counts of one task over a window.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Timing {
+    pub runs: u32,
+    pub total: u32,
+    /// `u32::MAX` without runs
+    pub min: u32,
+    pub max: u32,
+}
+
+impl Timing {
+    const NONE: Timing = Timing {
+        runs: 0,
+        total: 0,
+        min: u32::MAX,
+        max: 0,
+    };
+
+    pub fn avg(&self) -> Option<u32> {
+        (self.runs > 0).then(|| self.total / self.runs)
+    }
+}
+
+/// CPU use over the last closed window.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Load {
+    pub window: u32,
+    pub idle: u32,
+    pub tasks: [Timing; TASK_COUNT],
+    /// Delay from the scheduled to the actual start of the sampler, the
+    /// other timer scheduled tasks are not measured
+    pub sampler_latency: Timing,
+}
+
+impl Load {
+    pub fn idle_percent(&self) -> f32 {
+        match self.window {
+            0 => 0.0,
+            window => self.idle as f32 * 100.0 / window as f32,
+        }
+    }
+}
+
+struct Stats {
+    runs: AtomicU32,
+    total: AtomicU32,
+    min: AtomicU32,
+    max: AtomicU32,
+}
+
+impl Stats {
+    const fn new() -> Self {
+        Stats {
+            runs: AtomicU32::new(0),
+            total: AtomicU32::new(0),
+            min: AtomicU32::new(u32::MAX),
+            max: AtomicU32::new(0),
+        }
+    }
+
+    fn record(&self, cycles: u32) {
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(cycles, Ordering::Relaxed);
+        self.min.fetch_min(cycles, Ordering::Relaxed);
+        self.max.fetch_max(cycles, Ordering::Relaxed);
+    }
+
+    // called with interrupts disabled
+    fn take(&self) -> Timing {
+        Timing {
+            runs: self.runs.swap(0, Ordering::Relaxed),
+            total: self.total.swap(0, Ordering::Relaxed),
+            min: self.min.swap(u32::MAX, Ordering::Relaxed),
+            max: self.max.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const STATS: Stats = Stats::new();
+static TASK_STATS: [Stats; TASK_COUNT] = [STATS; TASK_COUNT];
+static SAMPLER_LATENCY: Stats = Stats::new();
+
+static IDLE: AtomicU32 = AtomicU32::new(0);
+static WINDOW_START: AtomicU32 = AtomicU32::new(0);
+// cycles charged to finished spans, nested ones included
+static CHARGED: AtomicU32 = AtomicU32::new(0);
+
+static LAST: Mutex<Cell<Load>> = Mutex::new(Cell::new(Load {
+    window: 0,
+    idle: 0,
+    tasks: [Timing::NONE; TASK_COUNT],
+    sampler_latency: Timing::NONE,
+}));
+
+// Longest idle loop pass, anything slower was interrupted
+const IDLE_PASS_MAX: u32 = 64;
+
+/// Charges the task for the cycles until the span is dropped.
+pub struct Span {
+    task: Task,
+    start: u32,
+    charged: u32,
+}
+
+pub fn span(task: Task) -> Span {
+    Span {
+        task,
+        start: DWT::cycle_count(),
+        charged: CHARGED.load(Ordering::Relaxed),
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        interrupt::free(|_| {
+            let elapsed = DWT::cycle_count().wrapping_sub(self.start);
+            let nested = CHARGED.load(Ordering::Relaxed).wrapping_sub(self.charged);
+            TASK_STATS[self.task as usize].record(elapsed.wrapping_sub(nested));
+            CHARGED.store(self.charged.wrapping_add(elapsed), Ordering::Relaxed);
+        });
+    }
+}
+
+/// Records the release delay of the sampler, in cycles.
+pub fn sampler_latency(cycles: u32) {
+    SAMPLER_LATENCY.record(cycles);
+}
+
+/// Idle task body, counts the cycles nothing else ran.
+pub fn idle() -> ! {
+    let mut last = DWT::cycle_count();
+    loop {
+        let now = DWT::cycle_count();
+        let pass = now.wrapping_sub(last);
+        last = now;
+        if pass < IDLE_PASS_MAX {
+            IDLE.fetch_add(pass, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Closes the current window, short enough for the counters not to wrap.
+pub fn roll() {
+    interrupt::free(|cs| {
+        let now = DWT::cycle_count();
+        let mut load = Load {
+            window: now.wrapping_sub(WINDOW_START.swap(now, Ordering::Relaxed)),
+            idle: IDLE.swap(0, Ordering::Relaxed),
+            tasks: [Timing::NONE; TASK_COUNT],
+            sampler_latency: SAMPLER_LATENCY.take(),
+        };
+        for (timing, stats) in load.tasks.iter_mut().zip(TASK_STATS.iter()) {
+            *timing = stats.take();
+        }
+        LAST.borrow(cs).set(load);
+    });
+}
+
+/// Load of the last closed window.
+pub fn last() -> Load {
+    interrupt::free(|cs| LAST.borrow(cs).get())
+}
@@ -0,0 +1,130 @@
+//! RTT transport for the shell.
+//!
+//! `defmt-rtt` and `rtt-target` both define the `_SEGGER_RTT` control block,
+//! so with the `rtt-shell` feature the defmt frames go to up channel 0 of the
+//! `rtt-target` block through the logger below and the shell takes channel 1.
+
+use core::convert::Infallible;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use cortex_m::{interrupt, register};
+use embedded_hal::serial;
+use rtt_target::{rtt_init, DownChannel, UpChannel};
+
+pub struct RttSerial {
+    up: UpChannel,
+    down: DownChannel,
+}
+
+impl serial::Read<u8> for RttSerial {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Infallible> {
+        let mut byte = [0u8];
+        match self.down.read(&mut byte) {
+            1 => Ok(byte[0]),
+            _ => Err(nb::Error::WouldBlock),
+        }
+    }
+}
+
+impl serial::Write<u8> for RttSerial {
+    type Error = Infallible;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), Infallible> {
+        // The channel trims output when no host drains it
+        self.up.write(&[word]);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Sets up the RTT control block and routes defmt to up channel 0.
+///
+/// Returns the shell transport on up channel 1 / down channel 0 and the
+/// telemetry stream on up channel 2.
+pub fn init() -> (RttSerial, UpChannel) {
+    let channels = rtt_init! {
+        up: {
+            0: {
+                size: 1024
+                mode: NoBlockSkip
+                name: "defmt"
+            }
+            1: {
+                size: 512
+                mode: NoBlockTrim
+                name: "Terminal"
+            }
+            2: {
+                size: 2048
+                mode: NoBlockSkip
+                name: "Telemetry"
+            }
+        }
+        down: {
+            0: {
+                size: 64
+                name: "Terminal"
+            }
+        }
+    };
+
+    interrupt::free(|_| unsafe { LOG_CHANNEL = Some(channels.up.0) });
+
+    let serial = RttSerial {
+        up: channels.up.1,
+        down: channels.down.0,
+    };
+    (serial, channels.up.2)
+}
+
+static TAKEN: AtomicBool = AtomicBool::new(false);
+static mut INTERRUPTS_ACTIVE: bool = false;
+static mut LOG_CHANNEL: Option<UpChannel> = None;
+static mut ENCODER: defmt::Encoder = defmt::Encoder::new();
+
+#[defmt::global_logger]
+struct Logger;
+
+unsafe impl defmt::Logger for Logger {
+    fn acquire() {
+        let primask = register::primask::read();
+        interrupt::disable();
+
+        if TAKEN.load(Ordering::Relaxed) {
+            panic!("defmt logger taken reentrantly")
+        }
+        TAKEN.store(true, Ordering::Relaxed);
+
+        unsafe {
+            INTERRUPTS_ACTIVE = primask.is_active();
+            ENCODER.start_frame(do_write)
+        }
+    }
+
+    unsafe fn flush() {}
+
+    unsafe fn release() {
+        ENCODER.end_frame(do_write);
+        TAKEN.store(false, Ordering::Relaxed);
+        if INTERRUPTS_ACTIVE {
+            interrupt::enable()
+        }
+    }
+
+    unsafe fn write(bytes: &[u8]) {
+        ENCODER.write(bytes, do_write);
+    }
+}
+
+fn do_write(bytes: &[u8]) {
+    unsafe {
+        if let Some(channel) = LOG_CHANNEL.as_mut() {
+            channel.write(bytes);
+        }
+    }
+}
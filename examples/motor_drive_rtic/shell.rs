@@ -1,147 +1,1988 @@
-use core::fmt::Write;
-
-use stm32g4xx_hal as hal;
-
-use hal::gpio::*;
-use hal::serial::Serial;
-use hal::stm32;
+use core::fmt::{self, Write};
+use core::sync::atomic::Ordering;
 
 pub use ushell::{
-    autocomplete::StaticAutocomplete, control, history::LRUHistory, Environment,
+    autocomplete::StaticAutocomplete, control, history, history::LRUHistory, Environment,
     Input as ushell_input, ShellError as ushell_error, SpinResult, UShell,
 };
 
-use crate::MotorState;
+use crate::capture::CAPTURE_LEN;
+use crate::crash;
+use crate::faults::{self, Faults};
+use crate::json::{self, Status, Value};
+use crate::log::{self, Level, Module, MODULES, MODULE_COUNT};
+use crate::mem;
+use crate::params::NO_PIN;
+use crate::profile::{self, Load, Task, Timing, TASKS, TASK_COUNT};
+#[cfg(feature = "rtt-shell")]
+use crate::rtt::RttSerial;
+use crate::telemetry::{self, Sink};
+use crate::uart::{self, BufferedSerial};
+use crate::{MotorState, CONTROL_HZ, SYS_FREQ};
 use btoi::btoi;
+use embedded_hal::serial;
+use robo_core::calibrate::AngleCorrection;
+use robo_core::capture::{Edge, State, Trigger};
+use robo_core::characterize::{self, Compensation, Curve};
+use robo_core::health::Stat;
+use robo_core::menu::Action;
+use robo_core::sensor::{self, Error, RotarySensor};
+use robo_core::step::{self, Metrics};
+use robo_core::telemetry::{Channel, ChannelSet, CHANNEL_COUNT};
+use robo_core::velocity::Method;
 use rtic::Mutex;
 
-pub const CMD_MAX_LEN: usize = 32;
+pub const CMD_MAX_LEN: usize = 48;
+
+pub type Autocomplete = StaticAutocomplete<27>;
+pub type Shell<S> = UShell<S, Autocomplete, History, { CMD_MAX_LEN }>;
+
+/// Command history that leaves out the lines carrying a pin, so the
+/// arrow keys do not recall it.
+#[derive(Default)]
+pub struct History(LRUHistory<{ CMD_MAX_LEN }, 16>);
 
-pub type Autocomplete = StaticAutocomplete<9>;
-pub type History = LRUHistory<{ CMD_MAX_LEN }, 16>;
-pub type Uart = Serial<stm32::USART2, gpioa::PA2<Alternate<7>>, gpioa::PA3<Alternate<7>>>;
-pub type Shell = UShell<Uart, Autocomplete, History, { CMD_MAX_LEN }>;
+impl history::History<CMD_MAX_LEN> for History {
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+
+    fn push(&mut self, command: &str) -> Result<(), ()> {
+        match command.split_once(' ') {
+            // an empty line only moves the cursor back to the newest entry
+            Some(("unlock" | "pin", _)) => self.0.push(""),
+            _ => self.0.push(command),
+        }
+    }
+
+    fn go_back(&mut self) -> Option<heapless::String<CMD_MAX_LEN>> {
+        self.0.go_back()
+    }
+
+    fn go_forward(&mut self) -> Option<heapless::String<CMD_MAX_LEN>> {
+        self.0.go_forward()
+    }
+}
+
+/// Byte stream the shell runs over: USART2 or an RTT channel pair.
+pub trait Transport: serial::Read<u8> + serial::Write<u8> {
+    /// Whose session the shell on it keeps
+    const CLIENT: Client;
+}
+
+impl Transport for BufferedSerial {
+    const CLIENT: Client = Client::Uart;
+}
+
+#[cfg(feature = "rtt-shell")]
+impl Transport for RttSerial {
+    const CLIENT: Client = Client::Rtt;
+}
+
+/// The shells, each with a session of its own.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Client {
+    Uart = 0,
+    #[cfg(feature = "rtt-shell")]
+    Rtt = 1,
+}
+
+impl Client {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Client::Uart => "uart",
+            #[cfg(feature = "rtt-shell")]
+            Client::Rtt => "rtt",
+        }
+    }
+}
+
+pub const CLIENT_COUNT: usize = if cfg!(feature = "rtt-shell") { 2 } else { 1 };
+
+/// Shells sharing the same command set, one per transport.
+pub struct Shells {
+    pub uart: Shell<BufferedSerial>,
+    #[cfg(feature = "rtt-shell")]
+    pub rtt: Shell<RttSerial>,
+}
 
 pub enum EnvSignal {
     Shell,
+    #[cfg(feature = "rtt-shell")]
+    RttShell,
+    /// One second tick driving the session timers
+    Tick,
+    /// The sampler finished recording a step response
+    StepDone,
+    /// The sampler finished the `characterize` sweep
+    SweepDone,
+    /// The sampler finished the `calibrate` turns
+    CalibrationDone,
+    /// Chosen in the front panel menu
+    Panel(Action),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ShellMode {
+    Text,
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Access {
+    Locked,
+    Unlocked,
+}
+
+/// Reply mode and lock state of one shell.
+pub struct Session {
+    pub mode: ShellMode,
+    pub access: Access,
+    idle_secs: u32,
+    failed_unlocks: u32,
+}
+
+impl Session {
+    pub const fn new() -> Self {
+        Session {
+            mode: ShellMode::Text,
+            access: Access::Locked,
+            idle_secs: 0,
+            failed_unlocks: 0,
+        }
+    }
+
+    fn prompt(&self) -> &'static str {
+        match self.access {
+            Access::Locked => LOCKED_PROMPT,
+            Access::Unlocked => UNLOCKED_PROMPT,
+        }
+    }
+}
+
+/// A session per client, unlocking or switching one shell leaves the others
+/// as they are.
+pub struct Sessions([Session; CLIENT_COUNT]);
+
+impl Sessions {
+    pub const fn new() -> Self {
+        const LOCKED: Session = Session::new();
+        Sessions([LOCKED; CLIENT_COUNT])
+    }
+
+    pub fn get(&mut self, client: Client) -> &mut Session {
+        &mut self.0[client as usize]
+    }
 }
 
 pub type Env<'a> = super::app::env::SharedResources<'a>;
-pub type EnvResult = SpinResult<Uart, ()>;
+pub type EnvResult<S> = SpinResult<S, ()>;
 
 impl Env<'_> {
-    pub fn on_signal(&mut self, shell: &mut Shell, sig: EnvSignal) -> EnvResult {
+    pub fn on_signal(&mut self, shells: &mut Shells, sig: EnvSignal) {
         match sig {
-            EnvSignal::Shell => shell.spin(self),
+            EnvSignal::Shell => {
+                shells.uart.spin(self).ok();
+            }
+            #[cfg(feature = "rtt-shell")]
+            EnvSignal::RttShell => {
+                shells.rtt.spin(self).ok();
+            }
+            EnvSignal::Tick => self.tick(shells),
+            EnvSignal::StepDone => self.step_done(shells),
+            EnvSignal::SweepDone => self.sweep_done(shells),
+            EnvSignal::CalibrationDone => self.calibration_done(shells),
+            EnvSignal::Panel(action) => self.panel_action(action),
         }
+        self.drain_log(shells);
     }
 
-    fn hard_brake_cmd(&mut self, shell: &mut Shell) -> EnvResult {
-        let state = self.motor.lock(|motor| motor.get_state());
+    /// Prints the warnings and errors mirrored since the last signal.
+    fn drain_log(&mut self, shells: &mut Shells) {
+        let mut text = [0; log::LINE_MAX];
+        while let Some((level, module, msg)) = log::take_mirrored(&mut text) {
+            self.log_notice(&mut shells.uart, level, module, msg).ok();
+            #[cfg(feature = "rtt-shell")]
+            self.log_notice(&mut shells.rtt, level, module, msg).ok();
+        }
+    }
 
-        if state != MotorState::HardBrake {
-            self.motor.lock(|motor| motor.hard_brake());
-            write!(shell, "{0:}ALARM!!!{0:}HARD BRAKE!!!{0:}", CR)?;
-        } else {
-            write!(shell, "{0:}Already hard brake{0:}", CR)?;
+    fn log_notice<S: Transport>(
+        &mut self,
+        shell: &mut Shell<S>,
+        level: Level,
+        module: Module,
+        msg: &str,
+    ) -> EnvResult<S> {
+        self.reply(
+            shell,
+            "log",
+            &[
+                ("level", Value::Str(level.name())),
+                ("module", Value::Str(module.name())),
+                ("msg", Value::Str(msg)),
+            ],
+            format_args!(
+                "{0:}[{1}] {2}: {3}{0:}",
+                CR,
+                level.name(),
+                module.name(),
+                msg
+            ),
+        )?;
+        self.prompt(shell)
+    }
+
+    fn tick(&mut self, shells: &mut Shells) {
+        self.tick_session(&mut shells.uart);
+        #[cfg(feature = "rtt-shell")]
+        self.tick_session(&mut shells.rtt);
+    }
+
+    fn tick_session<S: Transport>(&mut self, shell: &mut Shell<S>) {
+        let session = self.session::<S>();
+        session.idle_secs = session.idle_secs.saturating_add(1);
+        if session.idle_secs >= LOCKOUT_SECS {
+            session.failed_unlocks = 0;
         }
+        if session.access == Access::Unlocked && session.idle_secs >= AUTO_LOCK_SECS {
+            session.access = Access::Locked;
+            info!(Shell, "{} locked after inactivity", S::CLIENT.name());
+            self.locked_notice(shell).ok();
+        }
+    }
 
-        Ok(())
+    fn session<S: Transport>(&mut self) -> &mut Session {
+        self.sessions.get(S::CLIENT)
     }
 
-    fn brake_cmd(&mut self, shell: &mut Shell, args: &str) -> EnvResult {
-        let max_duty = self.motor.lock(|motor| motor.get_max_duty());
-        match btoi::<u32>(args.as_bytes()) {
-            Ok(duty) if duty <= max_duty => {
-                self.motor.lock(|motor| motor.brake(duty));
-                write!(shell, "{0:}Brake enabled: duty={1:}{0:}\r\n", CR, duty)?;
+    fn step_done(&mut self, shells: &mut Shells) {
+        // evaluated in place, a copy of the recording would not fit the stack
+        let done = self.step.lock(|step| {
+            // a new step may have started before this signal got here
+            (!step.recorder.is_running()).then(|| {
+                let samples = step.recorder.samples();
+                let period = step.recorder.decimation() as f32 / CONTROL_HZ as f32;
+                let report = StepReport {
+                    duty: step.duty,
+                    duration_ms: step.duration_ms,
+                    metrics: step::metrics(samples, period),
+                };
+                (step.previous, report, samples.len())
+            })
+        });
+        let (previous, report, len) = match done {
+            Some(done) => done,
+            None => return,
+        };
+
+        self.motor.lock(|motor| motor.set_state(previous));
+        info!(Motor, "step recorded, {} samples", len);
+        self.step_report(&mut shells.uart, &report).ok();
+        #[cfg(feature = "rtt-shell")]
+        self.step_report(&mut shells.rtt, &report).ok();
+    }
+
+    fn panel_action(&mut self, action: Action) {
+        let settings = self.panel.lock(|panel| panel.settings);
+        match action {
+            Action::SetSetpoint(rpm) => {
+                self.panel.lock(|panel| panel.settings.setpoint_rpm = rpm);
+                if settings.armed {
+                    self.drive_setpoint(rpm);
+                }
+            }
+            Action::SetPwmFrequency(hz) => {
+                self.motor.lock(|motor| motor.set_frequency(hz));
+                self.panel.lock(|panel| panel.settings.pwm_hz = hz);
+                info!(Motor, "pwm frequency {} Hz", hz);
+            }
+            Action::Arm => {
+                self.panel.lock(|panel| panel.settings.armed = true);
+                info!(Motor, "armed from the panel");
+                self.drive_setpoint(settings.setpoint_rpm);
+            }
+            Action::Disarm => {
+                self.panel.lock(|panel| panel.settings.armed = false);
+                self.sweep.lock(|sweep| sweep.cancel());
+                self.step.lock(|step| step.recorder.cancel());
+                self.cancel_calibration();
+                self.motor.lock(|motor| motor.hard_brake());
+                info!(Motor, "disarmed from the panel");
+            }
+            Action::Calibrate => match self.start_sweep() {
+                Ok(()) => info!(Motor, "characterize sweep started from the panel"),
+                Err(reason) => warn!(Motor, "calibrate: {}", reason),
+            },
+        }
+    }
+
+    /// Open loop through the feed-forward, zero releases the motor.
+    fn drive_setpoint(&mut self, rpm: i32) {
+        let duty = match rpm {
+            0 => Ok(0.0),
+            rpm => self.duty_for(rpm as f32),
+        };
+        match duty {
+            Ok(duty) => self.motor.lock(|motor| motor.drive(duty)),
+            Err(reason) => warn!(Motor, "setpoint {} rpm: {}", rpm, reason),
+        }
+    }
+
+    fn sweep_done(&mut self, shells: &mut Shells) {
+        let speeds = self.sweep.lock(|sweep| *sweep.speeds());
+        let cw = characterize::fit(&speeds[0]);
+        let ccw = characterize::fit(&speeds[1]);
+        let result = match (cw, ccw) {
+            (None, None) => {
+                warn!(Motor, "characterize: motor did not turn");
+                None
             }
             _ => {
-                write!(shell, "{0:}unsupported duty cycle{0:}\r\n", CR)?;
+                let compensation = Compensation {
+                    cw: cw.unwrap_or(Curve::NONE),
+                    ccw: ccw.unwrap_or(Curve::NONE),
+                };
+                // in use right away, the flash write waits for `characterize save`
+                self.params
+                    .lock(|params| params.set(|params| params.compensation = compensation));
+                Some(())
+            }
+        };
+        self.sweep_report(&mut shells.uart, result).ok();
+        #[cfg(feature = "rtt-shell")]
+        self.sweep_report(&mut shells.rtt, result).ok();
+    }
+
+    fn calibration_done(&mut self, shells: &mut Shells) {
+        let result = match self.calibration.lock(|calibration| calibration.result()) {
+            Some(correction) => {
+                // in use right away, the flash write waits for `calibrate save`
+                self.params
+                    .lock(|params| params.set(|params| params.angle = correction));
+                Some(())
+            }
+            None => {
+                warn!(Motor, "calibrate: motor did not make a turn each way");
+                None
             }
+        };
+        self.restore_correction();
+        self.calibration_report(&mut shells.uart, result).ok();
+        #[cfg(feature = "rtt-shell")]
+        self.calibration_report(&mut shells.rtt, result).ok();
+    }
+
+    fn calibration_report<S: Transport>(
+        &mut self,
+        shell: &mut Shell<S>,
+        result: Option<()>,
+    ) -> EnvResult<S> {
+        match result {
+            Some(()) => self.correction_reply(shell)?,
+            None => self.reply_error(
+                shell,
+                "calibrate",
+                Status::SensorError,
+                format_args!("motor did not make a turn each way"),
+                format_args!(
+                    "{0:}Calibration failed, the motor did not make a turn each way{0:}",
+                    CR
+                ),
+            )?,
+        }
+        self.prompt(shell)
+    }
+
+    fn sweep_report<S: Transport>(
+        &mut self,
+        shell: &mut Shell<S>,
+        result: Option<()>,
+    ) -> EnvResult<S> {
+        match result {
+            Some(()) => self.compensation_reply(shell)?,
+            None => self.reply_error(
+                shell,
+                "characterize",
+                Status::SensorError,
+                format_args!("motor did not turn"),
+                format_args!(
+                    "{0:}Characterization failed, the motor did not turn{0:}",
+                    CR
+                ),
+            )?,
+        }
+        self.prompt(shell)
+    }
+
+    fn step_report<S: Transport>(
+        &mut self,
+        shell: &mut Shell<S>,
+        report: &StepReport,
+    ) -> EnvResult<S> {
+        match report.metrics {
+            Some(metrics) => self.reply(
+                shell,
+                "step",
+                &[
+                    ("duty", Value::I32(report.duty)),
+                    ("duration_ms", Value::U32(report.duration_ms)),
+                    ("initial", Value::F32(metrics.initial)),
+                    ("steady_state", Value::F32(metrics.steady_state)),
+                    (
+                        "rise_time",
+                        Value::F32(metrics.rise_time.unwrap_or(f32::NAN)),
+                    ),
+                    ("overshoot", Value::F32(metrics.overshoot)),
+                    (
+                        "settling_time",
+                        Value::F32(metrics.settling_time.unwrap_or(f32::NAN)),
+                    ),
+                ],
+                format_args!(
+                    "{0:}Step duty={1} for {2} ms, speed in rpm:\r\n\
+                     \tinitial       {3}\r\n\
+                     \tsteady state  {4}\r\n\
+                     \trise time     {5}\r\n\
+                     \tovershoot     {6} %\r\n\
+                     \tsettling time {7}{0:}",
+                    CR,
+                    report.duty,
+                    report.duration_ms,
+                    metrics.initial,
+                    metrics.steady_state,
+                    Seconds(metrics.rise_time),
+                    metrics.overshoot,
+                    Seconds(metrics.settling_time)
+                ),
+            )?,
+            None => self.reply_error(
+                shell,
+                "step",
+                Status::SensorError,
+                format_args!("no speed readings"),
+                format_args!("{0:}Step failed, no speed readings{0:}", CR),
+            )?,
+        }
+        self.prompt(shell)
+    }
+
+    fn locked_notice<S: Transport>(&mut self, shell: &mut Shell<S>) -> EnvResult<S> {
+        self.reply(
+            shell,
+            "lock",
+            &[("reason", Value::Str("inactivity"))],
+            format_args!("{0:}Session locked after inactivity{0:}", CR),
+        )?;
+        self.prompt(shell)
+    }
+
+    fn prompt<S: Transport>(&mut self, shell: &mut Shell<S>) -> EnvResult<S> {
+        if self.session::<S>().mode == ShellMode::Text {
+            shell.write_str(self.session::<S>().prompt())?;
         }
         Ok(())
     }
 
-    fn release_cmd(&mut self, shell: &mut Shell) -> EnvResult {
-        self.motor.lock(|motor| motor.release());
-        write!(shell, "{0:}Release brake{0:}\r\n", CR)?;
+    fn reply<S: Transport>(
+        &mut self,
+        shell: &mut Shell<S>,
+        cmd: &str,
+        fields: &[(&str, Value)],
+        text: fmt::Arguments,
+    ) -> EnvResult<S> {
+        match self.session::<S>().mode {
+            ShellMode::Text => shell.write_fmt(text)?,
+            ShellMode::Json => json::write_line(shell, cmd, Status::Ok, fields)?,
+        }
+        Ok(())
+    }
 
+    fn reply_error<S: Transport>(
+        &mut self,
+        shell: &mut Shell<S>,
+        cmd: &str,
+        status: Status,
+        reason: fmt::Arguments,
+        text: fmt::Arguments,
+    ) -> EnvResult<S> {
+        match self.session::<S>().mode {
+            ShellMode::Text => shell.write_fmt(text)?,
+            ShellMode::Json => {
+                json::write_line(shell, cmd, status, &[("error", Value::Fmt(reason))])?
+            }
+        }
         Ok(())
     }
 
-    fn cw_cmd(&mut self, shell: &mut Shell, args: &str) -> EnvResult {
+    fn parse_duty(&mut self, args: &str) -> Option<u32> {
         let max_duty = self.motor.lock(|motor| motor.get_max_duty());
         match btoi::<u32>(args.as_bytes()) {
-            Ok(duty) if duty <= max_duty => {
+            Ok(duty) if duty <= max_duty => Some(duty),
+            _ => None,
+        }
+    }
+
+    fn hard_brake_cmd<S: Transport>(&mut self, shell: &mut Shell<S>) -> EnvResult<S> {
+        let state = self.motor.lock(|motor| motor.get_state());
+
+        if state != MotorState::HardBrake {
+            self.motor.lock(|motor| motor.hard_brake());
+            self.reply(
+                shell,
+                "hard",
+                &[("changed", Value::Bool(true))],
+                format_args!("{0:}ALARM!!!{0:}HARD BRAKE!!!{0:}", CR),
+            )
+        } else {
+            self.reply(
+                shell,
+                "hard",
+                &[("changed", Value::Bool(false))],
+                format_args!("{0:}Already hard brake{0:}", CR),
+            )
+        }
+    }
+
+    fn brake_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        match self.parse_duty(args) {
+            Some(duty) => {
+                self.motor.lock(|motor| motor.brake(duty));
+                self.reply(
+                    shell,
+                    "brake",
+                    &[("duty", Value::U32(duty))],
+                    format_args!("{0:}Brake enabled: duty={1:}{0:}\r\n", CR, duty),
+                )
+            }
+            None => self.duty_error(shell, "brake"),
+        }
+    }
+
+    fn release_cmd<S: Transport>(&mut self, shell: &mut Shell<S>) -> EnvResult<S> {
+        self.motor.lock(|motor| motor.release());
+        self.reply(
+            shell,
+            "release",
+            &[],
+            format_args!("{0:}Release brake{0:}\r\n", CR),
+        )
+    }
+
+    fn cw_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        match self.parse_duty(args) {
+            Some(duty) => {
                 self.motor.lock(|motor| motor.cw(duty));
-                write!(shell, "{0:}Clockwise enabled: duty={1:}{0:}\r\n", CR, duty)?;
+                self.reply(
+                    shell,
+                    "cw",
+                    &[("duty", Value::U32(duty))],
+                    format_args!("{0:}Clockwise enabled: duty={1:}{0:}\r\n", CR, duty),
+                )
             }
-            _ => {
-                write!(shell, "{0:}unsupported duty cycle{0:}\r\n", CR)?;
+            None => self.duty_error(shell, "cw"),
+        }
+    }
+
+    fn ccw_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        match self.parse_duty(args) {
+            Some(duty) => {
+                self.motor.lock(|motor| motor.ccw(duty));
+                self.reply(
+                    shell,
+                    "ccw",
+                    &[("duty", Value::U32(duty))],
+                    format_args!("{0:}Counter-clockwise enabled: duty={1:}{0:}\r\n", CR, duty),
+                )
             }
+            None => self.duty_error(shell, "ccw"),
         }
-        Ok(())
     }
 
-    fn ccw_cmd(&mut self, shell: &mut Shell, args: &str) -> EnvResult {
+    fn step_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        let (target, duration) = args.split_once(' ').unwrap_or((args, ""));
         let max_duty = self.motor.lock(|motor| motor.get_max_duty());
-        match btoi::<u32>(args.as_bytes()) {
-            Ok(duty) if duty <= max_duty => {
-                self.motor.lock(|motor| motor.ccw(duty));
-                write!(
+        let duty = match target.strip_suffix("rpm") {
+            // open loop, through the feed-forward of `characterize`
+            Some(rpm) => match self.feed_forward(rpm) {
+                Ok(duty) => (duty * max_duty as f32) as i32,
+                Err(reason) => return self.step_error(shell, reason),
+            },
+            None => match btoi::<i32>(target.as_bytes()) {
+                Ok(duty) if duty.unsigned_abs() <= max_duty => duty,
+                _ => return self.step_error(shell, "unsupported duty"),
+            },
+        };
+        let duration_ms = match btoi::<u32>(duration.as_bytes()) {
+            Ok(ms) if (STEP_MIN_MS..=STEP_MAX_MS).contains(&ms) => ms,
+            _ => return self.step_error(shell, "unsupported duration"),
+        };
+        if self.step.lock(|step| step.recorder.is_running()) {
+            return self.step_error(shell, "step running");
+        }
+        if self.sweep.lock(|sweep| sweep.is_running()) {
+            return self.step_error(shell, "characterize running");
+        }
+        if self
+            .calibration
+            .lock(|calibration| calibration.is_running())
+        {
+            return self.step_error(shell, "calibrate running");
+        }
+
+        let previous = self.motor.lock(|motor| {
+            let previous = motor.get_state();
+            if duty >= 0 {
+                motor.cw(duty as u32);
+            } else {
+                motor.ccw(duty.unsigned_abs());
+            }
+            previous
+        });
+        self.step.lock(|step| {
+            step.duty = duty;
+            step.duration_ms = duration_ms;
+            step.previous = previous;
+            step.recorder.start(duration_ms * CONTROL_HZ / 1000);
+        });
+        info!(Motor, "step duty={} for {} ms", duty, duration_ms);
+
+        self.reply(
+            shell,
+            "step",
+            &[
+                ("duty", Value::I32(duty)),
+                ("duration_ms", Value::U32(duration_ms)),
+            ],
+            format_args!(
+                "{0:}Step duty={1} for {2} ms started{0:}",
+                CR, duty, duration_ms
+            ),
+        )
+    }
+
+    fn step_error<S: Transport>(&mut self, shell: &mut Shell<S>, reason: &str) -> EnvResult<S> {
+        self.reply_error(
+            shell,
+            "step",
+            Status::InvalidArgument,
+            format_args!("{}", reason),
+            format_args!("{0:}{1:}{0:}", CR, reason),
+        )
+    }
+
+    /// Signed duty fraction for a signed rpm argument.
+    fn feed_forward(&mut self, rpm: &str) -> Result<f32, &'static str> {
+        let rpm = lexical_core::parse::<f32>(rpm.as_bytes()).map_err(|_| "unsupported rpm")?;
+        self.duty_for(rpm)
+    }
+
+    fn duty_for(&mut self, rpm: f32) -> Result<f32, &'static str> {
+        let compensation = self.params.lock(|params| params.get().compensation);
+        if !compensation.cw.is_valid() && !compensation.ccw.is_valid() {
+            return Err("not characterized, run characterize first");
+        }
+        compensation.duty_for(rpm).ok_or("rpm out of reach")
+    }
+
+    fn rpm_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        match self.feed_forward(args) {
+            Ok(duty) => {
+                let state = self.motor.lock(|motor| {
+                    motor.drive(duty);
+                    motor.get_state()
+                });
+                self.reply(
                     shell,
-                    "{0:}Counter-clockwise enabled: duty={1:}{0:}\r\n",
-                    CR, duty
-                )?;
+                    "rpm",
+                    &[
+                        ("state", Value::Str(state.name())),
+                        ("duty", Value::U32(state.duty().unwrap_or(0))),
+                    ],
+                    format_args!(
+                        "{0:}Feed-forward: {1} duty={2}{0:}",
+                        CR,
+                        state.name(),
+                        state.duty().unwrap_or(0)
+                    ),
+                )
             }
-            _ => {
-                write!(shell, "{0:}unsupported duty cycle{0:}\r\n", CR)?;
+            Err(reason) => self.reply_error(
+                shell,
+                "rpm",
+                Status::InvalidArgument,
+                format_args!("{}", reason),
+                format_args!("{0:}{1:}{0:}", CR, reason),
+            ),
+        }
+    }
+
+    fn characterize_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        match args {
+            "" => {
+                let progress = self
+                    .sweep
+                    .lock(|sweep| sweep.is_running().then(|| sweep.progress()));
+                if let Some((done, total)) = progress {
+                    return self.reply(
+                        shell,
+                        "characterize",
+                        &[
+                            ("running", Value::Bool(true)),
+                            ("step", Value::U32(done as u32)),
+                            ("steps", Value::U32(total as u32)),
+                        ],
+                        format_args!("{0:}Sweeping, step {1} of {2}{0:}", CR, done, total),
+                    );
+                }
+                self.compensation_reply(shell)
+            }
+            "run" => match self.start_sweep() {
+                Ok(()) => {
+                    info!(Motor, "characterize sweep started");
+                    self.reply(
+                        shell,
+                        "characterize",
+                        &[("running", Value::Bool(true))],
+                        format_args!("{0:}Sweeping both directions, keep the shaft free{0:}", CR),
+                    )
+                }
+                Err(reason) => self.characterize_error(shell, reason),
+            },
+            "save" => {
+                if !self.motor_stopped() {
+                    return self.characterize_error(shell, "motor running");
+                }
+                match self.params.lock(|params| params.store()) {
+                    Ok(()) => self.compensation_reply(shell),
+                    Err(error) => self.reply_error(
+                        shell,
+                        "characterize",
+                        Status::StorageError,
+                        format_args!("{:?}", error),
+                        format_args!("{0:}Failed to store compensation: {1:?}{0:}", CR, error),
+                    ),
+                }
             }
+            "clear" => match self
+                .params
+                .lock(|params| params.update(|params| params.compensation = Compensation::NONE))
+            {
+                Ok(()) => self.compensation_reply(shell),
+                Err(error) => self.reply_error(
+                    shell,
+                    "characterize",
+                    Status::StorageError,
+                    format_args!("{:?}", error),
+                    format_args!("{0:}Failed to store compensation: {1:?}{0:}", CR, error),
+                ),
+            },
+            _ => self.characterize_error(shell, "unsupported subcommand"),
+        }
+    }
+
+    fn start_sweep(&mut self) -> Result<(), &'static str> {
+        if self.step.lock(|step| step.recorder.is_running()) {
+            return Err("step running");
         }
+        if self
+            .calibration
+            .lock(|calibration| calibration.is_running())
+        {
+            return Err("calibrate running");
+        }
+        let started = self.sweep.lock(|sweep| {
+            if sweep.is_running() {
+                return None;
+            }
+            sweep.start();
+            Some(sweep.duty())
+        });
+        let duty = started.ok_or("characterize running")?;
+        self.motor.lock(|motor| motor.drive(duty));
         Ok(())
     }
 
-    fn state_cmd(&mut self, shell: &mut Shell) -> EnvResult {
+    fn compensation_reply<S: Transport>(&mut self, shell: &mut Shell<S>) -> EnvResult<S> {
+        let (compensation, saved) = self
+            .params
+            .lock(|params| (params.get().compensation, params.is_saved()));
+        let (cw, ccw) = (compensation.cw, compensation.ccw);
+        self.reply(
+            shell,
+            "characterize",
+            &[
+                ("running", Value::Bool(false)),
+                ("saved", Value::Bool(saved)),
+                ("cw_deadband", Value::F32(cw.deadband)),
+                ("cw_speeds", Value::Floats(&cw.speeds)),
+                ("ccw_deadband", Value::F32(ccw.deadband)),
+                ("ccw_speeds", Value::Floats(&ccw.speeds)),
+            ],
+            format_args!(
+                "{0:}Compensation, rpm at evenly spaced duties from the deadband:\r\n\
+                 \tcw  {1}\r\n\
+                 \tccw {2}{3}{0:}",
+                CR,
+                CurveText(&cw),
+                CurveText(&ccw),
+                if saved {
+                    ""
+                } else {
+                    "\r\nNot saved, characterize save keeps it"
+                }
+            ),
+        )
+    }
+
+    /// Whether nothing drives the motor, a flash write stalls the sampler.
+    fn motor_stopped(&mut self) -> bool {
+        let running = self.step.lock(|step| step.recorder.is_running())
+            || self.sweep.lock(|sweep| sweep.is_running())
+            || self
+                .calibration
+                .lock(|calibration| calibration.is_running());
         let state = self.motor.lock(|motor| motor.get_state());
-        let max_duty = self.motor.lock(|motor| motor.get_max_duty());
+        !running
+            && matches!(
+                state,
+                MotorState::HardBrake | MotorState::Brake(_) | MotorState::Release
+            )
+    }
 
-        write!(
+    fn characterize_error<S: Transport>(
+        &mut self,
+        shell: &mut Shell<S>,
+        reason: &str,
+    ) -> EnvResult<S> {
+        self.reply_error(
             shell,
-            "{0:}Motor state: {1:?}\r\nMax duty: {2}{0:}",
-            CR, state, max_duty
-        )?;
+            "characterize",
+            Status::InvalidArgument,
+            format_args!("{}", reason),
+            format_args!("{0:}{1:}{0:}", CR, reason),
+        )
+    }
+
+    fn calibrate_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        match args {
+            "" => {
+                let progress = self
+                    .calibration
+                    .lock(|calibration| calibration.is_running().then(|| calibration.progress()));
+                if let Some((direction, turn)) = progress {
+                    let direction = if direction == 0 { "cw" } else { "ccw" };
+                    return self.reply(
+                        shell,
+                        "calibrate",
+                        &[
+                            ("running", Value::Bool(true)),
+                            ("direction", Value::Str(direction)),
+                            ("turn", Value::F32(turn)),
+                        ],
+                        format_args!(
+                            "{0:}Turning {1}, {2:.0} % of the turn{0:}",
+                            CR,
+                            direction,
+                            turn * 100.0
+                        ),
+                    );
+                }
+                self.correction_reply(shell)
+            }
+            "run" => match self.start_calibration() {
+                Ok(()) => {
+                    info!(Motor, "calibrate started");
+                    self.reply(
+                        shell,
+                        "calibrate",
+                        &[("running", Value::Bool(true))],
+                        format_args!(
+                            "{0:}Turning once each way, the start is the new zero{0:}",
+                            CR
+                        ),
+                    )
+                }
+                Err(reason) => self.calibrate_error(shell, reason),
+            },
+            "zero" => {
+                if self
+                    .calibration
+                    .lock(|calibration| calibration.is_running())
+                {
+                    return self.calibrate_error(shell, "calibrate running");
+                }
+                let correction = self.params.lock(|params| params.get().angle);
+                let angle = match self.raw_angle() {
+                    Ok(angle) => angle,
+                    Err(error) => return self.calibrate_error(shell, error.name()),
+                };
+                self.store_correction(shell, correction.with_zero_at(angle))
+            }
+            "save" => {
+                if !self.motor_stopped() {
+                    return self.calibrate_error(shell, "motor running");
+                }
+                match self.params.lock(|params| params.store()) {
+                    Ok(()) => self.correction_reply(shell),
+                    Err(error) => self.reply_error(
+                        shell,
+                        "calibrate",
+                        Status::StorageError,
+                        format_args!("{:?}", error),
+                        format_args!("{0:}Failed to store calibration: {1:?}{0:}", CR, error),
+                    ),
+                }
+            }
+            "clear" => {
+                // the sampler no longer drives a cancelled run
+                if self.cancel_calibration() {
+                    self.motor.lock(|motor| motor.hard_brake());
+                    info!(Motor, "calibrate cancelled");
+                }
+                self.store_correction(shell, AngleCorrection::NONE)
+            }
+            _ => self.calibrate_error(shell, "unsupported subcommand"),
+        }
+    }
 
+    fn start_calibration(&mut self) -> Result<(), &'static str> {
+        if self.step.lock(|step| step.recorder.is_running()) {
+            return Err("step running");
+        }
+        if self.sweep.lock(|sweep| sweep.is_running()) {
+            return Err("characterize running");
+        }
+        if self
+            .calibration
+            .lock(|calibration| calibration.is_running())
+        {
+            return Err("calibrate running");
+        }
+        // open loop, the slower direction decides
+        let duty = self
+            .duty_for(CALIBRATE_RPM)?
+            .max(-self.duty_for(-CALIBRATE_RPM)?);
+        let angle = self.raw_angle().map_err(|error| error.name())?;
+        // the sampler feeds the angles as the sensor reads them
+        self.angle_sensor
+            .lock(|angle_sensor| angle_sensor.set_correction(AngleCorrection::NONE));
+        let duty = self.calibration.lock(|calibration| {
+            calibration.start(angle, duty);
+            calibration.duty()
+        });
+        self.motor.lock(|motor| motor.drive(duty));
         Ok(())
     }
 
-    fn speed_cmd(&mut self, shell: &mut Shell) -> EnvResult {
-        let angle_speed = self
+    /// Stops a `calibrate` run, the stored correction applies again. True
+    /// when one was running, the motor is still turning then.
+    fn cancel_calibration(&mut self) -> bool {
+        let running = self.calibration.lock(|calibration| {
+            let running = calibration.is_running();
+            calibration.cancel();
+            running
+        });
+        if running {
+            self.restore_correction();
+        }
+        running
+    }
+
+    fn restore_correction(&mut self) {
+        let correction = self.params.lock(|params| params.get().angle);
+        self.angle_sensor
+            .lock(|angle_sensor| angle_sensor.set_correction(correction));
+    }
+
+    /// Sensor angle without the correction.
+    fn raw_angle(&mut self) -> Result<f32, Error> {
+        self.angle_sensor.lock(|angle_sensor| {
+            let correction = *angle_sensor.correction();
+            angle_sensor.set_correction(AngleCorrection::NONE);
+            let angle = angle_sensor.angle();
+            angle_sensor.set_correction(correction);
+            angle
+        })
+    }
+
+    fn store_correction<S: Transport>(
+        &mut self,
+        shell: &mut Shell<S>,
+        correction: AngleCorrection,
+    ) -> EnvResult<S> {
+        match self
+            .params
+            .lock(|params| params.update(|params| params.angle = correction))
+        {
+            Ok(()) => {
+                self.restore_correction();
+                self.correction_reply(shell)
+            }
+            Err(error) => self.reply_error(
+                shell,
+                "calibrate",
+                Status::StorageError,
+                format_args!("{:?}", error),
+                format_args!("{0:}Failed to store calibration: {1:?}{0:}", CR, error),
+            ),
+        }
+    }
+
+    fn correction_reply<S: Transport>(&mut self, shell: &mut Shell<S>) -> EnvResult<S> {
+        let (correction, saved) = self
+            .params
+            .lock(|params| (params.get().angle, params.is_saved()));
+        let zero = sensor::degrees(correction.zero);
+        let peak = sensor::degrees(correction.peak_error());
+        self.reply(
+            shell,
+            "calibrate",
+            &[
+                ("running", Value::Bool(false)),
+                ("saved", Value::Bool(saved)),
+                ("zero", Value::F32(zero)),
+                ("peak_error", Value::F32(peak)),
+                ("cos", Value::Floats(&correction.cos)),
+                ("sin", Value::Floats(&correction.sin)),
+            ],
+            format_args!(
+                "{0:}Angle zero at {1:.2} deg of the sensor, \
+                 error up to {2:.2} deg corrected{3}{0:}",
+                CR,
+                zero,
+                peak,
+                if saved {
+                    ""
+                } else {
+                    "\r\nNot saved, calibrate save keeps it"
+                }
+            ),
+        )
+    }
+
+    fn calibrate_error<S: Transport>(
+        &mut self,
+        shell: &mut Shell<S>,
+        reason: &str,
+    ) -> EnvResult<S> {
+        self.reply_error(
+            shell,
+            "calibrate",
+            Status::InvalidArgument,
+            format_args!("{}", reason),
+            format_args!("{0:}{1:}{0:}", CR, reason),
+        )
+    }
+
+    fn duty_error<S: Transport>(&mut self, shell: &mut Shell<S>, cmd: &str) -> EnvResult<S> {
+        self.reply_error(
+            shell,
+            cmd,
+            Status::InvalidArgument,
+            format_args!("unsupported duty cycle"),
+            format_args!("{0:}unsupported duty cycle{0:}\r\n", CR),
+        )
+    }
+
+    fn state_cmd<S: Transport>(&mut self, shell: &mut Shell<S>) -> EnvResult<S> {
+        let state = self.motor.lock(|motor| motor.get_state());
+        let max_duty = self.motor.lock(|motor| motor.get_max_duty());
+
+        self.reply(
+            shell,
+            "state",
+            &[
+                ("state", Value::Str(state.name())),
+                ("duty", Value::U32(state.duty().unwrap_or(0))),
+                ("max_duty", Value::U32(max_duty)),
+            ],
+            format_args!(
+                "{0:}Motor state: {1:?}\r\nMax duty: {2}{0:}",
+                CR, state, max_duty
+            ),
+        )
+    }
+
+    fn speed_cmd<S: Transport>(&mut self, shell: &mut Shell<S>) -> EnvResult<S> {
+        let velocity = self
             .angle_sensor
-            .lock(|angle_sensor| angle_sensor.read_angle_speed());
+            .lock(|angle_sensor| angle_sensor.velocity());
+
+        match velocity {
+            // degrees per second, like the telemetry
+            Ok(velocity) => {
+                let angle_speed = sensor::degrees(velocity);
+                self.reply(
+                    shell,
+                    "speed",
+                    &[("speed", Value::F32(angle_speed))],
+                    format_args!("{0:}Angle speed is {1:}{0:}", CR, angle_speed),
+                )
+            }
+            Err(error) => self.reply_error(
+                shell,
+                "speed",
+                Status::SensorError,
+                format_args!("{}", error.name()),
+                format_args!("{0:}Error for read speed is {1:}{0:}", CR, error.name()),
+            ),
+        }
+    }
 
-        match angle_speed {
-            Ok(angle_speed) => {
-                write!(shell, "{0:}Angle speed is {1:}{0:}", CR, angle_speed)?;
+    fn velocity_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        let (sub, value) = args.split_once(' ').unwrap_or((args, ""));
+        match (sub, Method::from_name(sub)) {
+            ("", _) => {}
+            ("bw", _) => match btoi::<u32>(value.as_bytes()) {
+                Ok(hz) => self
+                    .velocity
+                    .lock(|velocity| velocity.set_bandwidth(hz as f32)),
+                Err(_) => return self.velocity_error(shell, "unsupported bandwidth"),
+            },
+            (_, Some(method)) if value.is_empty() => {
+                self.velocity.lock(|velocity| velocity.set_method(method))
             }
-            Err(error) => {
-                write!(shell, "{0:}Error for read status is {1:?}{0:}", CR, error)?;
+            _ => return self.velocity_error(shell, "unsupported subcommand"),
+        }
+
+        let (method, bandwidth, window, speed) = self.velocity.lock(|velocity| {
+            (
+                velocity.method(),
+                velocity.bandwidth(),
+                velocity.window(),
+                sensor::degrees(velocity.velocity()),
+            )
+        });
+        self.reply(
+            shell,
+            "velocity",
+            &[
+                ("method", Value::Str(method.name())),
+                ("bandwidth_hz", Value::F32(bandwidth)),
+                ("window", Value::U32(window as u32)),
+                ("speed", Value::F32(speed)),
+            ],
+            format_args!(
+                "{0:}Speed from {1}, {2:.1} Hz bandwidth, {3} sample average\r\n\
+                 Speed is {4:.1} deg/s{0:}",
+                CR,
+                method.name(),
+                bandwidth,
+                window,
+                speed
+            ),
+        )
+    }
+
+    fn velocity_error<S: Transport>(&mut self, shell: &mut Shell<S>, reason: &str) -> EnvResult<S> {
+        self.reply_error(
+            shell,
+            "velocity",
+            Status::InvalidArgument,
+            format_args!("{}", reason),
+            format_args!("{0:}{1:}{0:}", CR, reason),
+        )
+    }
+
+    fn sensor_cmd<S: Transport>(&mut self, shell: &mut Shell<S>) -> EnvResult<S> {
+        let (present, status, stat, health) = self.angle_sensor.lock(|angle_sensor| {
+            // the status register latches, read it now rather than show the last tick's
+            let status = angle_sensor.status();
+            (
+                angle_sensor.is_present(),
+                status,
+                angle_sensor.stat(),
+                angle_sensor.health().clone(),
+            )
+        });
+        let mut names = [""; Stat::FLAGS.len()];
+        let mut len = 0;
+        for name in stat.names() {
+            names[len] = name;
+            len += 1;
+        }
+        let flags = &names[..len];
+        let state = match status {
+            Ok(status) if status.is_ok() => "ok",
+            Ok(_) => "degraded",
+            Err(error) => error.name(),
+        };
+
+        self.reply(
+            shell,
+            "sensor",
+            &[
+                ("present", Value::Bool(present)),
+                ("state", Value::Str(state)),
+                ("flags", Value::List(flags)),
+                ("failing", Value::Bool(health.is_failing())),
+                ("reads", Value::U32(health.reads())),
+                ("failures", Value::U32(health.failures())),
+                ("retries", Value::U32(health.retries())),
+                ("crc_errors", Value::U32(health.errors(Error::Crc))),
+                ("bus_errors", Value::U32(health.errors(Error::Bus))),
+                ("not_ready", Value::U32(health.errors(Error::NotReady))),
+            ],
+            format_args!(
+                "{0:}Angle sensor: {1}{2}\r\n\
+                 Flags: {3:?}\r\n\
+                 Reads: {4}, {5} failed, {6} retried\r\n\
+                 Errors: {7} crc, {8} bus, {9} not ready{0:}",
+                CR,
+                if present { state } else { "not found" },
+                if health.is_failing() { ", failing" } else { "" },
+                flags,
+                health.reads(),
+                health.failures(),
+                health.retries(),
+                health.errors(Error::Crc),
+                health.errors(Error::Bus),
+                health.errors(Error::NotReady)
+            ),
+        )
+    }
+
+    fn uart_cmd<S: Transport>(&mut self, shell: &mut Shell<S>) -> EnvResult<S> {
+        let stats = &uart::STATS;
+        let rx_bytes = stats.rx_bytes.load(Ordering::Relaxed);
+        let rx_overflow = stats.rx_overflow.load(Ordering::Relaxed);
+        let rx_errors = stats.rx_errors.load(Ordering::Relaxed);
+        let tx_bytes = stats.tx_bytes.load(Ordering::Relaxed);
+        let tx_queued = uart::TX.len() as u32;
+
+        self.reply(
+            shell,
+            "uart",
+            &[
+                ("rx_bytes", Value::U32(rx_bytes)),
+                ("rx_overflow", Value::U32(rx_overflow)),
+                ("rx_errors", Value::U32(rx_errors)),
+                ("tx_bytes", Value::U32(tx_bytes)),
+                ("tx_queued", Value::U32(tx_queued)),
+            ],
+            format_args!(
+                "{0:}RX: {1} bytes, {2} overflow, {3} errors\r\n\
+                 TX: {4} bytes, {5}/{6} queued{0:}",
+                CR,
+                rx_bytes,
+                rx_overflow,
+                rx_errors,
+                tx_bytes,
+                tx_queued,
+                uart::TX.capacity()
+            ),
+        )
+    }
+
+    fn top_cmd<S: Transport>(&mut self, shell: &mut Shell<S>) -> EnvResult<S> {
+        let load = profile::last();
+        let mut names = [""; TASK_COUNT];
+        let mut runs = [0.0; TASK_COUNT];
+        let mut min = [f32::NAN; TASK_COUNT];
+        let mut avg = [f32::NAN; TASK_COUNT];
+        let mut max = [f32::NAN; TASK_COUNT];
+        for (n, (task, timing)) in TASKS.iter().zip(load.tasks.iter()).enumerate() {
+            names[n] = task.name();
+            runs[n] = timing.runs as f32;
+            if timing.runs > 0 {
+                min[n] = micros(timing.min);
+                avg[n] = timing.avg().map_or(f32::NAN, micros);
+                max[n] = micros(timing.max);
+            }
+        }
+        let latency = load.sampler_latency;
+        let (latency_min, latency_avg, latency_max) = match latency.avg() {
+            Some(avg) => (micros(latency.min), micros(avg), micros(latency.max)),
+            None => (f32::NAN, f32::NAN, f32::NAN),
+        };
+        // the control loop has to fit its period with room for the shell
+        let budget = max[Task::Sampler as usize] * CONTROL_HZ as f32 / 10_000.0;
+
+        self.reply(
+            shell,
+            "top",
+            &[
+                ("idle_percent", Value::F32(load.idle_percent())),
+                ("sys_hz", Value::U32(SYS_FREQ)),
+                ("tasks", Value::List(&names)),
+                ("runs", Value::Floats(&runs)),
+                ("min_us", Value::Floats(&min)),
+                ("avg_us", Value::Floats(&avg)),
+                ("max_us", Value::Floats(&max)),
+                ("sampler_latency_min_us", Value::F32(latency_min)),
+                ("sampler_latency_avg_us", Value::F32(latency_avg)),
+                ("sampler_latency_max_us", Value::F32(latency_max)),
+                ("sampler_budget_percent", Value::F32(budget)),
+            ],
+            format_args!(
+                "{0:}CPU idle {1:.1} % at {2} MHz over the last second\r\n\
+                 {3}\
+                 Sampler takes up to {4:.1} % of its period{0:}",
+                CR,
+                load.idle_percent(),
+                SYS_FREQ / 1_000_000,
+                LoadText(&load),
+                budget
+            ),
+        )
+    }
+
+    fn mem_cmd<S: Transport>(&mut self, shell: &mut Shell<S>) -> EnvResult<S> {
+        let usage = mem::usage();
+        let (pending, peak, full) = mem::ENV_QUEUE.stats();
+        let stack_low = faults::active().contains(Faults::STACK_LOW);
+
+        self.reply(
+            shell,
+            "mem",
+            &[
+                ("static", Value::U32(usage.statics)),
+                ("stack_size", Value::U32(usage.stack_size)),
+                ("stack_peak", Value::U32(usage.stack_peak)),
+                ("stack_margin", Value::U32(usage.margin())),
+                ("stack_low", Value::Bool(stack_low)),
+                ("env_pending", Value::U32(pending)),
+                ("env_peak", Value::U32(peak)),
+                ("env_capacity", Value::U32(mem::ENV_CAPACITY)),
+                ("env_full", Value::U32(full)),
+            ],
+            format_args!(
+                "{0:}Static: {1} bytes\r\n\
+                 Stack: {2} of {3} bytes used at most, margin {4}{5}\r\n\
+                 Env queue: {6}/{7} pending, {8} at most, {9} lost when full{0:}",
+                CR,
+                usage.statics,
+                usage.stack_peak,
+                usage.stack_size,
+                usage.margin(),
+                if stack_low { " (low)" } else { "" },
+                pending,
+                mem::ENV_CAPACITY,
+                peak,
+                full
+            ),
+        )
+    }
+
+    fn telemetry_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        let (sub, value) = args.split_once(' ').unwrap_or((args, ""));
+        let (mut channels, mut decimation, mut sink) = self
+            .telemetry
+            .lock(|telemetry| (telemetry.channels, telemetry.decimation, telemetry.sink));
+
+        match sub {
+            "" => {}
+            "on" => self.telemetry.lock(|telemetry| telemetry.start()),
+            "off" => self.telemetry.lock(|telemetry| telemetry.stop()),
+            "ch" | "div" | "rate" | "sink" => {
+                match sub {
+                    "ch" => match ChannelSet::parse(value) {
+                        Some(set) if !set.is_empty() && set.is_subset(telemetry::AVAILABLE) => {
+                            channels = set
+                        }
+                        _ => return self.telemetry_error(shell, "unsupported channel list"),
+                    },
+                    "div" => match btoi::<u16>(value.as_bytes()) {
+                        Ok(value) if value > 0 => decimation = value,
+                        _ => return self.telemetry_error(shell, "unsupported decimation"),
+                    },
+                    // a frame per sampler tick at most, nothing is sent faster
+                    "rate" => match btoi::<u32>(value.as_bytes()) {
+                        Ok(hz) if hz > CONTROL_HZ => {
+                            return self.reply_error(
+                                shell,
+                                "telemetry",
+                                Status::InvalidArgument,
+                                format_args!("rate above {} Hz", CONTROL_HZ),
+                                format_args!(
+                                    "{0:}Rates go up to the {1} Hz of the sampler{0:}",
+                                    CR, CONTROL_HZ
+                                ),
+                            )
+                        }
+                        Ok(hz) if hz > 0 => {
+                            decimation = (CONTROL_HZ / hz).min(u16::MAX as u32) as u16
+                        }
+                        _ => return self.telemetry_error(shell, "unsupported rate"),
+                    },
+                    _ => match Sink::from_name(value) {
+                        Some(value) => sink = value,
+                        None => return self.telemetry_error(shell, "unsupported sink"),
+                    },
+                }
+                self.telemetry
+                    .lock(|telemetry| telemetry.configure(channels, decimation, sink));
             }
+            _ => return self.telemetry_error(shell, "unsupported subcommand"),
         }
 
+        let (enabled, sent, dropped) = self
+            .telemetry
+            .lock(|telemetry| (telemetry.enabled, telemetry.sent, telemetry.dropped));
+        let mut names = [""; CHANNEL_COUNT];
+        let names = channel_names(channels, &mut names);
+
+        self.reply(
+            shell,
+            "telemetry",
+            &[
+                ("enabled", Value::Bool(enabled)),
+                ("channels", Value::List(names)),
+                ("decimation", Value::U32(decimation as u32)),
+                ("rate_hz", Value::F32(CONTROL_HZ as f32 / decimation as f32)),
+                ("max_rate_hz", Value::U32(CONTROL_HZ)),
+                ("sink", Value::Str(sink.name())),
+                ("sent", Value::U32(sent)),
+                ("dropped", Value::U32(dropped)),
+            ],
+            format_args!(
+                "{0:}Telemetry: {1}, sink {2}, every {3} of {4} Hz, {4} Hz at most\r\n\
+                 Channels: {5:?}\r\n\
+                 Frames: {6} sent, {7} dropped{0:}",
+                CR,
+                if enabled { "on" } else { "off" },
+                sink.name(),
+                decimation,
+                CONTROL_HZ,
+                names,
+                sent,
+                dropped
+            ),
+        )
+    }
+
+    fn telemetry_error<S: Transport>(
+        &mut self,
+        shell: &mut Shell<S>,
+        reason: &str,
+    ) -> EnvResult<S> {
+        self.reply_error(
+            shell,
+            "telemetry",
+            Status::InvalidArgument,
+            format_args!("{}", reason),
+            format_args!("{0:}{1:}{0:}", CR, reason),
+        )
+    }
+
+    fn capture_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        let mut words = args.split_whitespace();
+        match words.next() {
+            None => {}
+            Some("arm") => {
+                let channel = words.next().and_then(Channel::from_name);
+                let edge = words.next().and_then(Edge::from_name);
+                let level = words
+                    .next()
+                    .and_then(|level| lexical_core::parse::<f32>(level.as_bytes()).ok());
+                let trigger = match (channel, edge, level) {
+                    (Some(channel), Some(edge), Some(level))
+                        if telemetry::AVAILABLE.contains(channel) =>
+                    {
+                        Trigger {
+                            channel,
+                            edge,
+                            level,
+                        }
+                    }
+                    _ => return self.capture_error(shell, "unsupported trigger"),
+                };
+                let pretrigger = match words.next() {
+                    None => DEFAULT_PRETRIGGER,
+                    Some(percent) => match btoi::<u8>(percent.trim_end_matches('%').as_bytes()) {
+                        Ok(percent) if percent <= 100 => percent,
+                        _ => return self.capture_error(shell, "unsupported pretrigger"),
+                    },
+                };
+                self.scope
+                    .lock(|scope| scope.capture.arm(scope.channels, trigger, pretrigger))
+                    .ok();
+            }
+            Some("stop") => self.scope.lock(|scope| scope.capture.stop()),
+            Some("ch") => match words.next().and_then(ChannelSet::parse) {
+                Some(set) if !set.is_empty() && set.is_subset(telemetry::AVAILABLE) => {
+                    self.scope.lock(|scope| scope.channels = set)
+                }
+                _ => return self.capture_error(shell, "unsupported channel list"),
+            },
+            Some("dump") => return self.capture_dump(shell),
+            Some("send") => {
+                if self.telemetry.lock(|telemetry| telemetry.enabled) {
+                    return self.capture_error(shell, "stop telemetry first");
+                }
+                if !self.scope.lock(|scope| scope.replay()) {
+                    return self.capture_error(shell, "no finished capture");
+                }
+            }
+            Some(_) => return self.capture_error(shell, "unsupported subcommand"),
+        }
+
+        let (state, channels, trigger, filled, depth, pretrigger, replay) =
+            self.scope.lock(|scope| {
+                let capture = &scope.capture;
+                let channels = match capture.state() {
+                    State::Idle => scope.channels,
+                    _ => capture.channels(),
+                };
+                (
+                    capture.state(),
+                    channels,
+                    capture.trigger(),
+                    capture.filled(),
+                    capture.depth(),
+                    capture.pretrigger(),
+                    scope.replay_progress(),
+                )
+            });
+        let mut names = [""; CHANNEL_COUNT];
+        let names = channel_names(channels, &mut names);
+        let trigger = TriggerText(trigger);
+
+        self.reply(
+            shell,
+            "capture",
+            &[
+                ("state", Value::Str(state.name())),
+                ("channels", Value::List(names)),
+                ("trigger", Value::Fmt(format_args!("{}", trigger))),
+                ("filled", Value::U32(filled as u32)),
+                ("depth", Value::U32(depth as u32)),
+                ("pretrigger", Value::U32(pretrigger as u32)),
+                ("replay", Value::Bool(replay.is_some())),
+            ],
+            format_args!(
+                "{0:}Capture: {1}, {2}/{3} records, {4} before trigger\r\n\
+                 Channels: {5:?}, {6} values\r\n\
+                 Trigger: {7}{0:}",
+                CR,
+                state.name(),
+                filled,
+                depth,
+                pretrigger,
+                names,
+                CAPTURE_LEN,
+                trigger
+            ),
+        )
+    }
+
+    /// Prints the finished capture as CSV, or one JSON line per record.
+    ///
+    /// Holds the shell for a few seconds at 115200 baud, `capture send` is
+    /// the faster way out.
+    fn capture_dump<S: Transport>(&mut self, shell: &mut Shell<S>) -> EnvResult<S> {
+        let (state, channels, depth) = self.scope.lock(|scope| {
+            let capture = &scope.capture;
+            (capture.state(), capture.channels(), capture.depth())
+        });
+        if state != State::Done {
+            return self.capture_error(shell, "no finished capture");
+        }
+
+        let period_us = (1_000_000 / CONTROL_HZ) as i32;
+        if self.session::<S>().mode == ShellMode::Text {
+            shell.write_str("\r\noffset,t_us")?;
+            for channel in channels.iter() {
+                write!(shell, ",{}", channel.name())?;
+            }
+            shell.write_str(CR)?;
+        }
+        for n in 0..depth {
+            // copied out so the sampler is not held up while printing
+            let mut values = [0.0; CHANNEL_COUNT];
+            let offset = match self.scope.lock(|scope| {
+                scope.capture.record(n).map(|record| {
+                    values[..record.values.len()].copy_from_slice(record.values);
+                    record.offset
+                })
+            }) {
+                Some(offset) => offset,
+                None => break,
+            };
+            let values = &values[..channels.len()];
+
+            match self.session::<S>().mode {
+                ShellMode::Text => {
+                    write!(shell, "{},{}", offset, offset * period_us)?;
+                    for value in values {
+                        write!(shell, ",{}", value)?;
+                    }
+                    shell.write_str(CR)?;
+                }
+                ShellMode::Json => {
+                    let mut fields = [("", Value::Bool(false)); 1 + CHANNEL_COUNT];
+                    fields[0] = ("offset", Value::I32(offset));
+                    for (field, (channel, value)) in
+                        fields[1..].iter_mut().zip(channels.iter().zip(values))
+                    {
+                        *field = (channel.name(), Value::F32(*value));
+                    }
+                    json::write_line(shell, "capture", Status::Ok, &fields[..1 + values.len()])?;
+                }
+            }
+        }
         Ok(())
     }
 
-    fn help_cmd(&mut self, shell: &mut Shell, args: &str) -> EnvResult {
+    fn capture_error<S: Transport>(&mut self, shell: &mut Shell<S>, reason: &str) -> EnvResult<S> {
+        self.reply_error(
+            shell,
+            "capture",
+            Status::InvalidArgument,
+            format_args!("{}", reason),
+            format_args!("{0:}{1:}{0:}", CR, reason),
+        )
+    }
+
+    fn plot_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        let (sub, value) = args.split_once(' ').unwrap_or((args, ""));
+        match sub {
+            "" => {}
+            "off" => {
+                self.plot
+                    .lock(|plot| plot.set_channels(ChannelSet::EMPTY))
+                    .ok();
+            }
+            "ch" => match ChannelSet::parse(value) {
+                Some(set) if !set.is_empty() && set.is_subset(telemetry::AVAILABLE) => {
+                    if self.plot.lock(|plot| plot.set_channels(set)).is_err() {
+                        return self.plot_error(shell, "at most 3 channels");
+                    }
+                }
+                _ => return self.plot_error(shell, "unsupported channel list"),
+            },
+            "span" => match btoi::<u32>(value.as_bytes()) {
+                Ok(ms) if (PLOT_SPAN_MIN_MS..=PLOT_SPAN_MAX_MS).contains(&ms) => {
+                    self.plot.lock(|plot| plot.set_span_ms(ms))
+                }
+                _ => return self.plot_error(shell, "unsupported span"),
+            },
+            _ => return self.plot_error(shell, "unsupported subcommand"),
+        }
+
+        let (channels, span_ms) = self.plot.lock(|plot| (plot.channels(), plot.span_ms()));
+        let mut names = [""; CHANNEL_COUNT];
+        let names = channel_names(channels, &mut names);
+        self.reply(
+            shell,
+            "plot",
+            &[
+                ("enabled", Value::Bool(!channels.is_empty())),
+                ("channels", Value::List(names)),
+                ("span_ms", Value::U32(span_ms)),
+            ],
+            format_args!(
+                "{0:}Plot: {1}, {2} ms across the display\r\n\
+                 Channels: {3:?}{0:}",
+                CR,
+                if channels.is_empty() { "off" } else { "on" },
+                span_ms,
+                names
+            ),
+        )
+    }
+
+    fn plot_error<S: Transport>(&mut self, shell: &mut Shell<S>, reason: &str) -> EnvResult<S> {
+        self.reply_error(
+            shell,
+            "plot",
+            Status::InvalidArgument,
+            format_args!("{}", reason),
+            format_args!("{0:}{1:}{0:}", CR, reason),
+        )
+    }
+
+    fn log_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        let (sub, value) = args.split_once(' ').unwrap_or((args, ""));
+        match (sub, value) {
+            ("", _) => {}
+            ("mirror", "on") => log::set_mirror(true),
+            ("mirror", "off") => log::set_mirror(false),
+            ("mirror", _) => return self.log_error(shell, "unsupported mirror setting"),
+            (module, level) => {
+                let level = match Level::from_name(level) {
+                    Some(level) => level,
+                    None => return self.log_error(shell, "unsupported level"),
+                };
+                match module {
+                    "all" => MODULES
+                        .iter()
+                        .for_each(|module| log::set_level(*module, level)),
+                    _ => match Module::from_name(module) {
+                        Some(module) => log::set_level(module, level),
+                        None => return self.log_error(shell, "unsupported module"),
+                    },
+                }
+            }
+        }
+
+        let mut levels = [""; MODULE_COUNT];
+        for (name, module) in levels.iter_mut().zip(MODULES.iter()) {
+            *name = log::level(*module).name();
+        }
+        let mirror = log::mirror_enabled();
+        let mut fields = [("", Value::Bool(false)); MODULE_COUNT + 1];
+        for (field, (module, level)) in fields.iter_mut().zip(MODULES.iter().zip(levels)) {
+            *field = (module.name(), Value::Str(level));
+        }
+        fields[MODULE_COUNT] = ("mirror", Value::Bool(mirror));
+
+        self.reply(
+            shell,
+            "log",
+            &fields,
+            format_args!(
+                "{0:}Log levels:\r\n{1}Mirror to shell: {2}{0:}",
+                CR,
+                LevelsText(&levels),
+                if mirror { "on" } else { "off" }
+            ),
+        )
+    }
+
+    fn log_error<S: Transport>(&mut self, shell: &mut Shell<S>, reason: &str) -> EnvResult<S> {
+        self.reply_error(
+            shell,
+            "log",
+            Status::InvalidArgument,
+            format_args!("{}", reason),
+            format_args!("{0:}{1:}{0:}", CR, reason),
+        )
+    }
+
+    fn crash_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
         match args {
-            _ => shell.write_str(HELP)?,
+            "" => {}
+            "clear" => {
+                if let Err(error) = self
+                    .params
+                    .lock(|params| params.update(|params| params.crash = crash::Report::NONE))
+                {
+                    return self.reply_error(
+                        shell,
+                        "crash",
+                        Status::StorageError,
+                        format_args!("{:?}", error),
+                        format_args!("{0:}Failed to clear crash report: {1:?}{0:}", CR, error),
+                    );
+                }
+            }
+            _ => {
+                return self.reply_error(
+                    shell,
+                    "crash",
+                    Status::InvalidArgument,
+                    format_args!("unsupported subcommand"),
+                    format_args!("{0:}unsupported subcommand{0:}", CR),
+                )
+            }
+        }
+
+        let report = self.params.lock(|params| params.get().crash);
+        let kind = match report.kind() {
+            Some(kind) => kind,
+            None => {
+                return self.reply(
+                    shell,
+                    "crash",
+                    &[("kind", Value::Str("none"))],
+                    format_args!("{0:}No crash recorded{0:}", CR),
+                )
+            }
+        };
+        self.reply(
+            shell,
+            "crash",
+            &[
+                ("kind", Value::Str(kind.name())),
+                ("pc", Value::U32(report.pc)),
+                ("lr", Value::U32(report.lr)),
+                ("cfsr", Value::U32(report.cfsr)),
+                ("hfsr", Value::U32(report.hfsr)),
+                ("mmfar", Value::U32(report.mmfar)),
+                ("bfar", Value::U32(report.bfar)),
+                ("message", Value::Str(report.message())),
+            ],
+            format_args!(
+                "{0:}Last crash: {1}\r\n\
+                 \tpc    {2:#010x}\r\n\
+                 \tlr    {3:#010x}\r\n\
+                 \tcfsr  {4:#010x}\r\n\
+                 \thfsr  {5:#010x}\r\n\
+                 \tmmfar {6:#010x}\r\n\
+                 \tbfar  {7:#010x}\r\n\
+                 \t{8}{0:}",
+                CR,
+                kind.name(),
+                report.pc,
+                report.lr,
+                report.cfsr,
+                report.hfsr,
+                report.mmfar,
+                report.bfar,
+                report.message()
+            ),
+        )
+    }
+
+    fn mode_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        match args {
+            "text" => self.session::<S>().mode = ShellMode::Text,
+            "json" => self.session::<S>().mode = ShellMode::Json,
+            "" => {}
+            _ => {
+                return self.reply_error(
+                    shell,
+                    "mode",
+                    Status::InvalidArgument,
+                    format_args!("unsupported mode"),
+                    format_args!("{0:}unsupported mode: \"{1:}\"{0:}", CR, args),
+                )
+            }
+        }
+        let mode = match self.session::<S>().mode {
+            ShellMode::Text => "text",
+            ShellMode::Json => "json",
+        };
+        self.reply(
+            shell,
+            "mode",
+            &[("mode", Value::Str(mode))],
+            format_args!("{0:}Shell mode: {1:}{0:}", CR, mode),
+        )
+    }
+
+    fn unlock_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        if self.session::<S>().failed_unlocks >= MAX_FAILED_UNLOCKS {
+            return self.reply_error(
+                shell,
+                "unlock",
+                Status::AccessDenied,
+                format_args!("too many attempts"),
+                format_args!("{0:}Too many attempts, try again later{0:}", CR),
+            );
+        }
+
+        let pin = self.params.lock(|params| params.get().unlock_pin);
+        if pin == NO_PIN {
+            return self.reply_error(
+                shell,
+                "unlock",
+                Status::AccessDenied,
+                format_args!("no pin set"),
+                format_args!("{0:}No pin set yet, choose one with pin <new pin>{0:}", CR),
+            );
+        }
+        match btoi::<u32>(args.as_bytes()) {
+            Ok(value) if value == pin => {
+                let session = self.session::<S>();
+                session.access = Access::Unlocked;
+                session.failed_unlocks = 0;
+                self.reply(
+                    shell,
+                    "unlock",
+                    &[],
+                    format_args!("{0:}Actuator commands unlocked{0:}", CR),
+                )
+            }
+            _ => {
+                let session = self.session::<S>();
+                session.failed_unlocks += 1;
+                warn!(
+                    Shell,
+                    "wrong unlock pin on {}, attempt {}",
+                    S::CLIENT.name(),
+                    session.failed_unlocks
+                );
+                self.reply_error(
+                    shell,
+                    "unlock",
+                    Status::AccessDenied,
+                    format_args!("wrong pin"),
+                    format_args!("{0:}Wrong pin{0:}", CR),
+                )
+            }
+        }
+    }
+
+    fn lock_cmd<S: Transport>(&mut self, shell: &mut Shell<S>) -> EnvResult<S> {
+        self.session::<S>().access = Access::Locked;
+        self.reply(
+            shell,
+            "lock",
+            &[],
+            format_args!("{0:}Actuator commands locked{0:}", CR),
+        )
+    }
+
+    fn pin_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        let pin = match btoi::<u32>(args.as_bytes()) {
+            Ok(pin) if pin <= MAX_PIN => pin,
+            _ => {
+                return self.reply_error(
+                    shell,
+                    "pin",
+                    Status::InvalidArgument,
+                    format_args!("pin must be up to 8 digits"),
+                    format_args!("{0:}Pin must be up to 8 digits{0:}", CR),
+                )
+            }
+        };
+
+        match self
+            .params
+            .lock(|params| params.update(|params| params.unlock_pin = pin))
+        {
+            Ok(()) => self.reply(shell, "pin", &[], format_args!("{0:}Pin changed{0:}", CR)),
+            Err(error) => self.reply_error(
+                shell,
+                "pin",
+                Status::StorageError,
+                format_args!("{:?}", error),
+                format_args!("{0:}Failed to store pin: {1:?}{0:}", CR, error),
+            ),
+        }
+    }
+
+    fn help_cmd<S: Transport>(&mut self, shell: &mut Shell<S>, args: &str) -> EnvResult<S> {
+        match args {
+            _ => self.reply(
+                shell,
+                "help",
+                &[("commands", Value::List(&AUTOCOMPLETE.0))],
+                format_args!("{}", HELP),
+            ),
         }
-        Ok(())
     }
 }
 
-impl Environment<Uart, Autocomplete, History, (), { CMD_MAX_LEN }> for Env<'_> {
-    fn command(&mut self, shell: &mut Shell, cmd: &str, args: &str) -> EnvResult {
+impl<S: Transport> Environment<S, Autocomplete, History, (), { CMD_MAX_LEN }> for Env<'_> {
+    fn command(&mut self, shell: &mut Shell<S>, cmd: &str, args: &str) -> EnvResult<S> {
+        self.session::<S>().idle_secs = 0;
+
+        // with no pin yet `pin` sets the first one locked, both shells run
+        // over the ST-LINK USB, so that takes the board at hand
+        let first_pin =
+            cmd == "pin" && self.params.lock(|params| params.get().unlock_pin) == NO_PIN;
+        if self.session::<S>().access == Access::Locked && PRIVILEGED.contains(&cmd) && !first_pin {
+            self.reply_error(
+                shell,
+                cmd,
+                Status::AccessDenied,
+                format_args!("locked"),
+                format_args!("{0:}\"{1:}\" requires unlock <pin>{0:}", CR, cmd),
+            )?;
+            return self.prompt(shell);
+        }
+
+        // driving the motor by hand ends a running step or sweep as it is
+        if MOTOR_COMMANDS.contains(&cmd) {
+            self.step.lock(|step| step.recorder.cancel());
+            self.sweep.lock(|sweep| sweep.cancel());
+            self.cancel_calibration();
+        }
+
         match cmd {
             "hard" => self.hard_brake_cmd(shell)?,
             "brake" => self.brake_cmd(shell, args)?,
@@ -150,20 +1991,55 @@ impl Environment<Uart, Autocomplete, History, (), { CMD_MAX_LEN }> for Env<'_> {
             "ccw" => self.ccw_cmd(shell, args)?,
             "state" => self.state_cmd(shell)?,
             "speed" => self.speed_cmd(shell)?,
-            "clear" => shell.clear()?,
+            "sensor" => self.sensor_cmd(shell)?,
+            "uart" => self.uart_cmd(shell)?,
+            "top" => self.top_cmd(shell)?,
+            "mem" => self.mem_cmd(shell)?,
+            "telemetry" => self.telemetry_cmd(shell, args)?,
+            "capture" => self.capture_cmd(shell, args)?,
+            "plot" => self.plot_cmd(shell, args)?,
+            "step" => self.step_cmd(shell, args)?,
+            "rpm" => self.rpm_cmd(shell, args)?,
+            "characterize" => self.characterize_cmd(shell, args)?,
+            "calibrate" => self.calibrate_cmd(shell, args)?,
+            "velocity" => self.velocity_cmd(shell, args)?,
+            "log" => self.log_cmd(shell, args)?,
+            "crash" => self.crash_cmd(shell, args)?,
+            "mode" => self.mode_cmd(shell, args)?,
+            "unlock" => self.unlock_cmd(shell, args)?,
+            "lock" => self.lock_cmd(shell)?,
+            "pin" => self.pin_cmd(shell, args)?,
+            "clear" => {
+                // escape codes are not JSON, the status line alone answers
+                if self.session::<S>().mode == ShellMode::Text {
+                    shell.clear()?;
+                }
+                self.reply(shell, "clear", &[], format_args!(""))?
+            }
             "help" => self.help_cmd(shell, args)?,
-            "" => shell.write_str(CR)?,
-            _ => write!(shell, "{0:}unsupported command: \"{1:}\"{0:}", CR, cmd)?,
+            "" => {
+                if self.session::<S>().mode == ShellMode::Text {
+                    shell.write_str(CR)?
+                }
+            }
+            _ => self.reply_error(
+                shell,
+                cmd,
+                Status::UnknownCommand,
+                format_args!("unsupported command"),
+                format_args!("{0:}unsupported command: \"{1:}\"{0:}", CR, cmd),
+            )?,
         }
-        shell.write_str(SHELL_PROMPT)?;
-        Ok(())
+        self.prompt(shell)
     }
 
-    fn control(&mut self, shell: &mut Shell, code: u8) -> EnvResult {
+    fn control(&mut self, shell: &mut Shell<S>, code: u8) -> EnvResult<S> {
         match code {
             control::CTRL_C => {
-                shell.write_str(CR)?;
-                shell.write_str(SHELL_PROMPT)?;
+                if self.session::<S>().mode == ShellMode::Text {
+                    shell.write_str(CR)?;
+                }
+                self.prompt(shell)?;
             }
             _ => {}
         }
@@ -172,23 +2048,214 @@ impl Environment<Uart, Autocomplete, History, (), { CMD_MAX_LEN }> for Env<'_> {
 }
 
 pub const AUTOCOMPLETE: Autocomplete = StaticAutocomplete([
-    "hard", "brake", "release", "cw", "ccw", "state", "speed", "clear", "help",
+    "hard",
+    "brake",
+    "release",
+    "cw",
+    "ccw",
+    "rpm",
+    "state",
+    "speed",
+    "velocity",
+    "sensor",
+    "step",
+    "characterize",
+    "calibrate",
+    "uart",
+    "top",
+    "mem",
+    "telemetry",
+    "capture",
+    "plot",
+    "log",
+    "crash",
+    "mode",
+    "unlock",
+    "lock",
+    "pin",
+    "clear",
+    "help",
 ]);
 
-const SHELL_PROMPT: &str = "#> ";
+/// Commands driving the actuators or changing the access pin.
+const PRIVILEGED: [&str; 9] = [
+    "brake",
+    "release",
+    "cw",
+    "ccw",
+    "rpm",
+    "step",
+    "characterize",
+    "calibrate",
+    "pin",
+];
+
+/// Commands setting the motor state directly.
+const MOTOR_COMMANDS: [&str; 6] = ["hard", "brake", "release", "cw", "ccw", "rpm"];
+
+/// Speed of the `calibrate` turns, slow enough for the error to show
+const CALIBRATE_RPM: f32 = 30.0;
+
+const STEP_MIN_MS: u32 = 10;
+const STEP_MAX_MS: u32 = 10_000;
+
+const AUTO_LOCK_SECS: u32 = 120;
+const MAX_FAILED_UNLOCKS: u32 = 3;
+const LOCKOUT_SECS: u32 = 30;
+const MAX_PIN: u32 = 99_999_999;
+
+const DEFAULT_PRETRIGGER: u8 = 10;
+
+/// Time across the display plot, rounded to whole samples per column.
+const PLOT_SPAN_MIN_MS: u32 = 100;
+const PLOT_SPAN_MAX_MS: u32 = 60_000;
+
+const LOCKED_PROMPT: &str = "$> ";
+const UNLOCKED_PROMPT: &str = "#> ";
 const CR: &str = "\r\n";
 const HELP: &str = "\r\n\
 G474 ROBO Shell v.1\r\n\r\n\
 USAGE:\r\n\
 \tcommand\r\n\r\n\
-COMMANDS:\r\n\
-\thard      Hard brake\r\n\
-\tbrake     Brake\r\n\
-\trelease   Release\r\n\
-\tcw        Clockwise\r\n\
-\tccw       Counter-clockwise\r\n\
-\tstate     Motor state\r\n\
-\tspeed     Motor speed\r\n\
-\tclear     Clear screen\r\n\
-\thelp      Print this message\r\n\
+COMMANDS (* requires unlock):\r\n\
+\thard           Hard brake\r\n\
+\tbrake        * Brake\r\n\
+\trelease      * Release\r\n\
+\tcw           * Clockwise\r\n\
+\tccw          * Counter-clockwise\r\n\
+\trpm          * Feed-forward speed: rpm <signed rpm>\r\n\
+\tstate          Motor state\r\n\
+\tspeed          Motor speed\r\n\
+\tvelocity       Speed estimate: sensor | diff | average | tracking | bw <hz>\r\n\
+\tsensor         Angle sensor status flags and read errors\r\n\
+\tstep         * Step response: step <signed duty | Nrpm> <ms>\r\n\
+\tcharacterize * Deadband and speed curve: run | save | clear\r\n\
+\tcalibrate    * Angle sensor error and zero: run | save | zero | clear\r\n\
+\tuart           Serial statistics\r\n\
+\ttop            Task timing and CPU load\r\n\
+\tmem            Stack high-water mark, static RAM and queues\r\n\
+\ttelemetry      Binary stream: on | off | ch <list> | div <n> | rate <hz> |\r\n\
+\t               sink <uart|rtt>\r\n\
+\tcapture        Triggered capture: arm <signal> <rising|falling> <level> [pretrigger%] |\r\n\
+\t               stop | ch <list> | dump | send\r\n\
+\tplot           Display plot: ch <list> | span <ms> | off\r\n\
+\tlog            Log levels: <module|all> <off|error|warn|info|debug|trace> |\r\n\
+\t               mirror <on|off>\r\n\
+\tcrash          Last panic or HardFault: clear\r\n\
+\tmode           Reply mode: text | json\r\n\
+\tunlock         Unlock actuator commands: unlock <pin>\r\n\
+\tlock           Lock actuator commands\r\n\
+\tpin          * Change unlock pin, the first needs no unlock: pin <new pin>\r\n\
+\tclear          Clear screen\r\n\
+\thelp           Print this message\r\n\
 ";
+
+/// Names of `channels`, backed by `names`.
+fn channel_names(
+    channels: ChannelSet,
+    names: &mut [&'static str; CHANNEL_COUNT],
+) -> &[&'static str] {
+    for (name, channel) in names.iter_mut().zip(channels.iter()) {
+        *name = channel.name();
+    }
+    &names[..channels.len()]
+}
+
+/// Settings and metrics of a finished step.
+struct StepReport {
+    duty: i32,
+    duration_ms: u32,
+    metrics: Option<Metrics>,
+}
+
+struct CurveText<'a>(&'a Curve);
+
+impl fmt::Display for CurveText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.0.is_valid() {
+            return f.write_str("not characterized");
+        }
+        write!(f, "deadband {:.1} %:", self.0.deadband * 100.0)?;
+        for speed in self.0.speeds.iter() {
+            write!(f, " {:.0}", speed)?;
+        }
+        Ok(())
+    }
+}
+
+/// Level per module, one line each.
+struct LevelsText<'a>(&'a [&'static str; MODULE_COUNT]);
+
+impl fmt::Display for LevelsText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (module, level) in MODULES.iter().zip(self.0.iter()) {
+            write!(f, "\t{:<10}{}\r\n", module.name(), level)?;
+        }
+        Ok(())
+    }
+}
+
+fn micros(cycles: u32) -> f32 {
+    cycles as f32 * 1_000_000.0 / SYS_FREQ as f32
+}
+
+/// Timing table of the tasks and the sampler latency, in microseconds.
+struct LoadText<'a>(&'a Load);
+
+impl fmt::Display for LoadText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("\ttask           runs   min us   avg us   max us\r\n")?;
+        for (task, timing) in TASKS.iter().zip(self.0.tasks.iter()) {
+            write!(f, "\t{:<13}{}\r\n", task.name(), TimingText(timing))?;
+        }
+        let latency = TimingText(&self.0.sampler_latency);
+        write!(f, "\t{:<13}{}\r\n", "sampler_lat", latency)
+    }
+}
+
+struct TimingText<'a>(&'a Timing);
+
+impl fmt::Display for TimingText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let timing = self.0;
+        match timing.avg() {
+            Some(avg) => write!(
+                f,
+                "{:>6} {:>8.1} {:>8.1} {:>8.1}",
+                timing.runs,
+                micros(timing.min),
+                micros(avg),
+                micros(timing.max)
+            ),
+            None => write!(f, "{:>6} {:>8} {:>8} {:>8}", 0, "-", "-", "-"),
+        }
+    }
+}
+
+struct Seconds(Option<f32>);
+
+impl fmt::Display for Seconds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(seconds) => write!(f, "{:.3} s", seconds),
+            None => f.write_str("-"),
+        }
+    }
+}
+
+struct TriggerText(Option<Trigger>);
+
+impl fmt::Display for TriggerText {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(trigger) => write!(
+                f,
+                "{} {} {}",
+                trigger.channel.name(),
+                trigger.edge.name(),
+                trigger.level
+            ),
+            None => f.write_str("none"),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! SPI1 shared by the TLE5012 and the SSD1306.
+//!
+//! Each device brings its own chip select, mode and clock, the bus is set up
+//! for a device on every access. The sensor is read blocking by the sampler,
+//! display pages go out by DMA1 channel 2 and are started by the sampler
+//! right after its own transfer, so a page is long done by the next sample
+//! and the sensor never waits for the bus.
+
+use core::convert::Infallible;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use embedded_hal::blocking::spi;
+use embedded_hal::spi::{Mode, Phase, Polarity, MODE_0};
+use stm32g4xx_hal as hal;
+
+use hal::gpio::{gpioa, Alternate};
+use hal::stm32;
+
+pub type Pins = (
+    gpioa::PA5<Alternate<5>>,
+    gpioa::PA6<Alternate<5>>,
+    gpioa::PA7<Alternate<5>>,
+);
+
+// DMAMUX request line of SPI1_TX
+const DMAREQ_SPI1_TX: u8 = 11;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Device {
+    Sensor = 0,
+    Display = 1,
+}
+
+impl Device {
+    fn mode(&self) -> Mode {
+        match self {
+            Device::Sensor => tle5012::MODE,
+            Device::Display => MODE_0,
+        }
+    }
+
+    /// Clock divider `BR`, the bus runs at `SYS_FREQ / 2^(BR + 1)`
+    fn baud_rate(&self) -> u8 {
+        match self {
+            // 500 kHz
+            Device::Sensor => 4,
+            // 8 MHz
+            Device::Display => 0,
+        }
+    }
+}
+
+static SELECTED: AtomicU8 = AtomicU8::new(u8::MAX);
+static DMA_BUSY: AtomicBool = AtomicBool::new(false);
+
+/// Sensor side of the bus, also the owner of SPI1 and its pins.
+pub struct SensorSpi {
+    _spi: stm32::SPI1,
+    _pins: Pins,
+}
+
+/// Takes over SPI1 as master of 8 bit frames with software chip selects,
+/// set up for the sensor.
+pub fn init(spi: stm32::SPI1, pins: Pins, dma: &stm32::DMA1, dmamux: &stm32::DMAMUX) -> SensorSpi {
+    unsafe {
+        let rcc = &*stm32::RCC::ptr();
+        rcc.apb2enr.modify(|_, w| w.spi1en().set_bit());
+        rcc.ahb1enr
+            .modify(|_, w| w.dma1en().set_bit().dmamuxen().set_bit());
+
+        spi.cr2
+            .write(|w| w.ds().bits(0b0111).frxth().set_bit().txdmaen().set_bit());
+        spi.cr1
+            .write(|w| w.mstr().set_bit().ssm().set_bit().ssi().set_bit());
+
+        dma.cpar2.write(|w| w.pa().bits(&spi.dr as *const _ as u32));
+        dmamux.c1cr.write(|w| w.dmareq_id().bits(DMAREQ_SPI1_TX));
+    }
+    dma.ccr2
+        .write(|w| w.minc().set_bit().dir().set_bit().tcie().set_bit());
+
+    select(Device::Sensor);
+    SensorSpi {
+        _spi: spi,
+        _pins: pins,
+    }
+}
+
+fn regs() -> &'static stm32::spi1::RegisterBlock {
+    unsafe { &*stm32::SPI1::ptr() }
+}
+
+fn select(device: Device) {
+    if SELECTED.load(Ordering::Relaxed) == device as u8 {
+        return;
+    }
+    let spi = regs();
+    while spi.sr.read().bsy().bit_is_set() {}
+    let mode = device.mode();
+    spi.cr1.modify(|_, w| w.spe().clear_bit());
+    spi.cr1.modify(|_, w| {
+        unsafe { w.br().bits(device.baud_rate()) }
+            .cpol()
+            .bit(mode.polarity == Polarity::IdleHigh)
+            .cpha()
+            .bit(mode.phase == Phase::CaptureOnSecondTransition)
+            .spe()
+            .set_bit()
+    });
+    SELECTED.store(device as u8, Ordering::Relaxed);
+}
+
+/// Hands the bus back to the sensor once the display chip select is high,
+/// so the sensor finds it the way it left it.
+pub fn release() {
+    select(Device::Sensor);
+}
+
+fn exchange(byte: u8) -> u8 {
+    let spi = regs();
+    // 8 bit accesses, a 16 bit one would send two frames
+    let dr = &spi.dr as *const _ as *mut u8;
+    while spi.sr.read().txe().bit_is_clear() {}
+    unsafe { ptr::write_volatile(dr, byte) };
+    while spi.sr.read().rxne().bit_is_clear() {}
+    unsafe { ptr::read_volatile(dr) }
+}
+
+/// Blocking write for a few bytes, with the chip select of `device` low.
+pub fn write(device: Device, bytes: &[u8]) {
+    select(device);
+    for byte in bytes {
+        exchange(*byte);
+    }
+}
+
+/// Starts sending `bytes` by DMA, [`on_dma_interrupt`] reports the end.
+///
+/// # Safety
+///
+/// `bytes` must stay in place and unchanged until then.
+pub unsafe fn start_write(device: Device, bytes: &[u8]) {
+    select(device);
+    DMA_BUSY.store(true, Ordering::Relaxed);
+    let dma = &*stm32::DMA1::ptr();
+    dma.cmar2.write(|w| w.ma().bits(bytes.as_ptr() as u32));
+    dma.cndtr2.write(|w| w.ndt().bits(bytes.len() as u16));
+    dma.ccr2.modify(|_, w| w.en().set_bit());
+}
+
+/// Called from the DMA1 channel 2 interrupt, `true` once the bytes are out.
+pub fn on_dma_interrupt() -> bool {
+    let dma = unsafe { &*stm32::DMA1::ptr() };
+    if dma.isr.read().tcif2().bit_is_clear() {
+        return false;
+    }
+    dma.ifcr.write(|w| w.ctcif2().set_bit());
+    dma.ccr2.modify(|_, w| w.en().clear_bit());
+
+    // the last frame leaves the shift register after the transfer completes
+    let spi = regs();
+    while spi.sr.read().bsy().bit_is_set() {}
+    // what came back fills the receive FIFO, the sensor must not read it
+    let dr = &spi.dr as *const _ as *const u8;
+    while spi.sr.read().frlvl().bits() != 0 {
+        unsafe { ptr::read_volatile(dr) };
+    }
+    spi.sr.read();
+
+    DMA_BUSY.store(false, Ordering::Relaxed);
+    true
+}
+
+impl SensorSpi {
+    fn acquire(&mut self) {
+        // a page outlasting the sample period, wait it out
+        while DMA_BUSY.load(Ordering::Relaxed) {}
+        select(Device::Sensor);
+    }
+}
+
+impl spi::Transfer<u8> for SensorSpi {
+    type Error = Infallible;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Infallible> {
+        self.acquire();
+        for word in words.iter_mut() {
+            *word = exchange(*word);
+        }
+        Ok(words)
+    }
+}
+
+impl spi::Write<u8> for SensorSpi {
+    type Error = Infallible;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Infallible> {
+        self.acquire();
+        for word in words {
+            exchange(*word);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,28 @@
+use robo_core::step::StepRecorder;
+
+use crate::MotorState;
+
+/// 4K of RAM, steps up to a second keep the full `CONTROL_HZ` rate
+pub const STEP_LEN: usize = 1000;
+
+/// Step response test, recorded by the sampler and evaluated by the shell.
+pub struct StepTest {
+    /// Speed in rpm
+    pub recorder: StepRecorder<STEP_LEN>,
+    /// Signed duty of the step, clockwise is positive
+    pub duty: i32,
+    pub duration_ms: u32,
+    /// Motor state restored once the step is recorded
+    pub previous: MotorState,
+}
+
+impl StepTest {
+    pub const fn new() -> Self {
+        StepTest {
+            recorder: StepRecorder::new(),
+            duty: 0,
+            duration_ms: 0,
+            previous: MotorState::HardBrake,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use robo_core::telemetry::{self as proto, Channel, ChannelSet, Message, Sample, Snapshot};
+
+#[cfg(feature = "rtt-shell")]
+use rtt_target::UpChannel;
+
+use crate::uart;
+use crate::{MotorState, CONTROL_HZ};
+
+/// Channels this board can measure, there is no current sense on the Mx1508.
+pub const AVAILABLE: ChannelSet = ChannelSet::ALL.without(Channel::Current);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sink {
+    Uart,
+    #[cfg(feature = "rtt-shell")]
+    Rtt,
+}
+
+impl Sink {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sink::Uart => "uart",
+            #[cfg(feature = "rtt-shell")]
+            Sink::Rtt => "rtt",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Sink> {
+        match name {
+            "uart" => Some(Sink::Uart),
+            #[cfg(feature = "rtt-shell")]
+            "rtt" => Some(Sink::Rtt),
+            _ => None,
+        }
+    }
+}
+
+pub struct Telemetry {
+    pub enabled: bool,
+    pub channels: ChannelSet,
+    pub decimation: u16,
+    pub sink: Sink,
+    pub sent: u32,
+    pub dropped: u32,
+    countdown: u16,
+    seq: u16,
+    #[cfg(feature = "rtt-shell")]
+    rtt: UpChannel,
+}
+
+impl Telemetry {
+    pub fn new(#[cfg(feature = "rtt-shell")] rtt: UpChannel) -> Self {
+        Telemetry {
+            enabled: false,
+            channels: AVAILABLE,
+            // 100 Hz is what fits USART2 at 115200 baud with all channels
+            decimation: 10,
+            sink: Sink::Uart,
+            sent: 0,
+            dropped: 0,
+            countdown: 0,
+            seq: 0,
+            #[cfg(feature = "rtt-shell")]
+            rtt,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.enabled = true;
+        self.countdown = 0;
+        self.send_config();
+    }
+
+    pub fn stop(&mut self) {
+        self.enabled = false;
+    }
+
+    pub fn configure(&mut self, channels: ChannelSet, decimation: u16, sink: Sink) {
+        self.channels = channels;
+        self.decimation = decimation.max(1);
+        self.sink = sink;
+        if self.enabled {
+            self.countdown = 0;
+            self.send_config();
+        }
+    }
+
+    /// Called at `CONTROL_HZ`, sends every `decimation`-th snapshot, a frame
+    /// each, so `CONTROL_HZ` is also the highest rate.
+    pub fn sample(&mut self, snapshot: &Snapshot) {
+        if !self.enabled {
+            return;
+        }
+        if self.countdown > 0 {
+            self.countdown -= 1;
+            return;
+        }
+        self.countdown = self.decimation - 1;
+
+        let sample = Sample::new(self.seq, snapshot, self.channels);
+        self.seq = self.seq.wrapping_add(1);
+        self.send(&Message::Sample(sample));
+    }
+
+    fn send_config(&mut self) {
+        self.send(&Message::Config {
+            rate_hz: CONTROL_HZ,
+            decimation: self.decimation,
+            channels: self.channels,
+        });
+    }
+
+    /// Sends `message` now without counting it, false when the sink is full.
+    pub fn try_send(&mut self, message: &Message) -> bool {
+        let mut frame = [0u8; proto::MAX_FRAME];
+        match proto::encode(message, &mut frame) {
+            Ok(len) => match self.sink {
+                Sink::Uart => uart::write_frame(&frame[..len]),
+                #[cfg(feature = "rtt-shell")]
+                Sink::Rtt => self.rtt.write(&frame[..len]) == len,
+            },
+            Err(_) => false,
+        }
+    }
+
+    fn send(&mut self, message: &Message) {
+        if self.try_send(message) {
+            self.sent = self.sent.wrapping_add(1);
+        } else {
+            self.dropped = self.dropped.wrapping_add(1);
+            trace!(Telemetry, "frame dropped");
+        }
+    }
+}
+
+/// Signed duty as a fraction of `max_duty`, clockwise is positive.
+pub fn duty_fraction(state: MotorState, max_duty: u32) -> f32 {
+    match state {
+        MotorState::Cw(duty) => duty as f32 / max_duty as f32,
+        MotorState::Ccw(duty) => -(duty as f32) / max_duty as f32,
+        _ => 0.0,
+    }
+}
@@ -0,0 +1,228 @@
+//! Buffered USART2.
+//!
+//! Received bytes are moved into [`RX`] by the USART2 interrupt, written bytes
+//! are queued in [`TX`] and sent by DMA1 channel 1 in contiguous chunks, so a
+//! long shell reply never keeps the writer spinning on the data register.
+
+use core::cell::UnsafeCell;
+use core::convert::Infallible;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use cortex_m::interrupt;
+use embedded_hal::serial;
+use stm32g4xx_hal as hal;
+
+use hal::gpio::*;
+use hal::serial::Serial;
+use hal::stm32;
+
+pub type Usart2 = Serial<stm32::USART2, gpioa::PA2<Alternate<7>>, gpioa::PA3<Alternate<7>>>;
+
+// DMAMUX request line of USART2_TX
+const DMAREQ_USART2_TX: u8 = 27;
+
+pub static RX: Ring<128> = Ring::new();
+pub static TX: Ring<1024> = Ring::new();
+pub static STATS: Stats = Stats::new();
+
+static TX_BUSY: AtomicBool = AtomicBool::new(false);
+static TX_IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Stats {
+    pub rx_bytes: AtomicU32,
+    pub rx_overflow: AtomicU32,
+    pub rx_errors: AtomicU32,
+    pub tx_bytes: AtomicU32,
+}
+
+impl Stats {
+    const fn new() -> Self {
+        Stats {
+            rx_bytes: AtomicU32::new(0),
+            rx_overflow: AtomicU32::new(0),
+            rx_errors: AtomicU32::new(0),
+            tx_bytes: AtomicU32::new(0),
+        }
+    }
+}
+
+/// Byte ring with free running indices, `N` must be a power of two.
+///
+/// `head` is only advanced by the producer and `tail` only by the consumer.
+pub struct Ring<const N: usize> {
+    buffer: UnsafeCell<[u8; N]>,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+unsafe impl<const N: usize> Sync for Ring<N> {}
+
+impl<const N: usize> Ring<N> {
+    pub const fn new() -> Self {
+        Ring {
+            buffer: UnsafeCell::new([0; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn len(&self) -> usize {
+        self.head
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.tail.load(Ordering::Acquire))
+    }
+
+    pub fn push(&self, byte: u8) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        if head.wrapping_sub(self.tail.load(Ordering::Acquire)) == N {
+            return false;
+        }
+        unsafe { (*self.buffer.get())[head % N] = byte };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    pub fn pop(&self) -> Option<u8> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == self.head.load(Ordering::Acquire) {
+            return None;
+        }
+        let byte = unsafe { (*self.buffer.get())[tail % N] };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Some(byte)
+    }
+
+    /// Longest run of queued bytes that does not wrap around the buffer end.
+    fn contiguous(&self) -> &[u8] {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let start = tail % N;
+        let len = self.len().min(N - start);
+        unsafe { &(*self.buffer.get())[start..start + len] }
+    }
+
+    fn consume(&self, len: usize) {
+        let tail = self.tail.load(Ordering::Relaxed);
+        self.tail.store(tail.wrapping_add(len), Ordering::Release);
+    }
+}
+
+/// Routes USART2 TX requests to DMA1 channel 1.
+pub fn init_dma(dma: &stm32::DMA1, dmamux: &stm32::DMAMUX) {
+    unsafe {
+        let rcc = &*stm32::RCC::ptr();
+        rcc.ahb1enr
+            .modify(|_, w| w.dma1en().set_bit().dmamuxen().set_bit());
+
+        let usart = &*stm32::USART2::ptr();
+        dma.cpar1
+            .write(|w| w.pa().bits(&usart.tdr as *const _ as u32));
+        dmamux.c0cr.write(|w| w.dmareq_id().bits(DMAREQ_USART2_TX));
+        usart.cr3.modify(|_, w| w.dmat().set_bit());
+    }
+    dma.ccr1
+        .write(|w| w.minc().set_bit().dir().set_bit().tcie().set_bit());
+}
+
+/// Called from the USART2 interrupt.
+pub fn on_rx_interrupt(serial: &mut Usart2) {
+    loop {
+        match serial::Read::read(serial) {
+            Ok(byte) => {
+                STATS.rx_bytes.fetch_add(1, Ordering::Relaxed);
+                if !RX.push(byte) {
+                    STATS.rx_overflow.fetch_add(1, Ordering::Relaxed);
+                    debug!(Uart, "rx overflow");
+                }
+            }
+            Err(nb::Error::WouldBlock) => break,
+            Err(nb::Error::Other(_)) => {
+                STATS.rx_errors.fetch_add(1, Ordering::Relaxed);
+                debug!(Uart, "rx error");
+            }
+        }
+    }
+}
+
+/// Called from the DMA1 channel 1 interrupt, which must outrank every writer.
+pub fn on_dma_interrupt() {
+    let dma = unsafe { &*stm32::DMA1::ptr() };
+
+    if dma.isr.read().tcif1().bit_is_set() {
+        dma.ifcr.write(|w| w.ctcif1().set_bit());
+        dma.ccr1.modify(|_, w| w.en().clear_bit());
+        let sent = TX_IN_FLIGHT.swap(0, Ordering::Relaxed);
+        TX.consume(sent);
+        STATS.tx_bytes.fetch_add(sent as u32, Ordering::Relaxed);
+        TX_BUSY.store(false, Ordering::Relaxed);
+    }
+
+    if !TX_BUSY.load(Ordering::Relaxed) {
+        let chunk = TX.contiguous();
+        if !chunk.is_empty() {
+            TX_IN_FLIGHT.store(chunk.len(), Ordering::Relaxed);
+            TX_BUSY.store(true, Ordering::Relaxed);
+            unsafe {
+                dma.cmar1.write(|w| w.ma().bits(chunk.as_ptr() as u32));
+                dma.cndtr1.write(|w| w.ndt().bits(chunk.len() as u16));
+            }
+            dma.ccr1.modify(|_, w| w.en().set_bit());
+        }
+    }
+}
+
+fn kick_tx() {
+    if !TX_BUSY.load(Ordering::Relaxed) {
+        rtic::pend(stm32::Interrupt::DMA1_CH1);
+    }
+}
+
+/// Queues `bytes` only if all of them fit, so a frame is never cut short.
+pub fn write_frame(bytes: &[u8]) -> bool {
+    let queued = interrupt::free(|_| {
+        if TX.capacity() - TX.len() < bytes.len() {
+            return false;
+        }
+        bytes.iter().all(|byte| TX.push(*byte))
+    });
+    kick_tx();
+    queued
+}
+
+/// Shell side of the buffered USART2.
+pub struct BufferedSerial;
+
+impl serial::Read<u8> for BufferedSerial {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Infallible> {
+        RX.pop().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl serial::Write<u8> for BufferedSerial {
+    type Error = Infallible;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), Infallible> {
+        // Writers may run at different priorities
+        let queued = interrupt::free(|_| TX.push(word));
+        kick_tx();
+        if queued {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Infallible> {
+        if TX.len() == 0 {
+            Ok(())
+        } else {
+            kick_tx();
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
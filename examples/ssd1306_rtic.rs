@@ -54,6 +54,7 @@ mod app {
         info!("Init UART");
 
         let gpio_a = ctx.device.GPIOA.split(&mut rcc);
+        let gpio_b = ctx.device.GPIOB.split(&mut rcc);
         let tx = gpio_a.pa2.into_alternate();
         let rx = gpio_a.pa3.into_alternate();
 
@@ -68,12 +69,13 @@ mod app {
 
         info!("Init SPI");
 
-        // Setup spi i/o
+        // Setup spi i/o, PA9 and PA6 are the chip select and MISO of the
+        // TLE5012 sharing the bus
         let sck = gpio_a.pa5.into_alternate();
         let mosi = gpio_a.pa7.into_alternate();
-        let mut nss = gpio_a.pa9.into_push_pull_output();
+        let mut nss = gpio_b.pb12.into_push_pull_output();
         nss.set_high().ok();
-        let mut dc = gpio_a.pa6.into_push_pull_output();
+        let mut dc = gpio_b.pb14.into_push_pull_output();
         dc.set_high().ok();
 
         let spi = ctx.device.SPI1.spi(
@@ -15,9 +15,10 @@ use hal::serial::{Event::Rxne, FullConfig, Serial};
 use hal::stm32;
 use hal::syscfg::SysCfgExt;
 
-use dwt_systick_monotonic::DwtSystick;
+use dwt_systick_monotonic::{DwtSystick, ExtU32};
 
 use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use btoi::btoi;
 
@@ -25,8 +26,41 @@ use lexical_core::BUFFER_SIZE;
 
 use hal::time::RateExtU32;
 
+use robo_core::button::{Button, Gesture, Timing};
+use robo_core::led::{Layer, Led, Pattern, FULL};
+
 type LedType = Pwm<stm32::TIM2, C1, ComplementaryImpossible, ActiveHigh, ActiveHigh>;
 
+/// Rate the status LED is updated at
+const LED_HZ: u32 = 50;
+
+/// Set when a signal for `env` is dropped, shown as fault code 2.
+static DROPPED: AtomicBool = AtomicBool::new(false);
+
+fn now_ms() -> u32 {
+    app::monotonics::now().duration_since_epoch().to_millis() as u32
+}
+
+fn signal(sig: shell::EnvSignal) {
+    if app::env::spawn(sig).is_err() {
+        DROPPED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// PC13 with its gesture state, fed on both edges and at the deadline.
+pub struct UserButton {
+    pin: gpioc::PC13<Input<PullDown>>,
+    button: Button,
+}
+
+impl UserButton {
+    fn update(&mut self, now_ms: u32) -> Option<Gesture> {
+        self.pin.clear_interrupt_pending_bit();
+        let pressed = self.pin.is_high().unwrap_or(false);
+        self.button.update(pressed, now_ms)
+    }
+}
+
 mod shell {
     use super::*;
 
@@ -37,14 +71,14 @@ mod shell {
 
     pub const CMD_MAX_LEN: usize = 32;
 
-    pub type Autocomplete = StaticAutocomplete<7>;
+    pub type Autocomplete = StaticAutocomplete<8>;
     pub type History = LRUHistory<{ CMD_MAX_LEN }, 32>;
     pub type Uart = Serial<stm32::USART2, gpioa::PA2<Alternate<7>>, gpioa::PA3<Alternate<7>>>;
     pub type Shell = UShell<Uart, Autocomplete, History, { CMD_MAX_LEN }>;
 
     pub enum EnvSignal {
         Shell,
-        ButtonClick,
+        ButtonClick(Gesture),
     }
 
     pub type Env<'a> = super::app::env::SharedResources<'a>;
@@ -54,18 +88,30 @@ mod shell {
         pub fn on_signal(&mut self, shell: &mut Shell, sig: EnvSignal) -> EnvResult {
             match sig {
                 EnvSignal::Shell => shell.spin(self),
-                EnvSignal::ButtonClick => self.button_click(),
+                EnvSignal::ButtonClick(gesture) => self.button_click(shell, gesture),
             }
         }
 
-        fn button_click(&mut self) -> EnvResult {
-            let max_duty = self.led.lock(|pwm| pwm.get_max_duty());
-            let duty = self.led.lock(|pwm| pwm.get_duty());
-            if duty < max_duty / 2 {
-                self.led.lock(|pwm| pwm.set_duty(max_duty));
-            } else {
-                self.led.lock(|pwm| pwm.set_duty(0));
-            }
+        /// Click toggles the led, double click turns it full on, a long
+        /// press off and holding on brightens it in 10% steps.
+        fn button_click(&mut self, shell: &mut Shell, gesture: Gesture) -> EnvResult {
+            let duty = self.user_duty().unwrap_or(0);
+            let duty = match gesture {
+                Gesture::Short if duty < 50 => 100,
+                Gesture::Short => 0,
+                Gesture::Double => 100,
+                Gesture::Long => 0,
+                Gesture::Repeat => (duty / 10 * 10 + 10).min(100),
+            };
+            self.pwm_set_duty(duty);
+            write!(
+                shell,
+                "{0:}button {1:}: duty={2:}%{0:}",
+                CR,
+                gesture.name(),
+                duty
+            )?;
+            shell.write_str(SHELL_PROMPT)?;
             Ok(())
         }
 
@@ -83,18 +129,70 @@ mod shell {
         }
 
         fn status_cmd(&mut self, shell: &mut Shell) -> EnvResult {
-            let duty = self.pwm_get_duty();
-            if duty == 0 {
-                write!(shell, "{0:}Led disabled{0:}\r\n", CR)?;
-            } else {
-                write!(shell, "{0:}Led enabled: duty={1:}%{0:}\r\n", CR, duty)?;
+            match self.user_duty() {
+                Some(0) => write!(shell, "{0:}Led disabled{0:}\r\n", CR)?,
+                Some(duty) => write!(shell, "{0:}Led enabled: duty={1:}%{0:}\r\n", CR, duty)?,
+                None => {
+                    shell.write_str(CR)?;
+                    self.write_pattern(shell)?;
+                    write!(shell, "{0:}\r\n", CR)?;
+                }
             }
             Ok(())
         }
 
+        fn led_cmd(&mut self, shell: &mut Shell, args: &str) -> EnvResult {
+            let (sub, args) = args.split_once(' ').unwrap_or((args, ""));
+            match (sub, args.trim()) {
+                ("pattern", "") => {}
+                ("pattern", name) => match Pattern::parse(name) {
+                    Some(pattern) => {
+                        let now = now_ms();
+                        self.status_led
+                            .lock(|led| led.set(Layer::User, pattern, now));
+                    }
+                    None => {
+                        write!(shell, "{0:}unsupported pattern: \"{1:}\"{0:}", CR, name)?;
+                        return Ok(());
+                    }
+                },
+                // back to the status, faults included
+                ("clear", "") => self.status_led.lock(|led| {
+                    led.clear(Layer::User);
+                    led.clear(Layer::Fault);
+                }),
+                _ => {
+                    write!(shell, "{0:}usage: led pattern [name] | led clear{0:}", CR)?;
+                    return Ok(());
+                }
+            }
+            shell.write_str(CR)?;
+            self.write_pattern(shell)?;
+            shell.write_str(CR)?;
+            Ok(())
+        }
+
+        fn write_pattern(&mut self, shell: &mut Shell) -> EnvResult {
+            let active = self.status_led.lock(|led| led.active());
+            let (layer, pattern) = match active {
+                Some(active) => active,
+                None => {
+                    write!(shell, "Led pattern: none")?;
+                    return Ok(());
+                }
+            };
+            write!(shell, "Led pattern: {}", pattern.name())?;
+            match pattern {
+                Pattern::Steady(value) | Pattern::Code(value) => write!(shell, " {}", value)?,
+                _ => {}
+            }
+            write!(shell, " ({})", layer.name())?;
+            Ok(())
+        }
+
         fn off_cmd(&mut self, shell: &mut Shell) -> EnvResult {
-            let duty = self.pwm_get_duty();
-            if duty == 0 {
+            let duty = self.user_duty();
+            if duty == Some(0) {
                 write!(shell, "{0:}Led already off{0:}\r\n", CR)?;
             } else {
                 self.pwm_set_duty(0);
@@ -104,8 +202,8 @@ mod shell {
         }
 
         fn on_cmd(&mut self, shell: &mut Shell) -> EnvResult {
-            let duty = self.pwm_get_duty();
-            if duty != 0 {
+            let duty = self.user_duty();
+            if duty.map_or(false, |duty| duty != 0) {
                 write!(shell, "{0:}Led already on{0:}\r\n", CR)?;
             } else {
                 self.pwm_set_duty(100);
@@ -140,15 +238,21 @@ mod shell {
             Ok(())
         }
 
+        /// Steady duty on the user layer, over the status pattern.
         fn pwm_set_duty(&mut self, pwm_percentage: u32) {
-            let max_duty = self.led.lock(|pwm| pwm.get_max_duty());
-            let duty = max_duty * pwm_percentage / 100;
-            self.led.lock(|pwm| pwm.set_duty(duty));
+            let pattern = Pattern::Steady(pwm_percentage as u8);
+            let now = now_ms();
+            self.status_led
+                .lock(|led| led.set(Layer::User, pattern, now));
         }
 
-        fn pwm_get_duty(&mut self) -> u32 {
-            let max_duty = self.led.lock(|pwm| pwm.get_max_duty());
-            self.led.lock(|pwm| pwm.get_duty()) * 100 / max_duty
+        /// Duty set by the user, `None` while a pattern plays.
+        fn user_duty(&mut self) -> Option<u32> {
+            match self.status_led.lock(|led| led.get(Layer::User)) {
+                Some(Pattern::Steady(duty)) => Some(duty as u32),
+                Some(Pattern::Off) => Some(0),
+                _ => None,
+            }
         }
 
         fn help_cmd(&mut self, shell: &mut Shell, args: &str) -> EnvResult {
@@ -167,6 +271,7 @@ mod shell {
                 "status" => self.status_cmd(shell)?,
                 "on" => self.on_cmd(shell)?,
                 "off" => self.off_cmd(shell)?,
+                "led" => self.led_cmd(shell, args)?,
                 "float" => self.float_cmd(shell, args)?,
                 "help" => self.help_cmd(shell, args)?,
                 "" => shell.write_str(CR)?,
@@ -188,8 +293,9 @@ mod shell {
         }
     }
 
-    pub const AUTOCOMPLETE: Autocomplete =
-        StaticAutocomplete(["clear", "help", "off", "on", "pwm", "status", "float"]);
+    pub const AUTOCOMPLETE: Autocomplete = StaticAutocomplete([
+        "clear", "help", "led", "off", "on", "pwm", "status", "float",
+    ]);
 
     const SHELL_PROMPT: &str = "#> ";
     const CR: &str = "\r\n";
@@ -201,6 +307,7 @@ COMMANDS:\r\n\
 \ton        Enable led\r\n\
 \toff       Disable led\r\n\
 \tpwm       Set pwm value\r\n\
+\tled       Play a pattern: led pattern [name], led clear\r\n\
 \tstatus    Get led status\r\n\
 \tfloat     Float parse test\r\n\
 \tclear     Clear screen\r\n\
@@ -211,7 +318,6 @@ COMMANDS:\r\n\
 #[rtic::app(device = hal::stm32, peripherals = true, dispatchers = [USART1])]
 mod app {
     use super::*;
-    type ButtonType = gpioc::PC13<Input<PullDown>>;
 
     // Default system clocked by HSI (16 MHz)
     const SYSFREQ: u32 = 16_000_000;
@@ -220,12 +326,14 @@ mod app {
 
     #[shared]
     struct Shared {
-        led: LedType,
+        /// Heartbeat, overridden from the shell or the button and by faults
+        status_led: Led,
+        button: UserButton,
     }
 
     #[local]
     struct Local {
-        button: ButtonType,
+        led: LedType,
         shell: shell::Shell,
     }
 
@@ -247,10 +355,10 @@ mod app {
         let gpioa = ctx.device.GPIOA.split(&mut rcc);
         let gpioc = ctx.device.GPIOC.split(&mut rcc);
 
-        // button
+        // button, both edges
         let mut button = gpioc.pc13.into_pull_down_input();
         button.make_interrupt_source(&mut syscfg);
-        button.trigger_on_edge(&mut exti, SignalEdge::Rising);
+        button.trigger_on_edge(&mut exti, SignalEdge::RisingFalling);
         button.enable_interrupt(&mut exti);
         // led
         let mut led = ctx
@@ -277,35 +385,78 @@ mod app {
 
         writeln!(shell, "\r\nHello from USART2\r\n").unwrap();
 
+        let mut status_led = Led::new();
+        status_led.set(Layer::Status, Pattern::Heartbeat, 0);
+        led_tick::spawn().ok();
+
         (
             Shared {
                 // Initialization of shared resources go here
-                led,
+                status_led,
+                button: UserButton {
+                    pin: button,
+                    button: Button::new(Timing::DEFAULT),
+                },
             },
             Local {
                 // Initialization of local resources go here
-                button,
+                led,
                 shell,
             },
             init::Monotonics(mono),
         )
     }
 
-    #[task(binds = EXTI15_10, local = [button])]
-    fn button_click(ctx: button_click::Context) {
-        ctx.local.button.clear_interrupt_pending_bit();
-        env::spawn(shell::EnvSignal::ButtonClick).ok();
+    #[task(binds = EXTI15_10, shared = [button])]
+    fn button_edge(mut ctx: button_edge::Context) {
+        ctx.shared.button.lock(update_button);
+    }
+
+    #[task(priority = 2, capacity = 4, shared = [button])]
+    fn button_poll(mut ctx: button_poll::Context) {
+        ctx.shared.button.lock(update_button);
+    }
+
+    /// Feeds the button, hands gestures to `env` and polls at the deadline.
+    fn update_button(button: &mut UserButton) {
+        let now = monotonics::now();
+        let now_ms = now.duration_since_epoch().to_millis() as u32;
+        if let Some(gesture) = button.update(now_ms) {
+            signal(shell::EnvSignal::ButtonClick(gesture));
+        }
+        if let Some(delay) = button.button.poll_deadline(now_ms) {
+            button_poll::spawn_at(now + delay.millis()).ok();
+        }
     }
 
     #[task(binds = USART2, priority = 1)]
     fn serial_callback(_: serial_callback::Context) {
-        env::spawn(shell::EnvSignal::Shell).ok();
+        signal(shell::EnvSignal::Shell);
     }
 
-    #[task(priority = 2, capacity = 8, local = [shell], shared = [led])]
+    #[task(priority = 2, capacity = 8, local = [shell], shared = [status_led])]
     fn env(ctx: env::Context, sig: shell::EnvSignal) {
         let mut env = ctx.shared;
-        env.on_signal(ctx.local.shell, sig).ok();
+        if env.on_signal(ctx.local.shell, sig).is_err() {
+            let now = now_ms();
+            env.status_led
+                .lock(|led| led.set(Layer::Fault, Pattern::FastBlink, now));
+        }
+    }
+
+    #[task(priority = 2, local = [led], shared = [status_led])]
+    fn led_tick(mut ctx: led_tick::Context) {
+        let now = monotonics::now();
+        let now_ms = now.duration_since_epoch().to_millis() as u32;
+        let level = ctx.shared.status_led.lock(|led| {
+            if DROPPED.swap(false, Ordering::Relaxed) {
+                led.set(Layer::Fault, Pattern::Code(2), now_ms);
+            }
+            led.level(now_ms)
+        });
+        let pwm = ctx.local.led;
+        pwm.set_duty(pwm.get_max_duty() * level as u32 / FULL as u32);
+        led_tick::spawn_at(now + (1_000 / LED_HZ).millis()).ok();
     }
 
     #[idle]
@@ -2,6 +2,9 @@ MEMORY
 {
   /* NOTE K = KiBi = 1024 bytes */
   /* TODO Adjust these memory regions to match your device memory layout */
-  FLASH : ORIGIN = 0x8000000, LENGTH = 128K 
-  RAM : ORIGIN = 0x20000000, LENGTH = 32K
-}
\ No newline at end of file
+  FLASH : ORIGIN = 0x8000000, LENGTH = 126K 
+  /* Parameter store of motor_drive_rtic, one 2K page */
+  PARAMS : ORIGIN = 0x801F800, LENGTH = 2K
+  /* SRAM1 and SRAM2 of the G474RE, contiguous; CCM SRAM is left out */
+  RAM : ORIGIN = 0x20000000, LENGTH = 96K
+}
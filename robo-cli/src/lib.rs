@@ -0,0 +1,157 @@
+//! Decoding of the firmware telemetry stream into CSV or JSON lines.
+
+use std::io::{self, Read, Write};
+
+use robo_core::telemetry::{self, ChannelSet, Decoder, Message, Sample, MAX_FRAME};
+use serde_json::{json, Map, Value};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// One header line per channel set, then one row per sample
+    Csv,
+    /// One object per message
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub configs: u32,
+    pub samples: u32,
+    /// Text or noise between frames, like shell output on the same port
+    pub skipped: u32,
+    /// Frames with a bad CRC or payload
+    pub errors: u32,
+    /// Samples missing according to the sequence numbers
+    pub lost: u32,
+}
+
+pub struct Exporter<W: Write> {
+    out: W,
+    format: Format,
+    header: Option<ChannelSet>,
+    last_seq: Option<u16>,
+    stats: Stats,
+}
+
+impl<W: Write> Exporter<W> {
+    pub fn new(out: W, format: Format) -> Self {
+        Exporter {
+            out,
+            format,
+            header: None,
+            last_seq: None,
+            stats: Stats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    pub fn message(&mut self, message: &Message) -> io::Result<()> {
+        match message {
+            Message::Config {
+                rate_hz,
+                decimation,
+                channels,
+            } => {
+                self.stats.configs += 1;
+                // sent when the stream starts, the firmware may have been
+                // reset since the last sample
+                self.last_seq = None;
+                if self.format == Format::Json {
+                    let names: Vec<_> = channels.iter().map(|c| c.name()).collect();
+                    let config = json!({
+                        "config": {
+                            "rate_hz": rate_hz,
+                            "decimation": decimation,
+                            "channels": names,
+                        }
+                    });
+                    writeln!(self.out, "{}", config)?;
+                }
+                Ok(())
+            }
+            Message::Sample(sample) => {
+                self.stats.samples += 1;
+                if let Some(last) = self.last_seq {
+                    self.stats.lost += sample.seq.wrapping_sub(last).wrapping_sub(1) as u32;
+                }
+                self.last_seq = Some(sample.seq);
+                match self.format {
+                    Format::Csv => self.csv_row(sample),
+                    Format::Json => self.json_line(sample),
+                }
+            }
+        }
+    }
+
+    pub fn error(&mut self, error: telemetry::Error) {
+        match error {
+            telemetry::Error::Cobs | telemetry::Error::Overflow => self.stats.skipped += 1,
+            _ => self.stats.errors += 1,
+        }
+    }
+
+    fn csv_row(&mut self, sample: &Sample) -> io::Result<()> {
+        if self.header != Some(sample.channels) {
+            write!(self.out, "seq,timestamp_us")?;
+            for channel in sample.channels.iter() {
+                write!(self.out, ",{}", channel.name())?;
+            }
+            writeln!(self.out)?;
+            self.header = Some(sample.channels);
+        }
+
+        write!(self.out, "{},{}", sample.seq, sample.timestamp_us)?;
+        for channel in sample.channels.iter() {
+            match sample.get(channel) {
+                Some(value) if value.is_finite() => write!(self.out, ",{}", value)?,
+                _ => write!(self.out, ",")?,
+            }
+        }
+        writeln!(self.out)
+    }
+
+    fn json_line(&mut self, sample: &Sample) -> io::Result<()> {
+        let mut object = Map::new();
+        object.insert("seq".into(), sample.seq.into());
+        object.insert("timestamp_us".into(), sample.timestamp_us.into());
+        for channel in sample.channels.iter() {
+            // NaN and infinity become null
+            let value = sample.get(channel).map_or(Value::Null, Value::from);
+            object.insert(channel.name().into(), value);
+        }
+        writeln!(self.out, "{}", Value::Object(object))
+    }
+}
+
+/// Exports every frame of `input` until it ends.
+///
+/// Text and noise between frames is skipped, so the stream may share the
+/// serial port with the shell.
+pub fn export<R: Read, W: Write>(mut input: R, exporter: &mut Exporter<W>) -> io::Result<Stats> {
+    let mut decoder = Decoder::<MAX_FRAME>::new();
+    let mut buffer = [0u8; 256];
+    loop {
+        let len = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for byte in &buffer[..len] {
+            match decoder.feed(*byte) {
+                Some(Ok(message)) => exporter.message(&message)?,
+                Some(Err(error)) => exporter.error(error),
+                None => {}
+            }
+        }
+    }
+    exporter.out.flush()?;
+    Ok(exporter.stats)
+}
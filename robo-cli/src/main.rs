@@ -0,0 +1,91 @@
+use std::fs::File;
+use std::io::{self, BufWriter, LineWriter, Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::Parser;
+use robo_cli::{export, Exporter, Format};
+
+/// Decodes the motor_drive_rtic telemetry stream.
+///
+/// Enable it on the board with `telemetry on` and read the same port, the
+/// shell replies in between are skipped.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Recorded stream, stdin when missing or `-`
+    input: Option<PathBuf>,
+
+    /// Read from a serial device instead, like /dev/ttyACM0
+    #[arg(short, long, conflicts_with = "input")]
+    serial: Option<String>,
+
+    #[arg(short, long, default_value_t = 115_200)]
+    baud: u32,
+
+    #[arg(short, long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+
+    /// Output file, stdout by default
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+/// Serial port that waits for data instead of ending at a read timeout.
+struct Follow(Box<dyn serialport::SerialPort>);
+
+impl Read for Follow {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.0.read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                result => return result,
+            }
+        }
+    }
+}
+
+fn run(args: Args) -> io::Result<()> {
+    let (input, live): (Box<dyn Read>, bool) = match &args.serial {
+        Some(path) => {
+            let port = serialport::new(path, args.baud)
+                .timeout(Duration::from_millis(500))
+                .open()?;
+            (Box::new(Follow(port)), true)
+        }
+        None => match &args.input {
+            Some(path) if path.as_os_str() != "-" => (Box::new(File::open(path)?), false),
+            _ => (Box::new(io::stdin().lock()), false),
+        },
+    };
+
+    let output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    // a live stream ends with Ctrl-C, so nothing may stay buffered
+    let output: Box<dyn Write> = if live {
+        Box::new(LineWriter::new(output))
+    } else {
+        Box::new(BufWriter::new(output))
+    };
+
+    let mut exporter = Exporter::new(output, args.format);
+    let stats = export(input, &mut exporter)?;
+    eprintln!(
+        "{} samples, {} lost, {} bad frames, {} skipped",
+        stats.samples, stats.lost, stats.errors, stats.skipped
+    );
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("robo-cli: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
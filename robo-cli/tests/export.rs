@@ -0,0 +1,133 @@
+use std::io::{self, Read};
+
+use robo_cli::{export, Exporter, Format, Stats};
+use robo_core::telemetry::{
+    encode, ChannelSet, Message, Sample, Snapshot, CHANNEL_COUNT, MAX_FRAME,
+};
+
+// Stream of `telemetry on` then `telemetry ch angle,speed` and
+// `telemetry div 100`, with shell replies in between and the CRC of sample 5
+// corrupted.
+const SESSION: &[u8] = include_bytes!("data/session.bin");
+
+fn run(input: impl Read, format: Format) -> (String, Stats) {
+    let mut exporter = Exporter::new(Vec::new(), format);
+    let stats = export(input, &mut exporter).unwrap();
+    (String::from_utf8(exporter.into_inner()).unwrap(), stats)
+}
+
+fn frame(message: &Message) -> Vec<u8> {
+    let mut frame = [0u8; MAX_FRAME];
+    let len = encode(message, &mut frame).unwrap();
+    frame[..len].to_vec()
+}
+
+fn sample(seq: u16, channels: &str, values: [f32; CHANNEL_COUNT]) -> Vec<u8> {
+    let snapshot = Snapshot {
+        timestamp_us: seq as u32 * 1000,
+        values,
+    };
+    let channels = ChannelSet::parse(channels).unwrap();
+    frame(&Message::Sample(Sample::new(seq, &snapshot, channels)))
+}
+
+/// Hands out one byte per read, like a slow serial port.
+struct Trickle<'a>(&'a [u8]);
+
+impl Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.split_first() {
+            Some((byte, rest)) if !buf.is_empty() => {
+                buf[0] = *byte;
+                self.0 = rest;
+                Ok(1)
+            }
+            _ => Ok(0),
+        }
+    }
+}
+
+#[test]
+fn session_to_csv() {
+    let (csv, stats) = run(SESSION, Format::Csv);
+    assert_eq!(csv, include_str!("data/session.csv"));
+    assert_eq!(
+        stats,
+        Stats {
+            configs: 2,
+            samples: 12,
+            skipped: 2,
+            errors: 1,
+            lost: 1,
+        }
+    );
+}
+
+#[test]
+fn session_to_json() {
+    let (json, _) = run(SESSION, Format::Json);
+    assert_eq!(json, include_str!("data/session.jsonl"));
+}
+
+#[test]
+fn frames_split_across_reads() {
+    let (whole, _) = run(SESSION, Format::Csv);
+    let (trickled, stats) = run(Trickle(SESSION), Format::Csv);
+    assert_eq!(trickled, whole);
+    assert_eq!(stats.samples, 12);
+}
+
+#[test]
+fn resync_after_noise() {
+    let mut stream = vec![0x55; 3 * MAX_FRAME];
+    stream.extend(sample(1, "angle", [1.5, 0.0, 0.0, 0.0, 0.0, 0.0]));
+    stream.extend_from_slice(b"\r\n#> help\r\n");
+    stream.extend(sample(2, "angle", [2.5, 0.0, 0.0, 0.0, 0.0, 0.0]));
+
+    let (csv, stats) = run(&stream[..], Format::Csv);
+    assert_eq!(csv, "seq,timestamp_us,angle\n1,1000,1.5\n2,2000,2.5\n");
+    assert_eq!(stats.samples, 2);
+    assert_eq!(stats.skipped, 2);
+    assert_eq!(stats.errors, 0);
+}
+
+#[test]
+fn truncated_frame_at_end() {
+    let mut stream = sample(1, "angle", [1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+    let last = sample(2, "angle", [2.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+    stream.extend_from_slice(&last[..last.len() / 2]);
+
+    let (csv, stats) = run(&stream[..], Format::Csv);
+    assert_eq!(csv, "seq,timestamp_us,angle\n1,1000,1\n");
+    assert_eq!(stats.samples, 1);
+}
+
+#[test]
+fn lost_samples_across_wrap() {
+    let mut stream = Vec::new();
+    for seq in [65534, 65535, 1, 2] {
+        stream.extend(sample(seq, "speed", [0.0; CHANNEL_COUNT]));
+    }
+
+    let (_, stats) = run(&stream[..], Format::Json);
+    assert_eq!(stats.samples, 4);
+    assert_eq!(stats.lost, 1);
+}
+
+#[test]
+fn config_restarts_sequence() {
+    let mut stream = sample(500, "duty", [0.0; CHANNEL_COUNT]);
+    stream.extend(frame(&Message::Config {
+        rate_hz: 1000,
+        decimation: 1,
+        channels: ChannelSet::parse("duty").unwrap(),
+    }));
+    stream.extend(sample(0, "duty", [0.0; CHANNEL_COUNT]));
+
+    let (json, stats) = run(&stream[..], Format::Json);
+    assert_eq!(stats.lost, 0);
+    assert_eq!(
+        json.lines().nth(1).unwrap(),
+        r#"{"config":{"rate_hz":1000,"decimation":1,"channels":["duty"]}}"#
+    );
+}
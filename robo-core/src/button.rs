@@ -0,0 +1,223 @@
+//! Push button gestures from raw, bouncing levels.
+//!
+//! [`Button::update`] takes the raw level with a millisecond timestamp,
+//! on every edge interrupt and again at [`Button::deadline`], the only time
+//! something can happen without an edge. [`Button::poll_deadline`] tells
+//! when to schedule that poll. Timestamps wrap like a `u32` millisecond
+//! counter does.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Gesture {
+    /// Released before `long_ms`, with no second press within `double_ms`
+    Short,
+    /// Held for `long_ms`, reported while still held
+    Long,
+    /// Second short press within `double_ms` of the first release
+    Double,
+    /// Every `repeat_ms` while held after a `Long`
+    Repeat,
+}
+
+impl Gesture {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Gesture::Short => "short",
+            Gesture::Long => "long",
+            Gesture::Double => "double",
+            Gesture::Repeat => "repeat",
+        }
+    }
+}
+
+/// Durations in milliseconds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Timing {
+    /// Time a level has to be stable for
+    pub debounce_ms: u32,
+    pub long_ms: u32,
+    /// Zero reports every short press right away
+    pub double_ms: u32,
+    /// Zero disables repeats
+    pub repeat_ms: u32,
+}
+
+impl Timing {
+    pub const DEFAULT: Timing = Timing {
+        debounce_ms: 20,
+        long_ms: 600,
+        double_ms: 250,
+        repeat_ms: 150,
+    };
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    Idle,
+    /// `long` once `Long` was reported, `next` is then the next repeat
+    Pressed {
+        since: u32,
+        long: bool,
+        next: u32,
+    },
+    /// Released after a short press, waiting for a second one
+    Released {
+        at: u32,
+    },
+    SecondPress {
+        since: u32,
+    },
+}
+
+pub struct Button {
+    timing: Timing,
+    // debounced level
+    pressed: bool,
+    raw: bool,
+    raw_since: u32,
+    state: State,
+    // deadline a poll is already scheduled for
+    scheduled: Option<u32>,
+}
+
+impl Button {
+    pub const fn new(timing: Timing) -> Self {
+        Button {
+            timing,
+            pressed: false,
+            raw: false,
+            raw_since: 0,
+            state: State::Idle,
+            scheduled: None,
+        }
+    }
+
+    /// Debounced level.
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Feeds the raw level, at most one gesture per call.
+    ///
+    /// When gestures pile up, the deadline is `now` so the caller comes
+    /// right back for the next one.
+    pub fn update(&mut self, raw: bool, now: u32) -> Option<Gesture> {
+        if raw != self.raw {
+            self.raw = raw;
+            self.raw_since = now;
+        }
+        if self.raw != self.pressed && elapsed(self.raw_since, now) >= self.timing.debounce_ms {
+            self.pressed = self.raw;
+            // stable since the last bounce
+            let at = self.raw_since;
+            if let Some(gesture) = self.edge(at) {
+                return Some(gesture);
+            }
+        }
+        self.timeout(now)
+    }
+
+    /// When [`update`](Self::update) has to be called without an edge.
+    pub fn deadline(&self) -> Option<u32> {
+        let debounce = (self.raw != self.pressed)
+            .then(|| self.raw_since.wrapping_add(self.timing.debounce_ms));
+        let timeout = match self.state {
+            State::Idle => None,
+            State::Pressed { since, long, next } => match long {
+                false => Some(since.wrapping_add(self.timing.long_ms)),
+                true => (self.timing.repeat_ms > 0).then_some(next),
+            },
+            State::Released { at } => Some(at.wrapping_add(self.timing.double_ms)),
+            State::SecondPress { since } => Some(since.wrapping_add(self.timing.long_ms)),
+        };
+        match (debounce, timeout) {
+            (Some(a), Some(b)) => Some(if b.wrapping_sub(a) as i32 > 0 { a } else { b }),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Milliseconds from `now` to poll [`update`](Self::update) at, zero
+    /// for a deadline already passed.
+    ///
+    /// `None` when there is no deadline or a poll for it is still to come,
+    /// so the edge interrupt and the poll do not schedule it twice.
+    pub fn poll_deadline(&mut self, now: u32) -> Option<u32> {
+        let deadline = self.deadline()?;
+        let delay = (deadline.wrapping_sub(now) as i32).max(0) as u32;
+        if self.scheduled == Some(deadline) && delay > 0 {
+            return None;
+        }
+        self.scheduled = Some(deadline);
+        Some(delay)
+    }
+
+    fn edge(&mut self, at: u32) -> Option<Gesture> {
+        let (state, gesture) = match (self.state, self.pressed) {
+            (State::Idle, true) => (
+                State::Pressed {
+                    since: at,
+                    long: false,
+                    next: 0,
+                },
+                None,
+            ),
+            (State::Pressed { long: true, .. }, false) => (State::Idle, None),
+            (State::Pressed { .. }, false) if self.timing.double_ms == 0 => {
+                (State::Idle, Some(Gesture::Short))
+            }
+            (State::Pressed { .. }, false) => (State::Released { at }, None),
+            (State::Released { .. }, true) => (State::SecondPress { since: at }, None),
+            (State::SecondPress { .. }, false) => (State::Idle, Some(Gesture::Double)),
+            // a level the state already agrees with
+            (state, _) => (state, None),
+        };
+        self.state = state;
+        gesture
+    }
+
+    fn timeout(&mut self, now: u32) -> Option<Gesture> {
+        let timing = self.timing;
+        let (state, gesture) = match self.state {
+            State::Pressed {
+                since, long: false, ..
+            } if elapsed(since, now) >= timing.long_ms => (
+                State::Pressed {
+                    since,
+                    long: true,
+                    next: since.wrapping_add(timing.long_ms + timing.repeat_ms),
+                },
+                Gesture::Long,
+            ),
+            State::Pressed {
+                since,
+                long: true,
+                next,
+            } if timing.repeat_ms > 0 && elapsed(next, now) as i32 >= 0 => (
+                State::Pressed {
+                    since,
+                    long: true,
+                    next: next.wrapping_add(timing.repeat_ms),
+                },
+                Gesture::Repeat,
+            ),
+            State::Released { at } if elapsed(at, now) >= timing.double_ms => {
+                (State::Idle, Gesture::Short)
+            }
+            // a click followed by a long press, the long press comes next
+            State::SecondPress { since } if elapsed(since, now) >= timing.long_ms => (
+                State::Pressed {
+                    since,
+                    long: false,
+                    next: 0,
+                },
+                Gesture::Short,
+            ),
+            _ => return None,
+        };
+        self.state = state;
+        Some(gesture)
+    }
+}
+
+fn elapsed(since: u32, now: u32) -> u32 {
+    now.wrapping_sub(since)
+}
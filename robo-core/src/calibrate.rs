@@ -0,0 +1,272 @@
+//! Angle sensor offset and error calibration.
+//!
+//! A magnet off the shaft axis or tilted against the sensor bends the angle
+//! by an error that repeats every turn. [`Calibration`] spins the motor
+//! slowly for a turn each way and takes the speed as constant over the turn,
+//! what the angle strays from that straight line is the error. It is kept as
+//! the first [`HARMONICS`] of a Fourier series in an [`AngleCorrection`],
+//! together with the mechanical zero: the shaft position the run started at.
+//!
+//! Both directions are averaged so the sensor delay, which leads the angle
+//! one way and lags it the other, cancels out.
+
+use core::f32::consts::PI;
+
+use micromath::F32Ext;
+
+use crate::characterize::SweepStep;
+use crate::sensor;
+
+/// Harmonics of the angle error kept, eccentricity shows in the first and
+/// a tilted magnet in the second.
+pub const HARMONICS: usize = 4;
+
+/// Slots of a turn the error is averaged in before fitting.
+pub const BINS: usize = 32;
+
+/// Angle error and zero of a sensor, stored with the parameters.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct AngleCorrection {
+    /// Corrected sensor angle of the mechanical zero, radians
+    pub zero: f32,
+    /// Error coefficients of `cos(k θ)` and `sin(k θ)`, radians, `k` from 1
+    pub cos: [f32; HARMONICS],
+    pub sin: [f32; HARMONICS],
+}
+
+impl AngleCorrection {
+    /// Angles as the sensor reads them.
+    pub const NONE: AngleCorrection = AngleCorrection {
+        zero: 0.0,
+        cos: [0.0; HARMONICS],
+        sin: [0.0; HARMONICS],
+    };
+
+    /// Error of a sensor angle in radians.
+    pub fn error(&self, angle: f32) -> f32 {
+        let mut error = 0.0;
+        for k in 0..HARMONICS {
+            let phase = angle * (k + 1) as f32;
+            error += self.cos[k] * F32Ext::cos(phase) + self.sin[k] * F32Ext::sin(phase);
+        }
+        error
+    }
+
+    /// Shaft angle from the mechanical zero for a sensor angle, -π up to π.
+    pub fn apply(&self, angle: f32) -> f32 {
+        sensor::wrap(angle - self.error(angle) - self.zero)
+    }
+
+    /// The same error with the mechanical zero at sensor angle `angle`.
+    pub fn with_zero_at(&self, angle: f32) -> AngleCorrection {
+        AngleCorrection {
+            zero: sensor::wrap(angle - self.error(angle)),
+            ..*self
+        }
+    }
+
+    /// Largest error over a turn, radians.
+    pub fn peak_error(&self) -> f32 {
+        (0..BINS)
+            .map(|bin| self.error(bin_angle(bin)).abs())
+            .fold(0.0, f32::max)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Bin {
+    count: u32,
+    /// Sums of the sample ticks and the unwrapped angles
+    ticks: f32,
+    angles: f32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Phase {
+    /// Coming to a stop before the other direction
+    Stop,
+    /// Getting up to speed
+    Settle,
+    Record,
+}
+
+pub struct Calibration {
+    settle_ticks: u32,
+    timeout_ticks: u32,
+    running: bool,
+    // 0 is clockwise, 1 counter-clockwise
+    direction: usize,
+    phase: Phase,
+    tick: u32,
+    duty: f32,
+    /// Sensor angle the run started at, the mechanical zero
+    start: f32,
+    last: f32,
+    /// Unwrapped travel of the recorded turn
+    travel: f32,
+    /// Radians per tick of each direction, NaN if it never made a turn
+    speeds: [f32; 2],
+    bins: [[Bin; BINS]; 2],
+}
+
+impl Calibration {
+    /// A turn has to fit in `timeout_ticks` or the run fails.
+    pub const fn new(settle_ticks: u32, timeout_ticks: u32) -> Self {
+        Calibration {
+            settle_ticks,
+            timeout_ticks,
+            running: false,
+            direction: 0,
+            phase: Phase::Settle,
+            tick: 0,
+            duty: 0.0,
+            start: 0.0,
+            last: 0.0,
+            travel: 0.0,
+            speeds: [f32::NAN; 2],
+            bins: [[Bin {
+                count: 0,
+                ticks: 0.0,
+                angles: 0.0,
+            }; BINS]; 2],
+        }
+    }
+
+    /// Starts at uncorrected sensor angle `angle`, turning at duty fraction
+    /// `duty`. The caller applies [`duty`](Self::duty).
+    pub fn start(&mut self, angle: f32, duty: f32) {
+        self.running = true;
+        self.direction = 0;
+        self.duty = duty.abs();
+        self.start = angle;
+        self.speeds = [f32::NAN; 2];
+        self.bins = [[Bin::default(); BINS]; 2];
+        self.begin(Phase::Settle);
+    }
+
+    /// Ends a run, [`tick`](Self::tick) hands out no duty after this so the
+    /// caller stops the motor.
+    pub fn cancel(&mut self) {
+        self.running = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Signed duty fraction of the current direction.
+    pub fn duty(&self) -> f32 {
+        if self.direction == 0 {
+            self.duty
+        } else {
+            -self.duty
+        }
+    }
+
+    /// Direction being recorded and the part of its turn done.
+    pub fn progress(&self) -> (usize, f32) {
+        (self.direction, (self.travel.abs() / (2.0 * PI)).min(1.0))
+    }
+
+    /// Feeds the sensor angle of one control tick, uncorrected, NaN for a
+    /// failed read.
+    pub fn tick(&mut self, angle: f32) -> SweepStep {
+        if !self.running {
+            return SweepStep::Hold;
+        }
+
+        self.tick += 1;
+        match self.phase {
+            Phase::Stop => {
+                if self.tick < self.settle_ticks {
+                    return SweepStep::Hold;
+                }
+                self.begin(Phase::Settle);
+                return SweepStep::Duty(self.duty());
+            }
+            Phase::Settle => {
+                if self.tick < self.settle_ticks || angle.is_nan() {
+                    return SweepStep::Hold;
+                }
+                self.begin(Phase::Record);
+                self.last = angle;
+                self.record(angle);
+                return SweepStep::Hold;
+            }
+            Phase::Record => {}
+        }
+
+        if !angle.is_nan() {
+            self.travel += sensor::wrap(angle - self.last);
+            self.last = angle;
+            self.record(angle);
+        }
+        if self.travel.abs() >= 2.0 * PI {
+            self.speeds[self.direction] = self.travel / self.tick as f32;
+        } else if self.tick < self.timeout_ticks {
+            return SweepStep::Hold;
+        }
+
+        if self.direction == 0 && !self.speeds[0].is_nan() {
+            self.direction = 1;
+            self.begin(Phase::Stop);
+            SweepStep::Duty(0.0)
+        } else {
+            // done, or stalled short of a turn
+            self.running = false;
+            SweepStep::Done
+        }
+    }
+
+    fn begin(&mut self, phase: Phase) {
+        self.phase = phase;
+        self.tick = 0;
+        self.travel = 0.0;
+    }
+
+    fn record(&mut self, angle: f32) {
+        let bin = &mut self.bins[self.direction][bin_of(angle)];
+        bin.count += 1;
+        bin.ticks += self.tick as f32;
+        bin.angles += self.travel;
+    }
+
+    /// Correction from a finished run, `None` if the motor did not make a
+    /// turn each way.
+    pub fn result(&self) -> Option<AngleCorrection> {
+        // error plus an offset per direction in every bin
+        let mut offsets = [[0.0; BINS]; 2];
+        for ((offsets, speed), bins) in offsets.iter_mut().zip(self.speeds).zip(&self.bins) {
+            if speed.is_nan() || bins.iter().any(|bin| bin.count == 0) {
+                return None;
+            }
+            for (offset, bin) in offsets.iter_mut().zip(bins.iter()) {
+                *offset = (bin.angles - speed * bin.ticks) / bin.count as f32;
+            }
+            let mean = offsets.iter().sum::<f32>() / BINS as f32;
+            offsets.iter_mut().for_each(|offset| *offset -= mean);
+        }
+
+        let mut correction = AngleCorrection::NONE;
+        for (bin, (cw, ccw)) in offsets[0].iter().zip(&offsets[1]).enumerate() {
+            let error = (cw + ccw) / 2.0;
+            let angle = bin_angle(bin);
+            for k in 0..HARMONICS {
+                let phase = angle * (k + 1) as f32;
+                correction.cos[k] += error * F32Ext::cos(phase) * 2.0 / BINS as f32;
+                correction.sin[k] += error * F32Ext::sin(phase) * 2.0 / BINS as f32;
+            }
+        }
+        Some(correction.with_zero_at(self.start))
+    }
+}
+
+fn bin_of(angle: f32) -> usize {
+    (((angle + PI) / (2.0 * PI) * BINS as f32) as usize).min(BINS - 1)
+}
+
+/// Middle of `bin`.
+fn bin_angle(bin: usize) -> f32 {
+    -PI + (bin as f32 + 0.5) * 2.0 * PI / BINS as f32
+}
@@ -0,0 +1,237 @@
+//! Single shot triggered capture of telemetry channels.
+//!
+//! Like an oscilloscope, the capture keeps recording into a ring while armed,
+//! fires on an edge of one channel and freezes once the records after the
+//! trigger fill the rest of the ring.
+
+use crate::telemetry::{Channel, ChannelSet, Snapshot};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+impl Edge {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Edge::Rising => "rising",
+            Edge::Falling => "falling",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Edge> {
+        match name {
+            "rising" => Some(Edge::Rising),
+            "falling" => Some(Edge::Falling),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Trigger {
+    pub channel: Channel,
+    pub edge: Edge,
+    pub level: f32,
+}
+
+impl Trigger {
+    /// NaN never crosses the level, so sensor errors do not fire it.
+    pub fn crossed(&self, previous: f32, value: f32) -> bool {
+        match self.edge {
+            Edge::Rising => previous < self.level && value >= self.level,
+            Edge::Falling => previous > self.level && value <= self.level,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    Idle,
+    /// Recording and waiting for the trigger
+    Armed,
+    /// Recording the records after the trigger
+    Triggered,
+    /// Frozen until armed again
+    Done,
+}
+
+impl State {
+    pub fn name(&self) -> &'static str {
+        match self {
+            State::Idle => "idle",
+            State::Armed => "armed",
+            State::Triggered => "triggered",
+            State::Done => "done",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    NoChannels,
+}
+
+/// One record of a finished capture.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Record<'a> {
+    /// Samples relative to the trigger, negative before it
+    pub offset: i32,
+    /// Values of the captured channels in channel order
+    pub values: &'a [f32],
+}
+
+/// Capture of up to `N` values, the depth is `N` divided by the channel count.
+pub struct Capture<const N: usize> {
+    buffer: [f32; N],
+    channels: ChannelSet,
+    trigger: Option<Trigger>,
+    state: State,
+    depth: usize,
+    pretrigger: usize,
+    // record slot written next
+    next: usize,
+    // records written since arming, up to `depth`
+    filled: usize,
+    // records still to take after the trigger
+    remaining: usize,
+    previous: f32,
+    trigger_us: u32,
+}
+
+impl<const N: usize> Default for Capture<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Capture<N> {
+    pub const fn new() -> Self {
+        Capture {
+            buffer: [0.0; N],
+            channels: ChannelSet::EMPTY,
+            trigger: None,
+            state: State::Idle,
+            depth: 0,
+            pretrigger: 0,
+            next: 0,
+            filled: 0,
+            remaining: 0,
+            previous: f32::NAN,
+            trigger_us: 0,
+        }
+    }
+
+    /// Starts recording `channels`, keeping `pretrigger_percent` of the
+    /// records from before the trigger.
+    pub fn arm(
+        &mut self,
+        channels: ChannelSet,
+        trigger: Trigger,
+        pretrigger_percent: u8,
+    ) -> Result<(), Error> {
+        if channels.is_empty() {
+            return Err(Error::NoChannels);
+        }
+        self.channels = channels;
+        self.trigger = Some(trigger);
+        self.depth = N / channels.len();
+        // the trigger record itself always comes after them
+        self.pretrigger =
+            (self.depth * pretrigger_percent.min(100) as usize / 100).min(self.depth - 1);
+        self.next = 0;
+        self.filled = 0;
+        self.previous = f32::NAN;
+        self.state = State::Armed;
+        Ok(())
+    }
+
+    /// Stops recording and drops the records.
+    pub fn stop(&mut self) {
+        self.state = State::Idle;
+        self.filled = 0;
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn channels(&self) -> ChannelSet {
+        self.channels
+    }
+
+    pub fn trigger(&self) -> Option<Trigger> {
+        self.trigger
+    }
+
+    /// Records per capture.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Records before the trigger.
+    pub fn pretrigger(&self) -> usize {
+        self.pretrigger
+    }
+
+    /// Records written since arming, up to [`depth`](Self::depth).
+    pub fn filled(&self) -> usize {
+        self.filled
+    }
+
+    /// Timestamp of the trigger record.
+    pub fn trigger_us(&self) -> u32 {
+        self.trigger_us
+    }
+
+    pub fn sample(&mut self, snapshot: &Snapshot) {
+        let trigger = match (self.state, self.trigger) {
+            (State::Armed | State::Triggered, Some(trigger)) => trigger,
+            _ => return,
+        };
+
+        let stride = self.channels.len();
+        let slot = &mut self.buffer[self.next * stride..][..stride];
+        for (value, channel) in slot.iter_mut().zip(self.channels.iter()) {
+            *value = snapshot.get(channel);
+        }
+        self.next = (self.next + 1) % self.depth;
+        self.filled = (self.filled + 1).min(self.depth);
+
+        let value = snapshot.get(trigger.channel);
+        match self.state {
+            State::Armed
+                if self.filled > self.pretrigger && trigger.crossed(self.previous, value) =>
+            {
+                self.trigger_us = snapshot.timestamp_us;
+                self.remaining = self.depth - self.pretrigger - 1;
+                self.state = State::Triggered;
+            }
+            State::Triggered => self.remaining -= 1,
+            _ => {}
+        }
+        if self.state == State::Triggered && self.remaining == 0 {
+            self.state = State::Done;
+        }
+        self.previous = value;
+    }
+
+    /// Record `n` of a finished capture, oldest first.
+    pub fn record(&self, n: usize) -> Option<Record<'_>> {
+        if self.state != State::Done || n >= self.depth {
+            return None;
+        }
+        // the ring is full, so the oldest record is the one written next
+        let stride = self.channels.len();
+        let slot = (self.next + n) % self.depth;
+        Some(Record {
+            offset: n as i32 - self.pretrigger as i32,
+            values: &self.buffer[slot * stride..][..stride],
+        })
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = Record<'_>> + '_ {
+        (0..self.depth).map_while(move |n| self.record(n))
+    }
+}
@@ -0,0 +1,252 @@
+//! Open loop motor characterization.
+//!
+//! A [`Sweep`] steps the duty from zero to full scale in both directions and
+//! averages the steady state speed at every step. [`fit`] turns one direction
+//! of it into a [`Curve`]: the deadband, the smallest duty that keeps the
+//! motor turning, and a duty to speed table above it, which is inverted for
+//! feed-forward by [`Compensation::duty_for`].
+
+/// Duty steps of a sweep per direction, zero duty excluded.
+pub const SWEEP_STEPS: usize = 20;
+
+/// Entries of a [`Curve`] table.
+pub const CURVE_POINTS: usize = 8;
+
+/// Speed above which the motor counts as turning, rpm.
+pub const MOVING_RPM: f32 = 5.0;
+
+// the motor may still coast from full speed in the other direction
+const STOP_SETTLE_FACTOR: u32 = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SweepStep {
+    /// Keep the current duty
+    Hold,
+    /// Apply a new signed duty fraction, clockwise is positive
+    Duty(f32),
+    Done,
+}
+
+pub struct Sweep {
+    settle_ticks: u32,
+    measure_ticks: u32,
+    running: bool,
+    // 0 is clockwise, 1 counter-clockwise
+    direction: usize,
+    // 0 is the stop before each direction
+    step: usize,
+    tick: u32,
+    sum: f32,
+    count: u32,
+    /// Mean speed magnitude per direction at duty `step / SWEEP_STEPS`
+    speeds: [[f32; SWEEP_STEPS + 1]; 2],
+}
+
+impl Sweep {
+    pub const fn new(settle_ticks: u32, measure_ticks: u32) -> Self {
+        Sweep {
+            settle_ticks,
+            measure_ticks,
+            running: false,
+            direction: 0,
+            step: 0,
+            tick: 0,
+            sum: 0.0,
+            count: 0,
+            speeds: [[0.0; SWEEP_STEPS + 1]; 2],
+        }
+    }
+
+    /// Starts the sweep, the caller applies [`duty`](Self::duty).
+    pub fn start(&mut self) {
+        self.running = true;
+        self.direction = 0;
+        self.speeds = [[f32::NAN; SWEEP_STEPS + 1]; 2];
+        self.begin_step(0);
+    }
+
+    pub fn cancel(&mut self) {
+        self.running = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Completed steps and the total of both directions.
+    pub fn progress(&self) -> (usize, usize) {
+        (
+            self.direction * (SWEEP_STEPS + 1) + self.step,
+            2 * (SWEEP_STEPS + 1),
+        )
+    }
+
+    /// Clockwise and counter-clockwise speeds of a finished sweep.
+    pub fn speeds(&self) -> &[[f32; SWEEP_STEPS + 1]; 2] {
+        &self.speeds
+    }
+
+    /// Signed duty fraction of the current step.
+    pub fn duty(&self) -> f32 {
+        let duty = self.step as f32 / SWEEP_STEPS as f32;
+        if self.direction == 0 {
+            duty
+        } else {
+            -duty
+        }
+    }
+
+    /// Feeds the speed of one control tick, NaN readings are skipped.
+    pub fn tick(&mut self, rpm: f32) -> SweepStep {
+        if !self.running {
+            return SweepStep::Hold;
+        }
+
+        self.tick += 1;
+        let settle = match self.step {
+            0 => self.settle_ticks * STOP_SETTLE_FACTOR,
+            _ => self.settle_ticks,
+        };
+        if self.tick <= settle {
+            return SweepStep::Hold;
+        }
+        if !rpm.is_nan() {
+            self.sum += if rpm < 0.0 { -rpm } else { rpm };
+            self.count += 1;
+        }
+        if self.tick < settle + self.measure_ticks {
+            return SweepStep::Hold;
+        }
+
+        self.speeds[self.direction][self.step] = match self.count {
+            0 => f32::NAN,
+            count => self.sum / count as f32,
+        };
+        if self.step < SWEEP_STEPS {
+            self.begin_step(self.step + 1);
+        } else if self.direction == 0 {
+            self.direction = 1;
+            self.begin_step(0);
+        } else {
+            self.running = false;
+            return SweepStep::Done;
+        }
+        SweepStep::Duty(self.duty())
+    }
+
+    fn begin_step(&mut self, step: usize) {
+        self.step = step;
+        self.tick = 0;
+        self.sum = 0.0;
+        self.count = 0;
+    }
+}
+
+/// Duty to speed curve of one direction.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct Curve {
+    /// Smallest duty fraction that keeps the motor turning
+    pub deadband: f32,
+    /// Speed in rpm at evenly spaced duties from `deadband` to full scale
+    pub speeds: [f32; CURVE_POINTS],
+}
+
+impl Curve {
+    /// No characterization, every speed maps to zero duty.
+    pub const NONE: Curve = Curve {
+        deadband: 0.0,
+        speeds: [0.0; CURVE_POINTS],
+    };
+
+    pub fn is_valid(&self) -> bool {
+        self.speeds[CURVE_POINTS - 1] > 0.0
+    }
+
+    /// Duty fraction at table entry `n`.
+    pub fn duty(&self, n: usize) -> f32 {
+        self.deadband + (1.0 - self.deadband) * n as f32 / (CURVE_POINTS - 1) as f32
+    }
+
+    /// Feed-forward duty fraction for a speed magnitude in rpm.
+    ///
+    /// Speeds the motor cannot turn that slowly at get the deadband duty,
+    /// `None` if it is invalid or the speed is out of reach.
+    pub fn duty_for(&self, rpm: f32) -> Option<f32> {
+        if !self.is_valid() || rpm > self.speeds[CURVE_POINTS - 1] {
+            return None;
+        }
+        if rpm <= 0.0 {
+            return Some(0.0);
+        }
+        if rpm <= self.speeds[0] {
+            return Some(self.deadband);
+        }
+        let n = self.speeds.windows(2).position(|pair| rpm <= pair[1])?;
+        let (low, high) = (self.speeds[n], self.speeds[n + 1]);
+        let part = if high > low {
+            (rpm - low) / (high - low)
+        } else {
+            0.0
+        };
+        Some(self.duty(n) + (self.duty(n + 1) - self.duty(n)) * part)
+    }
+}
+
+/// Feed-forward and deadband compensation, stored with the parameters.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct Compensation {
+    pub cw: Curve,
+    pub ccw: Curve,
+}
+
+impl Compensation {
+    pub const NONE: Compensation = Compensation {
+        cw: Curve::NONE,
+        ccw: Curve::NONE,
+    };
+
+    /// Signed duty fraction for a signed speed, clockwise is positive.
+    pub fn duty_for(&self, rpm: f32) -> Option<f32> {
+        if rpm < 0.0 {
+            self.ccw.duty_for(-rpm).map(|duty| -duty)
+        } else {
+            self.cw.duty_for(rpm)
+        }
+    }
+}
+
+/// Fits the speeds of one sweep direction.
+///
+/// The deadband is the first step turning faster than [`MOVING_RPM`], the
+/// table interpolates the measured speeds above it, kept non decreasing so
+/// that it can be inverted. `None` if the motor never turned.
+pub fn fit(speeds: &[f32; SWEEP_STEPS + 1]) -> Option<Curve> {
+    let duty = |step: usize| step as f32 / SWEEP_STEPS as f32;
+    // the stop step only lets the motor spin down
+    let first = 1 + speeds[1..].iter().position(|speed| *speed > MOVING_RPM)?;
+
+    // monotonic envelope, readings lost to sensor errors take the previous
+    let mut envelope = [0.0; SWEEP_STEPS + 1];
+    let mut top = 0.0f32;
+    for (step, speed) in speeds.iter().enumerate().skip(first) {
+        if !speed.is_nan() {
+            top = top.max(*speed);
+        }
+        envelope[step] = top;
+    }
+
+    let mut curve = Curve {
+        deadband: duty(first),
+        speeds: [0.0; CURVE_POINTS],
+    };
+    for n in 0..CURVE_POINTS {
+        let at = curve.duty(n) * SWEEP_STEPS as f32;
+        let low = (at as usize).min(SWEEP_STEPS);
+        let high = (low + 1).min(SWEEP_STEPS);
+        let part = at - low as f32;
+        curve.speeds[n] = envelope[low] + (envelope[high] - envelope[low]) * part;
+    }
+    Some(curve)
+}
@@ -0,0 +1,271 @@
+//! Status dashboard for the 128x64 SSD1306.
+//!
+//! [`draw`] renders a [`Status`] onto any binary [`DrawTarget`], the firmware
+//! hands it the buffered display and the tests a plain framebuffer. While the
+//! menu is open, [`draw_menu`] takes over the whole screen.
+//!
+//! ```text
+//! +----------------------------+
+//! | CW               .--.      |
+//! | duty   45 %     /  | \     |
+//! | rpm   1234      \    /     |
+//! | bat   7.4 V      '--'      |
+//! |                   123      |
+//! |##### stack_low ############|
+//! +----------------------------+
+//! ```
+
+use core::fmt::Write;
+
+use embedded_graphics::mono_font::ascii::{FONT_10X20, FONT_6X10};
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{Circle, Line, PrimitiveStyle, Rectangle};
+use embedded_graphics::text::{Alignment, Baseline, Text, TextStyleBuilder};
+use heapless::String;
+use micromath::F32Ext;
+
+use crate::menu::{Item, Menu, Screen, Settings, ITEMS};
+
+pub const WIDTH: u32 = 128;
+pub const HEIGHT: u32 = 64;
+
+/// Center and diameter of the angle dial.
+pub const DIAL_CENTER: Point = Point::new(106, 20);
+pub const DIAL_DIAMETER: u32 = 37;
+
+/// Top of the fault bar, inverted while faults are active.
+pub const FAULT_BAR_Y: i32 = 53;
+
+const LINE_HEIGHT: i32 = 11;
+const NEEDLE_LENGTH: f32 = 15.0;
+
+/// What the dashboard shows, unknown readings are NaN.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Status<'a> {
+    /// Motor state name
+    pub state: &'a str,
+    /// Signed duty fraction, clockwise is positive
+    pub duty: f32,
+    /// Speed in rpm
+    pub speed: f32,
+    /// Shaft angle in degrees, zero points up and grows clockwise
+    pub angle: f32,
+    /// Battery voltage
+    pub battery: f32,
+    /// Names of the active faults
+    pub faults: &'a [&'a str],
+}
+
+/// Clears `target` and draws the whole dashboard.
+pub fn draw<D>(target: &mut D, status: &Status) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    target.clear(BinaryColor::Off)?;
+
+    let on = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+    let line = |n: i32| Point::new(0, n * LINE_HEIGHT);
+
+    let mut text = String::<24>::new();
+    upper(&mut text, status.state);
+    Text::with_baseline(&text, line(0), on, Baseline::Top).draw(target)?;
+
+    text.clear();
+    text.push_str("duty ").ok();
+    number(&mut text, status.duty * 100.0, 5, 0);
+    text.push_str(" %").ok();
+    Text::with_baseline(&text, line(1), on, Baseline::Top).draw(target)?;
+
+    text.clear();
+    text.push_str("rpm  ").ok();
+    number(&mut text, status.speed, 5, 0);
+    Text::with_baseline(&text, line(2), on, Baseline::Top).draw(target)?;
+
+    text.clear();
+    text.push_str("bat  ").ok();
+    number(&mut text, status.battery, 5, 1);
+    text.push_str(" V").ok();
+    Text::with_baseline(&text, line(3), on, Baseline::Top).draw(target)?;
+
+    dial(target, status.angle)?;
+
+    fault_bar(target, status.faults)
+}
+
+/// Clears `target` and draws an open menu screen, nothing for `Status`.
+pub fn draw_menu<D>(target: &mut D, screen: Screen, settings: &Settings) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    target.clear(BinaryColor::Off)?;
+
+    let on = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+    let right = TextStyleBuilder::new()
+        .alignment(Alignment::Right)
+        .baseline(Baseline::Top)
+        .build();
+    let centered = TextStyleBuilder::new()
+        .alignment(Alignment::Center)
+        .baseline(Baseline::Top)
+        .build();
+    let mut text = String::<24>::new();
+
+    let (title, hint) = match screen {
+        Screen::Status => return Ok(()),
+        Screen::List { cursor } => {
+            for (n, item) in ITEMS.iter().enumerate() {
+                let y = (n as i32 + 1) * LINE_HEIGHT;
+                let marker = if n == cursor { ">" } else { " " };
+                text.clear();
+                write!(text, "{}{}", marker, item.name()).ok();
+                Text::with_baseline(&text, Point::new(0, y), on, Baseline::Top).draw(target)?;
+
+                text.clear();
+                match item {
+                    Item::Setpoint => write!(text, "{} rpm", settings.setpoint_rpm).ok(),
+                    Item::PwmFrequency => write!(text, "{} Hz", settings.pwm_hz).ok(),
+                    Item::Arm => text
+                        .push_str(if settings.armed { "armed" } else { "off" })
+                        .ok(),
+                    Item::Calibrate => None,
+                };
+                let end = Point::new(WIDTH as i32 - 1, y);
+                Text::with_text_style(&text, end, on, right).draw(target)?;
+            }
+            ("MENU", "short next  long ok")
+        }
+        Screen::Edit { item, value } => {
+            let unit = match item {
+                Item::PwmFrequency => "Hz",
+                _ => "rpm",
+            };
+            write!(text, "{} {}", Menu::edit_value(item, value), unit).ok();
+            let large = MonoTextStyle::new(&FONT_10X20, BinaryColor::On);
+            let center = Point::new(WIDTH as i32 / 2, 2 * LINE_HEIGHT);
+            Text::with_text_style(&text, center, large, centered).draw(target)?;
+            (item.name(), "short +  long set")
+        }
+        Screen::Confirm { item } => {
+            write!(text, "Run {}?", item.name()).ok();
+            let center = Point::new(WIDTH as i32 / 2, 2 * LINE_HEIGHT + 5);
+            Text::with_text_style(&text, center, on, centered).draw(target)?;
+            (item.name(), "long run  double back")
+        }
+    };
+
+    // inverted title bar
+    Rectangle::new(Point::zero(), Size::new(WIDTH, LINE_HEIGHT as u32))
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(target)?;
+    text.clear();
+    upper(&mut text, title);
+    let off = MonoTextStyle::new(&FONT_6X10, BinaryColor::Off);
+    Text::with_text_style(&text, Point::new(WIDTH as i32 / 2, 0), off, centered).draw(target)?;
+
+    let bottom = Point::new(WIDTH as i32 / 2, FAULT_BAR_Y + 1);
+    Text::with_text_style(hint, bottom, on, centered).draw(target)?;
+    Ok(())
+}
+
+/// Where the needle of the dial ends for `angle` in degrees.
+pub fn needle_end(angle: f32) -> Point {
+    let radians = angle.to_radians();
+    Point::new(
+        DIAL_CENTER.x + (F32Ext::sin(radians) * NEEDLE_LENGTH).round() as i32,
+        DIAL_CENTER.y - (F32Ext::cos(radians) * NEEDLE_LENGTH).round() as i32,
+    )
+}
+
+fn dial<D>(target: &mut D, angle: f32) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let stroke = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+    Circle::with_center(DIAL_CENTER, DIAL_DIAMETER)
+        .into_styled(stroke)
+        .draw(target)?;
+
+    let mut text = String::<24>::new();
+    number(&mut text, angle, 3, 0);
+    let centered = TextStyleBuilder::new()
+        .alignment(Alignment::Center)
+        .baseline(Baseline::Top)
+        .build();
+    let below = DIAL_CENTER + Point::new(0, DIAL_DIAMETER as i32 / 2 + 3);
+    Text::with_text_style(
+        text.trim_start(),
+        below,
+        MonoTextStyle::new(&FONT_6X10, BinaryColor::On),
+        centered,
+    )
+    .draw(target)?;
+
+    if angle.is_nan() {
+        return Ok(());
+    }
+    Line::new(DIAL_CENTER, needle_end(angle))
+        .into_styled(stroke)
+        .draw(target)?;
+    Ok(())
+}
+
+fn fault_bar<D>(target: &mut D, faults: &[&str]) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let position = Point::new(0, FAULT_BAR_Y + 1);
+    if faults.is_empty() {
+        let style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+        Text::with_baseline("OK", position, style, Baseline::Top).draw(target)?;
+        return Ok(());
+    }
+
+    Rectangle::new(
+        Point::new(0, FAULT_BAR_Y),
+        Size::new(WIDTH, HEIGHT - FAULT_BAR_Y as u32),
+    )
+    .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+    .draw(target)?;
+
+    // as many names as fit, the rest is cut
+    let mut text = String::<24>::new();
+    for (n, fault) in faults.iter().enumerate() {
+        let separator = if n == 0 { "" } else { " " };
+        if text.push_str(separator).is_err() || text.push_str(fault).is_err() {
+            break;
+        }
+    }
+    let style = MonoTextStyle::new(&FONT_6X10, BinaryColor::Off);
+    Text::with_baseline(&text, position, style, Baseline::Top).draw(target)?;
+    Ok(())
+}
+
+fn upper<const N: usize>(text: &mut String<N>, name: &str) {
+    for c in name.chars() {
+        let c = match c {
+            '_' => ' ',
+            c => c.to_ascii_uppercase(),
+        };
+        if text.push(c).is_err() {
+            break;
+        }
+    }
+}
+
+/// Right aligned in `width` characters, `--` for NaN.
+fn number<const N: usize>(text: &mut String<N>, value: f32, width: usize, decimals: usize) {
+    if value.is_nan() {
+        write!(text, "{:>width$}", "--", width = width).ok();
+    } else {
+        write!(
+            text,
+            "{:>width$.decimals$}",
+            value,
+            width = width,
+            decimals = decimals
+        )
+        .ok();
+    }
+}
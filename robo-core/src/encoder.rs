@@ -0,0 +1,130 @@
+//! Quadrature encoder position and speed from a 16 bit timer count.
+//!
+//! [`Quadrature::update`] extends the count of a timer in encoder mode to 64
+//! bits, it has to be called before the count can move by half its range.
+//! The index pulse sets the zero of [`Quadrature::angle`], until the first one
+//! the zero is where counting started. Angles and speeds are in degrees and
+//! degrees per second like the TLE5012 gives them.
+
+/// Samples the speed is estimated over.
+pub const SPEED_SAMPLES: usize = 16;
+
+/// Fewest counts a speed is taken over, the window grows back in time until
+/// it covers them, so slow turning does not jitter by whole counts.
+pub const SPEED_MIN_COUNTS: i64 = 8;
+
+/// Index pulses may land one count apart depending on the direction.
+const INDEX_TOLERANCE: i64 = 1;
+
+#[derive(Clone, Debug)]
+pub struct Quadrature {
+    counts_per_rev: u32,
+    last_raw: u16,
+    position: i64,
+    /// Position of the last index pulse
+    zero: Option<i64>,
+    index_errors: u32,
+    /// Position and timestamp in microseconds of the last updates
+    samples: [(i64, u32); SPEED_SAMPLES],
+    head: usize,
+    len: usize,
+}
+
+impl Quadrature {
+    /// `counts_per_rev` counts every edge of both channels, four per line.
+    pub const fn new(counts_per_rev: u32, raw: u16) -> Self {
+        Quadrature {
+            counts_per_rev,
+            last_raw: raw,
+            position: 0,
+            zero: None,
+            index_errors: 0,
+            samples: [(0, 0); SPEED_SAMPLES],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn counts_per_rev(&self) -> u32 {
+        self.counts_per_rev
+    }
+
+    /// Takes the timer count read at `now_us`.
+    pub fn update(&mut self, raw: u16, now_us: u32) {
+        self.position = self.extend(raw);
+        self.last_raw = raw;
+        self.samples[self.head] = (self.position, now_us);
+        self.head = (self.head + 1) % SPEED_SAMPLES;
+        self.len = (self.len + 1).min(SPEED_SAMPLES);
+    }
+
+    /// Takes the timer count latched by an index pulse.
+    ///
+    /// An index more than a count away from a whole number of turns since
+    /// the previous one means counts were lost, it is counted in
+    /// [`index_errors`](Self::index_errors) and the zero moves anyway.
+    pub fn on_index(&mut self, raw: u16) {
+        let position = self.extend(raw);
+        if let Some(zero) = self.zero {
+            let offset = (position - zero).rem_euclid(self.counts_per_rev as i64);
+            if offset > INDEX_TOLERANCE && offset < self.counts_per_rev as i64 - INDEX_TOLERANCE {
+                self.index_errors += 1;
+            }
+        }
+        self.zero = Some(position);
+    }
+
+    fn extend(&self, raw: u16) -> i64 {
+        self.position + raw.wrapping_sub(self.last_raw) as i16 as i64
+    }
+
+    /// Counts since the start.
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+
+    /// Whether an index pulse has set the zero.
+    pub fn is_referenced(&self) -> bool {
+        self.zero.is_some()
+    }
+
+    pub fn index_errors(&self) -> u32 {
+        self.index_errors
+    }
+
+    /// Angle from the zero, -180 up to 180 degrees.
+    pub fn angle(&self) -> f32 {
+        let counts = self.counts_per_rev as i64;
+        let mut offset = (self.position - self.zero.unwrap_or(0)).rem_euclid(counts);
+        if offset >= counts / 2 {
+            offset -= counts;
+        }
+        offset as f32 * 360.0 / counts as f32
+    }
+
+    /// Speed in degrees per second over the last [`SPEED_SAMPLES`] at most.
+    pub fn speed(&self) -> f32 {
+        if self.len < 2 {
+            return 0.0;
+        }
+        let newest = self.sample(0);
+        let mut oldest = newest;
+        for n in 1..self.len {
+            oldest = self.sample(n);
+            if (newest.0 - oldest.0).abs() >= SPEED_MIN_COUNTS {
+                break;
+            }
+        }
+        let dt_us = newest.1.wrapping_sub(oldest.1);
+        if dt_us == 0 {
+            return 0.0;
+        }
+        let counts = (newest.0 - oldest.0) as f32;
+        counts * 360.0 / self.counts_per_rev as f32 * 1e6 / dt_us as f32
+    }
+
+    /// The `n`th sample back from the newest.
+    fn sample(&self, n: usize) -> (i64, u32) {
+        self.samples[(self.head + SPEED_SAMPLES - 1 - n) % SPEED_SAMPLES]
+    }
+}
@@ -0,0 +1,243 @@
+//! Field oriented control of a three phase motor.
+//!
+//! Phase currents go through [`clarke`] and [`park`] into the rotor frame,
+//! where a [`CurrentLoop`] holds the flux current `d` at zero and the torque
+//! current `q` at its target. The voltage it asks for goes back through
+//! [`inverse_park`] and [`svpwm`] to the duties of the three half bridges.
+//! [`ElectricalAngle`] turns the shaft angle into the rotor flux angle, its
+//! offset found by [`ElectricalAngle::align`].
+//!
+//! Transforms are amplitude invariant: a phase current amplitude of 1 A is
+//! 1 A in `alpha`/`beta` and `d`/`q`. Angles are radians.
+
+use core::f32::consts::PI;
+
+use micromath::F32Ext;
+
+use crate::sensor;
+
+const SQRT_3: f32 = 1.732_050_8;
+
+/// Longest voltage vector the modulation reaches without distortion, as a
+/// fraction of the bus voltage.
+pub const MAX_MODULATION: f32 = 1.0 / SQRT_3;
+
+/// Phase quantities.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Abc {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+}
+
+/// Stator frame quantities.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AlphaBeta {
+    pub alpha: f32,
+    pub beta: f32,
+}
+
+/// Rotor frame quantities, `d` along the magnet flux.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Dq {
+    pub d: f32,
+    pub q: f32,
+}
+
+impl Dq {
+    pub fn magnitude(&self) -> f32 {
+        sqrt(self.d * self.d + self.q * self.q)
+    }
+}
+
+/// Sine and cosine of an angle, worked out once for [`park`] and
+/// [`inverse_park`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SinCos {
+    pub sin: f32,
+    pub cos: f32,
+}
+
+impl SinCos {
+    pub fn new(angle: f32) -> Self {
+        SinCos {
+            sin: F32Ext::sin(angle),
+            cos: F32Ext::cos(angle),
+        }
+    }
+}
+
+/// Stator frame from phases `a` and `b`, the third adds up to zero.
+pub fn clarke(a: f32, b: f32) -> AlphaBeta {
+    AlphaBeta {
+        alpha: a,
+        beta: (a + 2.0 * b) / SQRT_3,
+    }
+}
+
+pub fn inverse_clarke(value: AlphaBeta) -> Abc {
+    let half = -0.5 * value.alpha;
+    let side = 0.5 * SQRT_3 * value.beta;
+    Abc {
+        a: value.alpha,
+        b: half + side,
+        c: half - side,
+    }
+}
+
+/// Rotor frame at electrical angle `angle`.
+pub fn park(value: AlphaBeta, angle: SinCos) -> Dq {
+    Dq {
+        d: value.alpha * angle.cos + value.beta * angle.sin,
+        q: value.beta * angle.cos - value.alpha * angle.sin,
+    }
+}
+
+pub fn inverse_park(value: Dq, angle: SinCos) -> AlphaBeta {
+    AlphaBeta {
+        alpha: value.d * angle.cos - value.q * angle.sin,
+        beta: value.d * angle.sin + value.q * angle.cos,
+    }
+}
+
+/// Duties from 0 to 1 of the three half bridges for `voltage` on a bus of
+/// `bus` volts.
+///
+/// Centring the phases between the highest and the lowest gives the same
+/// switching as space vector modulation, without working out the sector.
+/// Vectors longer than [`MAX_MODULATION`] of the bus are shortened to it.
+pub fn svpwm(voltage: AlphaBeta, bus: f32) -> Abc {
+    let mut alpha = voltage.alpha / bus;
+    let mut beta = voltage.beta / bus;
+    let length = sqrt(alpha * alpha + beta * beta);
+    if length > MAX_MODULATION {
+        alpha *= MAX_MODULATION / length;
+        beta *= MAX_MODULATION / length;
+    }
+    let phases = inverse_clarke(AlphaBeta { alpha, beta });
+    let high = phases.a.max(phases.b).max(phases.c);
+    let low = phases.a.min(phases.b).min(phases.c);
+    let shift = 0.5 - (high + low) / 2.0;
+    Abc {
+        a: phases.a + shift,
+        b: phases.b + shift,
+        c: phases.c + shift,
+    }
+}
+
+/// Proportional integral controller, the integral stops at the output limit.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Pi {
+    pub kp: f32,
+    /// Integral gain per sample, `ki` over the sample rate
+    pub ki: f32,
+    integral: f32,
+}
+
+impl Pi {
+    pub const fn new(kp: f32, ki: f32) -> Self {
+        Pi {
+            kp,
+            ki,
+            integral: 0.0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+    }
+
+    /// Output for `error`, within `-limit` up to `limit`.
+    pub fn update(&mut self, error: f32, limit: f32) -> f32 {
+        let proportional = self.kp * error;
+        let integral = self.integral + self.ki * error;
+        let output = proportional + integral;
+        if output > limit {
+            // only integrate back out of the limit
+            self.integral = self.integral.min(integral).min(limit);
+            return limit;
+        }
+        if output < -limit {
+            self.integral = self.integral.max(integral).max(-limit);
+            return -limit;
+        }
+        self.integral = integral;
+        output
+    }
+}
+
+/// The `d` and `q` current controllers.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CurrentLoop {
+    pub d: Pi,
+    pub q: Pi,
+}
+
+impl CurrentLoop {
+    /// Gains for a closed loop bandwidth of `bandwidth_hz`, the integral
+    /// cancelling the pole of a winding of `resistance` ohm and `inductance`
+    /// henry, run at `sample_hz`.
+    pub fn tuned(resistance: f32, inductance: f32, bandwidth_hz: f32, sample_hz: f32) -> Self {
+        let omega = 2.0 * PI * bandwidth_hz;
+        let pi = Pi::new(inductance * omega, resistance * omega / sample_hz);
+        CurrentLoop { d: pi, q: pi }
+    }
+
+    pub fn reset(&mut self) {
+        self.d.reset();
+        self.q.reset();
+    }
+
+    /// Voltage to drive `measured` to `target` on a bus of `bus` volts.
+    ///
+    /// The vector stays within what [`svpwm`] reaches, `d` first so the
+    /// flux stays under control when the voltage runs out.
+    pub fn update(&mut self, target: Dq, measured: Dq, bus: f32) -> Dq {
+        let limit = bus * MAX_MODULATION;
+        let d = self.d.update(target.d - measured.d, limit);
+        let left = sqrt((limit * limit - d * d).max(0.0));
+        let q = self.q.update(target.q - measured.q, left);
+        Dq { d, q }
+    }
+}
+
+/// Rotor flux angle from the shaft angle.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ElectricalAngle {
+    pub pole_pairs: u8,
+    /// Electrical angle the shaft zero is at
+    pub offset: f32,
+}
+
+impl ElectricalAngle {
+    pub const fn new(pole_pairs: u8) -> Self {
+        ElectricalAngle {
+            pole_pairs,
+            offset: 0.0,
+        }
+    }
+
+    /// Sets the offset from the shaft angle `mechanical` the rotor settled
+    /// at with current along electrical angle zero.
+    pub fn align(&mut self, mechanical: f32) {
+        self.offset = sensor::wrap(mechanical * self.pole_pairs as f32);
+    }
+
+    /// Electrical angle at shaft angle `mechanical`, -π up to π.
+    pub fn electrical(&self, mechanical: f32) -> f32 {
+        sensor::wrap(mechanical * self.pole_pairs as f32 - self.offset)
+    }
+}
+
+/// Square root to full precision, the micromath estimate is only good to a
+/// few percent.
+fn sqrt(value: f32) -> f32 {
+    if value <= 0.0 {
+        return 0.0;
+    }
+    let mut root = F32Ext::sqrt(value);
+    for _ in 0..2 {
+        root = 0.5 * (root + value / root);
+    }
+    root
+}
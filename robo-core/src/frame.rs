@@ -0,0 +1,95 @@
+//! SSD1306 frame buffer in the controller's own memory layout.
+//!
+//! Byte `x` of page `n` holds the column of rows `8n..8n + 8` at `x`, least
+//! significant bit on top, so a page goes out to the display as it is.
+
+use core::convert::Infallible;
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+
+use crate::dashboard::{HEIGHT, WIDTH};
+
+pub const PAGE_COUNT: usize = HEIGHT as usize / 8;
+pub const PAGE_LEN: usize = WIDTH as usize;
+pub const FRAME_LEN: usize = PAGE_COUNT * PAGE_LEN;
+
+pub struct Frame {
+    bytes: [u8; FRAME_LEN],
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Frame {
+    pub const fn new() -> Self {
+        Frame {
+            bytes: [0; FRAME_LEN],
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8; FRAME_LEN] {
+        &self.bytes
+    }
+
+    pub fn page(&self, n: usize) -> &[u8] {
+        &self.bytes[n * PAGE_LEN..(n + 1) * PAGE_LEN]
+    }
+
+    pub fn get(&self, point: Point) -> bool {
+        match index(point) {
+            Some((byte, bit)) => self.bytes[byte] & bit != 0,
+            None => false,
+        }
+    }
+}
+
+/// Byte and bit of `point`, `None` off screen.
+fn index(point: Point) -> Option<(usize, u8)> {
+    let (x, y) = (
+        usize::try_from(point.x).ok()?,
+        usize::try_from(point.y).ok()?,
+    );
+    if x >= PAGE_LEN || y >= HEIGHT as usize {
+        return None;
+    }
+    Some(((y / 8) * PAGE_LEN + x, 1 << (y % 8)))
+}
+
+impl DrawTarget for Frame {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Infallible>
+    where
+        I: IntoIterator<Item = Pixel<BinaryColor>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let Some((byte, bit)) = index(point) {
+                match color {
+                    BinaryColor::On => self.bytes[byte] |= bit,
+                    BinaryColor::Off => self.bytes[byte] &= !bit,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: BinaryColor) -> Result<(), Infallible> {
+        let fill = match color {
+            BinaryColor::On => 0xff,
+            BinaryColor::Off => 0x00,
+        };
+        self.bytes = [fill; FRAME_LEN];
+        Ok(())
+    }
+}
+
+impl OriginDimensions for Frame {
+    fn size(&self) -> Size {
+        Size::new(WIDTH, HEIGHT)
+    }
+}
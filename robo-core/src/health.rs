@@ -0,0 +1,196 @@
+//! Sensor health: the TLE5012 status register and read error statistics.
+//!
+//! [`Stat`] names the flags of the TLE5012 STAT register and maps them onto
+//! [`Status`]. [`Health`] wraps every read of a sensor, retries the ones
+//! that failed in transit and counts errors by kind, so a loose wire shows
+//! as counts going up long before the sensor is given up on.
+
+use crate::sensor::{Error, Status};
+
+/// Tries a read gets before its error is passed on.
+pub const ATTEMPTS: u32 = 2;
+
+/// Reads failing in a row, retries included, until [`Health::is_failing`].
+pub const FAIL_AFTER: u32 = 3;
+
+/// The TLE5012 STAT register, a set of flags.
+///
+/// Most flags latch until the register is read, so reading it now and then
+/// catches short faults as well.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stat(u16);
+
+impl Stat {
+    pub const OK: Stat = Stat(0);
+    /// A reset happened, set once after power up
+    pub const RESET: Stat = Stat(1 << 0);
+    pub const WATCHDOG: Stat = Stat(1 << 1);
+    /// Supply voltage out of range
+    pub const VOLTAGE: Stat = Stat(1 << 2);
+    /// Configuration fuses failed their CRC
+    pub const FUSE_CRC: Stat = Stat(1 << 3);
+    /// Signal processing unit self test
+    pub const DSPU: Stat = Stat(1 << 4);
+    pub const OVERFLOW: Stat = Stat(1 << 5);
+    /// X or Y signal out of range
+    pub const XY_OUT_OF_LIMIT: Stat = Stat(1 << 6);
+    /// Magnet too weak or too strong
+    pub const MAGNET_LOSS: Stat = Stat(1 << 7);
+    pub const ADC_TEST: Stat = Stat(1 << 9);
+    /// Firmware ROM failed its CRC
+    pub const ROM_CRC: Stat = Stat(1 << 10);
+    /// No valid X and Y values yet
+    pub const NO_GMR_XY: Stat = Stat(1 << 11);
+    /// No valid angle yet
+    pub const NO_GMR_ANGLE: Stat = Stat(1 << 12);
+
+    pub const FLAGS: [(Stat, &'static str); 12] = [
+        (Stat::RESET, "reset"),
+        (Stat::WATCHDOG, "watchdog"),
+        (Stat::VOLTAGE, "voltage"),
+        (Stat::FUSE_CRC, "fuse_crc"),
+        (Stat::DSPU, "dspu"),
+        (Stat::OVERFLOW, "overflow"),
+        (Stat::XY_OUT_OF_LIMIT, "xy_out_of_limit"),
+        (Stat::MAGNET_LOSS, "magnet_loss"),
+        (Stat::ADC_TEST, "adc_test"),
+        (Stat::ROM_CRC, "rom_crc"),
+        (Stat::NO_GMR_XY, "no_gmr_xy"),
+        (Stat::NO_GMR_ANGLE, "no_gmr_angle"),
+    ];
+
+    /// Flags of the register as read, the slave number and the read status
+    /// bit on top are dropped.
+    pub const fn from_register(value: u16) -> Stat {
+        Stat(value & 0x1eff)
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    pub fn contains(&self, flags: Stat) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+
+    pub fn intersects(&self, flags: Stat) -> bool {
+        self.0 & flags.0 != 0
+    }
+
+    pub const fn union(self, flags: Stat) -> Stat {
+        Stat(self.0 | flags.0)
+    }
+
+    /// Names of the flags set.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        Stat::FLAGS
+            .iter()
+            .filter(move |(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+    }
+
+    /// The flags as any sensor reports them, a reset alone is fine.
+    pub fn status(&self) -> Status {
+        let mut status = Status::OK;
+        if self.intersects(Stat::MAGNET_LOSS.union(Stat::XY_OUT_OF_LIMIT)) {
+            status.insert(Status::SIGNAL_LOST);
+        }
+        if self.contains(Stat::OVERFLOW) {
+            status.insert(Status::OVERFLOW);
+        }
+        if self.intersects(Stat::NO_GMR_XY.union(Stat::NO_GMR_ANGLE)) {
+            status.insert(Status::DATA_LOST);
+        }
+        let faults = Stat::WATCHDOG
+            .union(Stat::VOLTAGE)
+            .union(Stat::FUSE_CRC)
+            .union(Stat::DSPU)
+            .union(Stat::ADC_TEST)
+            .union(Stat::ROM_CRC);
+        if self.intersects(faults) {
+            status.insert(Status::FAULT);
+        }
+        status
+    }
+}
+
+/// Read statistics of a sensor.
+#[derive(Clone, Debug, Default)]
+pub struct Health {
+    reads: u32,
+    failures: u32,
+    retries: u32,
+    /// Failed tries by [`Error`]
+    errors: [u32; Error::COUNT],
+    /// Reads failed in a row
+    consecutive: u32,
+}
+
+impl Health {
+    pub const fn new() -> Self {
+        Health {
+            reads: 0,
+            failures: 0,
+            retries: 0,
+            errors: [0; Error::COUNT],
+            consecutive: 0,
+        }
+    }
+
+    /// Runs `read` until it succeeds, at most [`ATTEMPTS`] times.
+    ///
+    /// Bus and CRC errors are worth another try, `NotReady` is passed on
+    /// straight away.
+    pub fn retry<T>(&mut self, mut read: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+        self.reads = self.reads.wrapping_add(1);
+        let mut result = read();
+        for _ in 1..ATTEMPTS {
+            match result {
+                Err(error) if error != Error::NotReady => {
+                    self.count(error);
+                    self.retries = self.retries.wrapping_add(1);
+                    result = read();
+                }
+                _ => break,
+            }
+        }
+        match result {
+            Ok(_) => self.consecutive = 0,
+            Err(error) => {
+                self.count(error);
+                self.failures = self.failures.wrapping_add(1);
+                self.consecutive = self.consecutive.saturating_add(1);
+            }
+        }
+        result
+    }
+
+    fn count(&mut self, error: Error) {
+        let count = &mut self.errors[error as usize];
+        *count = count.wrapping_add(1);
+    }
+
+    /// Reads asked for, each may have taken several tries.
+    pub fn reads(&self) -> u32 {
+        self.reads
+    }
+
+    /// Reads that failed after all their tries.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Tries that failed with `error`.
+    pub fn errors(&self, error: Error) -> u32 {
+        self.errors[error as usize]
+    }
+
+    /// Whether the last [`FAIL_AFTER`] reads all failed.
+    pub fn is_failing(&self) -> bool {
+        self.consecutive >= FAIL_AFTER
+    }
+}
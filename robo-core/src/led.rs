@@ -0,0 +1,168 @@
+//! Status LED patterns with priorities.
+//!
+//! [`Pattern::level`] is the brightness of a pattern some time after it
+//! started. [`Led`] holds a pattern per [`Layer`] and plays the highest one
+//! set, so a fault shows over whatever the user asked for, and that over the
+//! normal status. Timestamps are milliseconds and wrap like a `u32` counter.
+
+/// Brightness of a fully lit LED, levels are per mille.
+pub const FULL: u16 = 1000;
+
+const HEARTBEAT_MS: u32 = 1200;
+const BLINK_MS: u32 = 200;
+/// A code blink is on for `CODE_ON_MS` out of `CODE_BLINK_MS`
+const CODE_BLINK_MS: u32 = 500;
+const CODE_ON_MS: u32 = 200;
+const CODE_PAUSE_MS: u32 = 1500;
+const BREATH_MS: u32 = 3000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    Off,
+    /// Steady at a percentage
+    Steady(u8),
+    /// Two short flashes every 1.2 s
+    Heartbeat,
+    /// 5 Hz, a fault without a code
+    FastBlink,
+    /// `n` blinks and a pause, fault codes 1 to 9
+    Code(u8),
+    /// Fading in and out every 3 s
+    Breathing,
+}
+
+impl Pattern {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pattern::Off => "off",
+            Pattern::Steady(_) => "steady",
+            Pattern::Heartbeat => "heartbeat",
+            Pattern::FastBlink => "blink",
+            Pattern::Code(_) => "code",
+            Pattern::Breathing => "breathe",
+        }
+    }
+
+    /// `off`, `on`, `steady <percent>`, `heartbeat`, `blink`, `code <1-9>`
+    /// or `breathe`.
+    pub fn parse(args: &str) -> Option<Pattern> {
+        let mut args = args.split_whitespace();
+        let name = args.next()?;
+        let arg = args.next().map(|arg| arg.parse::<u8>().ok());
+        if args.next().is_some() {
+            return None;
+        }
+        match (name, arg) {
+            ("off", None) => Some(Pattern::Off),
+            ("on", None) => Some(Pattern::Steady(100)),
+            ("steady", Some(Some(percent))) if percent <= 100 => Some(Pattern::Steady(percent)),
+            ("heartbeat", None) => Some(Pattern::Heartbeat),
+            ("blink", None) => Some(Pattern::FastBlink),
+            ("code", Some(Some(code))) if (1..=9).contains(&code) => Some(Pattern::Code(code)),
+            ("breathe", None) => Some(Pattern::Breathing),
+            _ => None,
+        }
+    }
+
+    /// Brightness `elapsed_ms` after the start, up to [`FULL`].
+    pub fn level(&self, elapsed_ms: u32) -> u16 {
+        let lit = match *self {
+            Pattern::Off => false,
+            Pattern::Steady(percent) => return percent.min(100) as u16 * (FULL / 100),
+            Pattern::Heartbeat => {
+                let t = elapsed_ms % HEARTBEAT_MS;
+                t < 100 || (250..350).contains(&t)
+            }
+            Pattern::FastBlink => elapsed_ms % BLINK_MS < BLINK_MS / 2,
+            Pattern::Code(code) => {
+                let blinks = code.clamp(1, 9) as u32 * CODE_BLINK_MS;
+                let t = elapsed_ms % (blinks + CODE_PAUSE_MS);
+                t < blinks && t % CODE_BLINK_MS < CODE_ON_MS
+            }
+            Pattern::Breathing => {
+                // a triangle squared, closer to how the eye sees it
+                let t = elapsed_ms % BREATH_MS;
+                let half = BREATH_MS / 2;
+                let ramp = if t < half { t } else { BREATH_MS - t };
+                return (ramp * ramp / (half * half / FULL as u32)) as u16;
+            }
+        };
+        if lit {
+            FULL
+        } else {
+            0
+        }
+    }
+}
+
+/// Who asks for a pattern, later ones win.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    /// What the system is up to
+    Status = 0,
+    /// Set from the shell or the button
+    User = 1,
+    Fault = 2,
+}
+
+pub const LAYER_COUNT: usize = 3;
+
+pub const LAYERS: [Layer; LAYER_COUNT] = [Layer::Status, Layer::User, Layer::Fault];
+
+impl Layer {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layer::Status => "status",
+            Layer::User => "user",
+            Layer::Fault => "fault",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Led {
+    /// Pattern and start of every layer
+    layers: [Option<(Pattern, u32)>; LAYER_COUNT],
+}
+
+impl Led {
+    pub const fn new() -> Self {
+        Led {
+            layers: [None; LAYER_COUNT],
+        }
+    }
+
+    /// Plays `pattern` on `layer`, it carries on if already playing there.
+    pub fn set(&mut self, layer: Layer, pattern: Pattern, now_ms: u32) {
+        if self.get(layer) != Some(pattern) {
+            self.layers[layer as usize] = Some((pattern, now_ms));
+        }
+    }
+
+    pub fn clear(&mut self, layer: Layer) {
+        self.layers[layer as usize] = None;
+    }
+
+    pub fn get(&self, layer: Layer) -> Option<Pattern> {
+        self.layers[layer as usize].map(|(pattern, _)| pattern)
+    }
+
+    /// The layer playing and its pattern, `None` with no layer set.
+    pub fn active(&self) -> Option<(Layer, Pattern)> {
+        LAYERS
+            .iter()
+            .rev()
+            .find_map(|layer| self.get(*layer).map(|pattern| (*layer, pattern)))
+    }
+
+    /// Brightness of the layer playing, dark with none.
+    pub fn level(&self, now_ms: u32) -> u16 {
+        LAYERS
+            .iter()
+            .rev()
+            .find_map(|layer| self.layers[*layer as usize])
+            .map_or(0, |(pattern, start)| {
+                pattern.level(now_ms.wrapping_sub(start))
+            })
+    }
+}
@@ -0,0 +1,21 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub mod button;
+pub mod calibrate;
+pub mod capture;
+pub mod characterize;
+#[cfg(feature = "graphics")]
+pub mod dashboard;
+pub mod encoder;
+pub mod foc;
+#[cfg(feature = "graphics")]
+pub mod frame;
+pub mod health;
+pub mod led;
+pub mod menu;
+#[cfg(feature = "graphics")]
+pub mod plot;
+pub mod sensor;
+pub mod step;
+pub mod telemetry;
+pub mod velocity;
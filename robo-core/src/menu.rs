@@ -0,0 +1,226 @@
+//! On-device menu as a pure state machine.
+//!
+//! The menu only moves between [`Screen`]s and asks for [`Action`]s, the
+//! firmware applies them and hands the resulting [`Settings`] back in. One
+//! button is enough: a short press is [`Input::Next`], a long press
+//! [`Input::Enter`] and a double click [`Input::Back`], see [`Input::from_gesture`].
+
+use crate::button::Gesture;
+
+/// Navigation input, from gestures or from dedicated buttons.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    Next,
+    Prev,
+    Enter,
+    Back,
+}
+
+impl Input {
+    /// Single button navigation, holding after a long press does nothing.
+    pub fn from_gesture(gesture: Gesture) -> Option<Input> {
+        match gesture {
+            Gesture::Short => Some(Input::Next),
+            Gesture::Long => Some(Input::Enter),
+            Gesture::Double => Some(Input::Back),
+            Gesture::Repeat => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Item {
+    /// Feed-forward speed in rpm
+    Setpoint,
+    /// Motor PWM frequency in Hz
+    PwmFrequency,
+    /// Arms or disarms the motor
+    Arm,
+    /// Runs the open loop characterization
+    Calibrate,
+}
+
+pub const ITEMS: [Item; 4] = [
+    Item::Setpoint,
+    Item::PwmFrequency,
+    Item::Arm,
+    Item::Calibrate,
+];
+
+/// Speed setpoints offered by the editor, rpm.
+pub const SETPOINT_MAX: i32 = 300;
+pub const SETPOINT_STEP: i32 = 25;
+
+/// PWM frequencies offered by the editor, Hz.
+pub const PWM_FREQUENCIES: [u32; 6] = [500, 1_000, 2_000, 5_000, 10_000, 20_000];
+
+impl Item {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Item::Setpoint => "setpoint",
+            Item::PwmFrequency => "pwm",
+            Item::Arm => "arm",
+            Item::Calibrate => "calibrate",
+        }
+    }
+
+    /// Next editor value towards `forward`, wrapping at the ends.
+    fn step(&self, value: i32, forward: bool) -> i32 {
+        let (min, max, step) = match self {
+            Item::Setpoint => (-SETPOINT_MAX, SETPOINT_MAX, SETPOINT_STEP),
+            // an index into `PWM_FREQUENCIES`
+            Item::PwmFrequency => (0, PWM_FREQUENCIES.len() as i32 - 1, 1),
+            _ => return value,
+        };
+        match forward {
+            true if value + step > max => min,
+            true => value + step,
+            false if value - step < min => max,
+            false => value - step,
+        }
+    }
+}
+
+/// What the menu shows and edits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Settings {
+    pub setpoint_rpm: i32,
+    pub pwm_hz: u32,
+    pub armed: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    SetSetpoint(i32),
+    SetPwmFrequency(u32),
+    Arm,
+    Disarm,
+    Calibrate,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Screen {
+    /// Status dashboard, the menu is closed
+    Status,
+    /// Item list with the cursor on `ITEMS[cursor]`
+    List { cursor: usize },
+    /// Editing the value of an item, applied on `Enter`
+    Edit { item: Item, value: i32 },
+    /// Asks before running an item
+    Confirm { item: Item },
+}
+
+pub struct Menu {
+    screen: Screen,
+    // list position kept while editing
+    cursor: usize,
+}
+
+impl Default for Menu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Menu {
+    pub const fn new() -> Self {
+        Menu {
+            screen: Screen::Status,
+            cursor: 0,
+        }
+    }
+
+    pub fn screen(&self) -> Screen {
+        self.screen
+    }
+
+    /// Value of an editor screen for display, in the unit of the item.
+    pub fn edit_value(item: Item, value: i32) -> i32 {
+        match item {
+            Item::PwmFrequency => PWM_FREQUENCIES[value as usize] as i32,
+            _ => value,
+        }
+    }
+
+    /// Applies one input, returns what the firmware should do.
+    pub fn input(&mut self, input: Input, settings: &Settings) -> Option<Action> {
+        let list = Screen::List {
+            cursor: self.cursor,
+        };
+        let (screen, action) = match (self.screen, input) {
+            (Screen::Status, Input::Enter) => (list, None),
+            (Screen::Status, _) => (Screen::Status, None),
+
+            (Screen::List { cursor }, Input::Next) => {
+                self.cursor = (cursor + 1) % ITEMS.len();
+                (
+                    Screen::List {
+                        cursor: self.cursor,
+                    },
+                    None,
+                )
+            }
+            (Screen::List { cursor }, Input::Prev) => {
+                self.cursor = (cursor + ITEMS.len() - 1) % ITEMS.len();
+                (
+                    Screen::List {
+                        cursor: self.cursor,
+                    },
+                    None,
+                )
+            }
+            (Screen::List { .. }, Input::Back) => (Screen::Status, None),
+            (Screen::List { cursor }, Input::Enter) => match ITEMS[cursor] {
+                Item::Setpoint => (
+                    Screen::Edit {
+                        item: Item::Setpoint,
+                        value: settings.setpoint_rpm,
+                    },
+                    None,
+                ),
+                Item::PwmFrequency => (
+                    Screen::Edit {
+                        item: Item::PwmFrequency,
+                        value: PWM_FREQUENCIES
+                            .iter()
+                            .position(|hz| *hz >= settings.pwm_hz)
+                            .unwrap_or(0) as i32,
+                    },
+                    None,
+                ),
+                Item::Arm if settings.armed => (list, Some(Action::Disarm)),
+                Item::Arm => (list, Some(Action::Arm)),
+                // the sweep spins the motor, it only runs armed
+                Item::Calibrate if settings.armed => (
+                    Screen::Confirm {
+                        item: Item::Calibrate,
+                    },
+                    None,
+                ),
+                Item::Calibrate => (list, None),
+            },
+
+            (Screen::Edit { item, value }, Input::Next | Input::Prev) => (
+                Screen::Edit {
+                    item,
+                    value: item.step(value, input == Input::Next),
+                },
+                None,
+            ),
+            (Screen::Edit { item, value }, Input::Enter) => {
+                let action = match item {
+                    Item::Setpoint => Action::SetSetpoint(value),
+                    _ => Action::SetPwmFrequency(PWM_FREQUENCIES[value as usize]),
+                };
+                (list, Some(action))
+            }
+            (Screen::Edit { .. }, Input::Back) => (list, None),
+
+            (Screen::Confirm { .. }, Input::Enter) => (Screen::Status, Some(Action::Calibrate)),
+            (Screen::Confirm { .. }, Input::Back) => (list, None),
+            (screen @ Screen::Confirm { .. }, _) => (screen, None),
+        };
+        self.screen = screen;
+        action
+    }
+}
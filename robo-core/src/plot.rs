@@ -0,0 +1,316 @@
+//! Rolling time series plot for the SSD1306.
+//!
+//! [`Plot`] keeps one column per pixel for up to [`TRACE_COUNT`] channels,
+//! each column the average of `decimation` snapshots, so the width of the
+//! screen spans [`Plot::span_ms`]. [`Plot::draw`] scales to the visible
+//! values and tells the traces apart by their line [`Pattern`].
+//!
+//! ```text
+//! +----------------------------+
+//! |__ speed -- setpoint   1.3s |
+//! |412      ___                |
+//! |     ___/   \___    __      |
+//! |- - /- - - - - -\- -  - - - |
+//! |-38                         |
+//! +----------------------------+
+//! ```
+
+use core::fmt::Write;
+
+use embedded_graphics::mono_font::ascii::FONT_4X6;
+use embedded_graphics::mono_font::{MonoTextStyle, MonoTextStyleBuilder};
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::text::{Alignment, Baseline, Text, TextStyleBuilder};
+use heapless::String;
+
+use crate::dashboard::{HEIGHT, WIDTH};
+use crate::telemetry::{ChannelSet, Snapshot};
+
+pub const TRACE_COUNT: usize = 3;
+
+/// One column per pixel.
+pub const COLUMNS: usize = WIDTH as usize;
+
+/// Rows below the legend line.
+pub const PLOT_TOP: i32 = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    Solid,
+    /// Three pixels on, three off
+    Dashed,
+    /// Every other pixel
+    Dotted,
+}
+
+/// Pattern of each trace, in channel order.
+pub const PATTERNS: [Pattern; TRACE_COUNT] = [Pattern::Solid, Pattern::Dashed, Pattern::Dotted];
+
+impl Pattern {
+    /// Whether the `n`th pixel along a trace is drawn.
+    pub fn lit(&self, n: u32) -> bool {
+        match self {
+            Pattern::Solid => true,
+            Pattern::Dashed => n % 6 < 3,
+            Pattern::Dotted => n & 1 == 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    TooManyTraces,
+}
+
+#[derive(Clone, Debug)]
+pub struct Plot {
+    rate_hz: u32,
+    channels: ChannelSet,
+    decimation: u32,
+    columns: [[f32; TRACE_COUNT]; COLUMNS],
+    // next column written
+    head: usize,
+    len: usize,
+    sums: [f32; TRACE_COUNT],
+    counts: [u32; TRACE_COUNT],
+    pending: u32,
+}
+
+impl Plot {
+    /// Plot of nothing yet, fed at `rate_hz`.
+    pub const fn new(rate_hz: u32) -> Self {
+        Plot {
+            rate_hz,
+            channels: ChannelSet::EMPTY,
+            decimation: 1,
+            columns: [[f32::NAN; TRACE_COUNT]; COLUMNS],
+            head: 0,
+            len: 0,
+            sums: [0.0; TRACE_COUNT],
+            counts: [0; TRACE_COUNT],
+            pending: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.channels.is_empty()
+    }
+
+    pub fn channels(&self) -> ChannelSet {
+        self.channels
+    }
+
+    /// Starts over with `channels`, empty turns the plot off.
+    pub fn set_channels(&mut self, channels: ChannelSet) -> Result<(), Error> {
+        if channels.len() > TRACE_COUNT {
+            return Err(Error::TooManyTraces);
+        }
+        self.channels = channels;
+        self.clear();
+        Ok(())
+    }
+
+    /// Time the whole width covers.
+    pub fn span_ms(&self) -> u32 {
+        (COLUMNS as u64 * self.decimation as u64 * 1000 / self.rate_hz as u64) as u32
+    }
+
+    /// Starts over with the time base closest to `span_ms`.
+    pub fn set_span_ms(&mut self, span_ms: u32) {
+        let samples = span_ms as u64 * self.rate_hz as u64 / 1000;
+        let decimation = (samples + COLUMNS as u64 / 2) / COLUMNS as u64;
+        self.decimation = decimation.clamp(1, u32::MAX as u64) as u32;
+        self.clear();
+    }
+
+    pub fn decimation(&self) -> u32 {
+        self.decimation
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+        self.sums = [0.0; TRACE_COUNT];
+        self.counts = [0; TRACE_COUNT];
+        self.pending = 0;
+    }
+
+    /// Adds a snapshot, every `decimation`th one closes a column.
+    ///
+    /// NaN values are left out of the average, a column without any value
+    /// leaves a gap in the trace.
+    pub fn sample(&mut self, snapshot: &Snapshot) {
+        if !self.is_enabled() {
+            return;
+        }
+        for (n, channel) in self.channels.iter().enumerate() {
+            let value = snapshot.get(channel);
+            if !value.is_nan() {
+                self.sums[n] += value;
+                self.counts[n] += 1;
+            }
+        }
+        self.pending += 1;
+        if self.pending < self.decimation {
+            return;
+        }
+
+        let column = &mut self.columns[self.head];
+        for (n, value) in column.iter_mut().enumerate() {
+            *value = match self.counts[n] {
+                0 => f32::NAN,
+                count => self.sums[n] / count as f32,
+            };
+        }
+        self.head = (self.head + 1) % COLUMNS;
+        self.len = (self.len + 1).min(COLUMNS);
+        self.sums = [0.0; TRACE_COUNT];
+        self.counts = [0; TRACE_COUNT];
+        self.pending = 0;
+    }
+
+    /// Finished columns, at most [`COLUMNS`].
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Value of `trace` in the `n`th column, oldest first.
+    pub fn value(&self, trace: usize, n: usize) -> Option<f32> {
+        if n >= self.len || trace >= self.channels.len() {
+            return None;
+        }
+        let index = (self.head + COLUMNS - self.len + n) % COLUMNS;
+        Some(self.columns[index][trace]).filter(|value| !value.is_nan())
+    }
+
+    /// Smallest and largest value shown, flat traces get some room.
+    pub fn range(&self) -> Option<(f32, f32)> {
+        let mut range: Option<(f32, f32)> = None;
+        for trace in 0..self.channels.len() {
+            for n in 0..self.len {
+                if let Some(value) = self.value(trace, n) {
+                    range = Some(match range {
+                        None => (value, value),
+                        Some((min, max)) => (min.min(value), max.max(value)),
+                    });
+                }
+            }
+        }
+        range.map(|(min, max)| match max - min {
+            span if span > 1e-6 => (min, max),
+            _ => (min - 1.0, max + 1.0),
+        })
+    }
+
+    /// Screen row of `value` within `range`.
+    pub fn row(value: f32, (min, max): (f32, f32)) -> i32 {
+        let bottom = HEIGHT as i32 - 1;
+        let rows = (bottom - PLOT_TOP) as f32;
+        bottom - ((value - min) / (max - min) * rows + 0.5) as i32
+    }
+
+    /// Clears `target` and draws the legend, the traces and the scale.
+    pub fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        target.clear(BinaryColor::Off)?;
+        self.legend(target)?;
+
+        let range = match self.range() {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+        // the newest column is on the right edge
+        let left = (COLUMNS - self.len) as i32;
+        for (trace, pattern) in PATTERNS.iter().enumerate().take(self.channels.len()) {
+            let mut drawn = 0;
+            let mut previous: Option<i32> = None;
+            for n in 0..self.len {
+                let row = match self.value(trace, n) {
+                    Some(value) => Self::row(value, range),
+                    None => {
+                        previous = None;
+                        continue;
+                    }
+                };
+                // joined to the previous column by a vertical run
+                let (top, bottom) = match previous {
+                    Some(previous) if previous < row => (previous + 1, row),
+                    Some(previous) if previous > row => (row, previous - 1),
+                    _ => (row, row),
+                };
+                for y in top..=bottom {
+                    if pattern.lit(drawn) {
+                        Pixel(Point::new(left + n as i32, y), BinaryColor::On).draw(target)?;
+                    }
+                    drawn += 1;
+                }
+                previous = Some(row);
+            }
+        }
+
+        // on a background of their own, over the traces
+        let style = MonoTextStyleBuilder::new()
+            .font(&FONT_4X6)
+            .text_color(BinaryColor::On)
+            .background_color(BinaryColor::Off)
+            .build();
+        let mut text = String::<8>::new();
+        label(&mut text, range.1);
+        Text::with_baseline(&text, Point::new(0, PLOT_TOP), style, Baseline::Top).draw(target)?;
+        text.clear();
+        label(&mut text, range.0);
+        let bottom = Point::new(0, HEIGHT as i32 - 1);
+        Text::with_baseline(&text, bottom, style, Baseline::Bottom).draw(target)?;
+        Ok(())
+    }
+
+    fn legend<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let style = MonoTextStyle::new(&FONT_4X6, BinaryColor::On);
+        let mut x = 0;
+        for (channel, pattern) in self.channels.iter().zip(PATTERNS.iter()) {
+            // a sample of the pattern in front of the name
+            for n in 0..6 {
+                if pattern.lit(n) {
+                    Pixel(Point::new(x + n as i32, 3), BinaryColor::On).draw(target)?;
+                }
+            }
+            x += 8;
+            let next = Text::with_baseline(channel.name(), Point::new(x, 0), style, Baseline::Top)
+                .draw(target)?;
+            x = next.x + 4;
+        }
+
+        let mut text = String::<8>::new();
+        match self.span_ms() {
+            ms if ms < 1000 => write!(text, "{}ms", ms).ok(),
+            ms => write!(text, "{:.1}s", ms as f32 / 1000.0).ok(),
+        };
+        let right = TextStyleBuilder::new()
+            .alignment(Alignment::Right)
+            .baseline(Baseline::Top)
+            .build();
+        let end = Point::new(WIDTH as i32 - 1, 0);
+        Text::with_text_style(&text, end, style, right).draw(target)?;
+        Ok(())
+    }
+}
+
+/// Scale label, fewer decimals the larger it gets.
+fn label<const N: usize>(text: &mut String<N>, value: f32) {
+    let magnitude = if value < 0.0 { -value } else { value };
+    match magnitude {
+        m if m >= 100.0 => write!(text, "{:.0}", value).ok(),
+        m if m >= 10.0 => write!(text, "{:.1}", value).ok(),
+        _ => write!(text, "{:.2}", value).ok(),
+    };
+}
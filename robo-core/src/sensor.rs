@@ -0,0 +1,132 @@
+//! Position and velocity sensors behind one interface.
+//!
+//! [`RotarySensor`] reads in SI units, radians from -π up to π and radians
+//! per second, and reports failures as [`Error`] whatever the sensor, so the
+//! sampler, the shell and the controllers work the same with a TLE5012, a
+//! quadrature encoder or a mock.
+
+use core::f32::consts::PI;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The transfer to the sensor failed
+    Bus,
+    /// The reply failed its check
+    Crc,
+    /// No valid value yet, after start up or a reset
+    NotReady,
+}
+
+impl Error {
+    pub const COUNT: usize = 3;
+
+    pub const ALL: [Error; Error::COUNT] = [Error::Bus, Error::Crc, Error::NotReady];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Error::Bus => "bus",
+            Error::Crc => "crc",
+            Error::NotReady => "not_ready",
+        }
+    }
+}
+
+/// What a sensor reports about itself, a set of flags.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Status(u8);
+
+impl Status {
+    pub const OK: Status = Status(0);
+    /// Magnet or signal out of range, the angle is not to be trusted
+    pub const SIGNAL_LOST: Status = Status(1);
+    /// A value did not fit its range
+    pub const OVERFLOW: Status = Status(2);
+    /// Counts or updates went missing
+    pub const DATA_LOST: Status = Status(4);
+    /// The sensor's own self test failed
+    pub const FAULT: Status = Status(8);
+
+    pub const FLAGS: [(Status, &'static str); 4] = [
+        (Status::SIGNAL_LOST, "signal_lost"),
+        (Status::OVERFLOW, "overflow"),
+        (Status::DATA_LOST, "data_lost"),
+        (Status::FAULT, "fault"),
+    ];
+
+    pub fn is_ok(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(&self, flags: Status) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+
+    pub fn insert(&mut self, flags: Status) {
+        self.0 |= flags.0;
+    }
+
+    pub const fn union(self, flags: Status) -> Status {
+        Status(self.0 | flags.0)
+    }
+
+    /// Names of the flags set.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        Status::FLAGS
+            .iter()
+            .filter(move |(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+    }
+}
+
+pub trait RotarySensor {
+    /// Shaft angle, radians from -π up to π.
+    fn angle(&mut self) -> Result<f32, Error>;
+
+    /// Shaft velocity, radians per second, positive counting up the angle.
+    fn velocity(&mut self) -> Result<f32, Error>;
+
+    fn status(&mut self) -> Result<Status, Error>;
+
+    /// Smallest step of the angle, radians.
+    fn resolution(&self) -> f32;
+
+    /// Both, as the sampler takes them every period.
+    fn read(&mut self) -> Result<Reading, Error> {
+        Ok(Reading {
+            angle: self.angle()?,
+            velocity: self.velocity()?,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Reading {
+    pub angle: f32,
+    pub velocity: f32,
+}
+
+/// Wraps `angle` into -π up to π.
+pub fn wrap(angle: f32) -> f32 {
+    let turns = angle / (2.0 * PI);
+    // rounding without std
+    let whole = (turns + if turns < 0.0 { -0.5 } else { 0.5 }) as i32 as f32;
+    let wrapped = angle - whole * 2.0 * PI;
+    if wrapped >= PI {
+        wrapped - 2.0 * PI
+    } else {
+        wrapped
+    }
+}
+
+pub fn degrees(radians: f32) -> f32 {
+    radians * (180.0 / PI)
+}
+
+pub fn radians(degrees: f32) -> f32 {
+    degrees * (PI / 180.0)
+}
+
+/// Revolutions per minute of `velocity` in radians per second.
+pub fn rpm(velocity: f32) -> f32 {
+    velocity * (60.0 / (2.0 * PI))
+}
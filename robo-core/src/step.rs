@@ -0,0 +1,152 @@
+//! Step response recording and its time domain metrics.
+
+/// Band around the steady state value the response has to stay in, the
+/// TLE5012 speed is too noisy for the usual 2 %.
+pub const SETTLING_BAND: f32 = 0.05;
+
+/// Records one value every few control ticks so that any duration fits `N`.
+pub struct StepRecorder<const N: usize> {
+    buffer: [f32; N],
+    len: usize,
+    // records wanted for the requested duration
+    wanted: usize,
+    decimation: u32,
+    countdown: u32,
+}
+
+impl<const N: usize> Default for StepRecorder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> StepRecorder<N> {
+    pub const fn new() -> Self {
+        StepRecorder {
+            buffer: [0.0; N],
+            len: 0,
+            wanted: 0,
+            decimation: 1,
+            countdown: 0,
+        }
+    }
+
+    /// Starts a recording lasting `ticks` calls to [`push`](Self::push).
+    pub fn start(&mut self, ticks: u32) {
+        self.decimation = (ticks as usize).div_ceil(N).max(1) as u32;
+        self.wanted = (ticks / self.decimation).max(1) as usize;
+        self.len = 0;
+        self.countdown = 0;
+    }
+
+    /// Drops the recording, a finished one stays readable.
+    pub fn cancel(&mut self) {
+        self.wanted = self.len;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.len < self.wanted
+    }
+
+    /// Control ticks between two records.
+    pub fn decimation(&self) -> u32 {
+        self.decimation
+    }
+
+    /// Feeds one control tick, true when this completes the recording.
+    pub fn push(&mut self, value: f32) -> bool {
+        if !self.is_running() {
+            return false;
+        }
+        if self.countdown > 0 {
+            self.countdown -= 1;
+            return false;
+        }
+        self.countdown = self.decimation - 1;
+        self.buffer[self.len] = value;
+        self.len += 1;
+        !self.is_running()
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.buffer[..self.len]
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Metrics {
+    /// Value when the step was applied
+    pub initial: f32,
+    /// Mean of the last tenth of the recording
+    pub steady_state: f32,
+    /// 10 % to 90 % of the change, seconds
+    pub rise_time: Option<f32>,
+    /// Peak beyond the steady state, percent of the change
+    pub overshoot: f32,
+    /// Time until the response stays within [`SETTLING_BAND`], seconds
+    pub settling_time: Option<f32>,
+}
+
+/// Metrics of `samples` taken every `period` seconds from the step on.
+///
+/// Samples that are NaN, like sensor read errors, are ignored. Times are
+/// `None` when the response never gets there or there is no change at all,
+/// and a response has only settled when it stays in the band over the last
+/// tenth the steady state is taken from.
+pub fn metrics(samples: &[f32], period: f32) -> Option<Metrics> {
+    let initial = samples.iter().copied().find(|value| !value.is_nan())?;
+
+    let tail_start = samples.len() - (samples.len() / 10).max(1);
+    let tail = &samples[tail_start..];
+    let (sum, count) = tail
+        .iter()
+        .filter(|value| !value.is_nan())
+        .fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+    if count == 0 {
+        return None;
+    }
+    let steady_state = sum / count as f32;
+
+    let change = steady_state - initial;
+    if change == 0.0 {
+        return Some(Metrics {
+            initial,
+            steady_state,
+            rise_time: None,
+            overshoot: 0.0,
+            settling_time: None,
+        });
+    }
+
+    // progress of the response, 0 at the initial and 1 at the steady state
+    let normalized = samples.iter().map(|value| (value - initial) / change);
+    let time = |n: usize| n as f32 * period;
+    let first_above = |level: f32| normalized.clone().position(|progress| progress >= level);
+
+    let rise_time = match (first_above(0.1), first_above(0.9)) {
+        (Some(low), Some(high)) => Some(time(high) - time(low)),
+        _ => None,
+    };
+    let peak = normalized
+        .clone()
+        .filter(|progress| !progress.is_nan())
+        .fold(0.0f32, f32::max);
+    let settling_time = normalized
+        .clone()
+        .rposition(|progress| {
+            !progress.is_nan() && !(1.0 - SETTLING_BAND..=1.0 + SETTLING_BAND).contains(&progress)
+        })
+        .map_or(Some(0.0), |n| {
+            // still leaving the band while the steady state is taken, the
+            // recording is too short or the response never settles
+            (n < tail_start).then(|| time(n + 1))
+        });
+
+    Some(Metrics {
+        initial,
+        steady_state,
+        rise_time,
+        overshoot: (peak - 1.0).max(0.0) * 100.0,
+        settling_time,
+    })
+}
@@ -0,0 +1,257 @@
+//! Binary telemetry stream.
+//!
+//! Every [`Message`] is postcard encoded, followed by a little endian
+//! CRC-16/IBM-SDLC of the payload and COBS framed. Frames start and end with
+//! a zero byte, so a receiver resynchronises on any text or noise sent
+//! between them.
+
+use crc::{Crc, CRC_16_IBM_SDLC};
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+pub const CHANNEL_COUNT: usize = 6;
+
+/// Largest postcard payload of a [`Message`].
+pub const MAX_PAYLOAD: usize = 40;
+
+// payload and CRC
+const MAX_BODY: usize = MAX_PAYLOAD + 2;
+
+/// Largest frame produced by [`encode`], delimiters included.
+pub const MAX_FRAME: usize = MAX_BODY + MAX_BODY / 254 + 1 + 2;
+
+const CRC: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_SDLC);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Channel {
+    /// Shaft angle, degrees
+    Angle = 0,
+    /// Shaft speed, degrees per second
+    Speed = 1,
+    /// Applied duty, signed fraction of full scale (positive is clockwise)
+    Duty = 2,
+    /// Motor current, amperes
+    Current = 3,
+    /// Commanded value of the active controller
+    Setpoint = 4,
+    /// Supply voltage, volts
+    Battery = 5,
+}
+
+pub const CHANNELS: [Channel; CHANNEL_COUNT] = [
+    Channel::Angle,
+    Channel::Speed,
+    Channel::Duty,
+    Channel::Current,
+    Channel::Setpoint,
+    Channel::Battery,
+];
+
+impl Channel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Channel::Angle => "angle",
+            Channel::Speed => "speed",
+            Channel::Duty => "duty",
+            Channel::Current => "current",
+            Channel::Setpoint => "setpoint",
+            Channel::Battery => "battery",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Channel> {
+        CHANNELS.iter().copied().find(|channel| channel.name() == name)
+    }
+}
+
+/// Set of channels carried by a sample, bit `n` is the channel with value `n`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelSet(u8);
+
+impl ChannelSet {
+    pub const EMPTY: ChannelSet = ChannelSet(0);
+    pub const ALL: ChannelSet = ChannelSet((1 << CHANNEL_COUNT) - 1);
+
+    pub fn contains(&self, channel: Channel) -> bool {
+        self.0 & (1 << channel as u8) != 0
+    }
+
+    pub fn insert(&mut self, channel: Channel) {
+        self.0 |= 1 << channel as u8;
+    }
+
+    pub fn remove(&mut self, channel: Channel) {
+        self.0 &= !(1 << channel as u8);
+    }
+
+    pub const fn without(self, channel: Channel) -> ChannelSet {
+        ChannelSet(self.0 & !(1 << channel as u8))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_subset(&self, other: ChannelSet) -> bool {
+        self.0 & !other.0 == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Channel> + '_ {
+        CHANNELS.iter().copied().filter(|channel| self.contains(*channel))
+    }
+
+    /// Parses a comma separated channel list like `angle,speed`.
+    pub fn parse(list: &str) -> Option<ChannelSet> {
+        let mut set = ChannelSet::EMPTY;
+        for name in list.split(',') {
+            set.insert(Channel::from_name(name.trim())?);
+        }
+        Some(set)
+    }
+}
+
+/// Value of every channel at one control tick.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub timestamp_us: u32,
+    pub values: [f32; CHANNEL_COUNT],
+}
+
+impl Snapshot {
+    pub fn get(&self, channel: Channel) -> f32 {
+        self.values[channel as usize]
+    }
+
+    pub fn set(&mut self, channel: Channel, value: f32) {
+        self.values[channel as usize] = value;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    /// Wrapping counter of sent samples, gaps mean lost frames
+    pub seq: u16,
+    pub timestamp_us: u32,
+    pub channels: ChannelSet,
+    /// Values of `channels` in channel order
+    pub values: Vec<f32, CHANNEL_COUNT>,
+}
+
+impl Sample {
+    pub fn new(seq: u16, snapshot: &Snapshot, channels: ChannelSet) -> Self {
+        Sample {
+            seq,
+            timestamp_us: snapshot.timestamp_us,
+            channels,
+            values: channels.iter().map(|channel| snapshot.get(channel)).collect(),
+        }
+    }
+
+    pub fn get(&self, channel: Channel) -> Option<f32> {
+        self.channels
+            .iter()
+            .position(|c| c == channel)
+            .and_then(|n| self.values.get(n).copied())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Message {
+    /// Sent when the stream starts and whenever its settings change
+    Config {
+        /// Rate samples are taken at before decimation
+        rate_hz: u32,
+        decimation: u16,
+        channels: ChannelSet,
+    },
+    Sample(Sample),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Message does not fit `MAX_PAYLOAD` or the output buffer
+    Encode,
+    /// Frame longer than the decoder buffer
+    Overflow,
+    Cobs,
+    Crc,
+    Decode,
+}
+
+/// Frames `message` into `out` and returns the frame length.
+pub fn encode(message: &Message, out: &mut [u8]) -> Result<usize, Error> {
+    let mut raw = [0u8; MAX_BODY];
+    let len = postcard::to_slice(message, &mut raw[..MAX_PAYLOAD])
+        .map_err(|_| Error::Encode)?
+        .len();
+    let crc = CRC.checksum(&raw[..len]);
+    raw[len..len + 2].copy_from_slice(&crc.to_le_bytes());
+
+    let body = len + 2;
+    if out.len() < cobs::max_encoding_length(body) + 2 {
+        return Err(Error::Encode);
+    }
+    out[0] = 0;
+    let encoded = cobs::encode(&raw[..body], &mut out[1..]);
+    out[encoded + 1] = 0;
+    Ok(encoded + 2)
+}
+
+/// Checks and decodes the COBS encoded body of one frame, without delimiters.
+pub fn decode(frame: &mut [u8]) -> Result<Message, Error> {
+    let len = cobs::decode_in_place(frame).map_err(|_| Error::Cobs)?;
+    if len < 2 {
+        return Err(Error::Cobs);
+    }
+    let (payload, crc) = frame[..len].split_at(len - 2);
+    if CRC.checksum(payload).to_le_bytes() != crc {
+        return Err(Error::Crc);
+    }
+    postcard::from_bytes(payload).map_err(|_| Error::Decode)
+}
+
+/// Splits a byte stream into frames and decodes them.
+pub struct Decoder<const N: usize> {
+    buffer: Vec<u8, N>,
+    overflow: bool,
+}
+
+impl<const N: usize> Default for Decoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Decoder<N> {
+    pub const fn new() -> Self {
+        Decoder {
+            buffer: Vec::new(),
+            overflow: false,
+        }
+    }
+
+    /// Returns a result whenever `byte` completes a non empty frame.
+    pub fn feed(&mut self, byte: u8) -> Option<Result<Message, Error>> {
+        if byte != 0 {
+            if self.buffer.push(byte).is_err() {
+                self.overflow = true;
+            }
+            return None;
+        }
+
+        let result = if self.overflow {
+            Some(Err(Error::Overflow))
+        } else if self.buffer.is_empty() {
+            None
+        } else {
+            Some(decode(&mut self.buffer))
+        };
+        self.buffer.clear();
+        self.overflow = false;
+        result
+    }
+}
@@ -0,0 +1,207 @@
+//! Shaft velocity from successive angle samples.
+//!
+//! The TLE5012 speed register differences the angle over a short window and
+//! is noisy at low speed. An [`Estimator`] fed every sample period derives the
+//! velocity from the angle instead, with a [`Method`] trading noise for delay
+//! through one bandwidth setting. Angles are radians, wrapped as any
+//! [`RotarySensor`](crate::sensor::RotarySensor) reads them, velocities are
+//! radians per second.
+
+use core::f32::consts::PI;
+
+use crate::sensor::{self, Error, Reading};
+
+/// Longest moving average window, samples.
+pub const AVERAGE_MAX: usize = 128;
+
+/// Lowest bandwidth, Hz. The highest is [`Estimator::max_bandwidth`].
+pub const BANDWIDTH_MIN: f32 = 1.0;
+
+/// -3 dB frequency of an `n` sample moving average is this over `n` periods.
+const AVERAGE_CORNER: f32 = 0.443;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Method {
+    /// The sensor's own velocity, passed through
+    Sensor,
+    /// Angle step over one period, no delay and all the noise
+    Difference,
+    /// Angle steps averaged over a window sized by the bandwidth
+    Average,
+    /// Alpha-beta observer, a PLL locked to the angle
+    Tracking,
+}
+
+pub const METHODS: [Method; 4] = [
+    Method::Sensor,
+    Method::Difference,
+    Method::Average,
+    Method::Tracking,
+];
+
+impl Method {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Method::Sensor => "sensor",
+            Method::Difference => "diff",
+            Method::Average => "average",
+            Method::Tracking => "tracking",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Method> {
+        METHODS.iter().copied().find(|method| method.name() == name)
+    }
+}
+
+pub struct Estimator {
+    method: Method,
+    sample_hz: f32,
+    bandwidth: f32,
+    velocity: f32,
+    /// Last good angle, None until the first
+    last: Option<f32>,
+    /// Periods since the last good angle
+    gap: u32,
+    /// Angle steps of the last periods, for the average
+    steps: [f32; AVERAGE_MAX],
+    head: usize,
+    len: usize,
+    window: usize,
+    /// Tracked angle and its gains per period
+    angle: f32,
+    alpha: f32,
+    beta: f32,
+}
+
+impl Estimator {
+    /// An estimator fed at `sample_hz`, the bandwidth is clamped to the
+    /// range it supports.
+    pub fn new(method: Method, sample_hz: u32, bandwidth: f32) -> Self {
+        let mut estimator = Estimator {
+            method,
+            sample_hz: sample_hz as f32,
+            bandwidth: 0.0,
+            velocity: 0.0,
+            last: None,
+            gap: 0,
+            steps: [0.0; AVERAGE_MAX],
+            head: 0,
+            len: 0,
+            window: 1,
+            angle: 0.0,
+            alpha: 0.0,
+            beta: 0.0,
+        };
+        estimator.set_bandwidth(bandwidth);
+        estimator
+    }
+
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// Switches method, the new one starts over from the next sample.
+    pub fn set_method(&mut self, method: Method) {
+        self.method = method;
+        self.reset();
+    }
+
+    pub fn bandwidth(&self) -> f32 {
+        self.bandwidth
+    }
+
+    /// Highest bandwidth, a twentieth of the sample rate keeps the tracking
+    /// loop well clear of instability.
+    pub fn max_bandwidth(&self) -> f32 {
+        self.sample_hz / 20.0
+    }
+
+    /// Sets the -3 dB bandwidth in Hz of the average and the tracking loop,
+    /// clamped to [`BANDWIDTH_MIN`] up to [`max_bandwidth`](Self::max_bandwidth).
+    /// The average stops growing at [`AVERAGE_MAX`] samples.
+    pub fn set_bandwidth(&mut self, hz: f32) {
+        self.bandwidth = hz.clamp(BANDWIDTH_MIN, self.max_bandwidth());
+        let window = AVERAGE_CORNER * self.sample_hz / self.bandwidth + 0.5;
+        self.window = (window as usize).clamp(1, AVERAGE_MAX);
+
+        // critically damped, the bandwidth is about 2.5 times the natural
+        // frequency
+        let omega = 2.0 * PI * self.bandwidth / 2.5;
+        let period = 1.0 / self.sample_hz;
+        self.alpha = 2.0 * omega * period;
+        self.beta = omega * omega * period * period;
+        self.reset();
+    }
+
+    /// Samples averaged by [`Method::Average`].
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Forgets the past samples.
+    pub fn reset(&mut self) {
+        self.velocity = 0.0;
+        self.last = None;
+        self.gap = 0;
+        self.len = 0;
+    }
+
+    pub fn velocity(&self) -> f32 {
+        self.velocity
+    }
+
+    /// Takes the reading of one sample period, a failed read keeps the
+    /// estimate and the next good angle makes up for the gap.
+    pub fn update(&mut self, reading: Result<Reading, Error>) -> f32 {
+        let reading = match reading {
+            Ok(reading) if !reading.angle.is_nan() => reading,
+            _ => {
+                self.gap += 1;
+                if self.method == Method::Tracking {
+                    self.angle = sensor::wrap(self.angle + self.velocity / self.sample_hz);
+                }
+                return self.velocity;
+            }
+        };
+        let periods = self.gap + 1;
+        self.gap = 0;
+        let last = match self.last.replace(reading.angle) {
+            Some(last) => last,
+            None => {
+                self.angle = reading.angle;
+                if self.method == Method::Sensor {
+                    self.velocity = reading.velocity;
+                }
+                return self.velocity;
+            }
+        };
+
+        let step = sensor::wrap(reading.angle - last) / periods as f32;
+        self.velocity = match self.method {
+            Method::Sensor => reading.velocity,
+            Method::Difference => step * self.sample_hz,
+            Method::Average => {
+                for _ in 0..periods.min(AVERAGE_MAX as u32) {
+                    self.steps[self.head] = step;
+                    self.head = (self.head + 1) % AVERAGE_MAX;
+                    self.len = (self.len + 1).min(AVERAGE_MAX);
+                }
+                let count = self.len.min(self.window);
+                let sum: f32 = (1..=count)
+                    .map(|n| self.steps[(self.head + AVERAGE_MAX - n) % AVERAGE_MAX])
+                    .sum();
+                sum / count as f32 * self.sample_hz
+            }
+            Method::Tracking => {
+                // predicted over the gap, corrected by the angle error
+                let period = 1.0 / self.sample_hz;
+                let predicted = self.angle + self.velocity * period;
+                let error = sensor::wrap(reading.angle - predicted);
+                self.angle = sensor::wrap(predicted + self.alpha * error);
+                self.velocity + self.beta * error * self.sample_hz
+            }
+        };
+        self.velocity
+    }
+}
@@ -0,0 +1,194 @@
+use robo_core::button::{Button, Gesture, Timing};
+
+const TIMING: Timing = Timing::DEFAULT;
+
+/// Feeds `(level, ms)` edges, then calls back at every deadline until `end`,
+/// collecting the gestures with the time they were reported.
+fn run(start: u32, edges: &[(bool, u32)], end: u32) -> Vec<(Gesture, u32)> {
+    let mut button = Button::new(TIMING);
+    let mut level = false;
+    let mut gestures = Vec::new();
+    let mut edges = edges.iter().peekable();
+    loop {
+        let next_edge = edges.peek().map(|(_, at)| start.wrapping_add(*at));
+        let next = match (next_edge, button.deadline()) {
+            (Some(edge), Some(deadline)) if (deadline.wrapping_sub(edge) as i32) < 0 => deadline,
+            (Some(edge), _) => edge,
+            (None, Some(deadline)) => deadline,
+            (None, None) => break,
+        };
+        if next.wrapping_sub(start) > end {
+            break;
+        }
+        let now = next;
+        if next_edge == Some(now) {
+            level = edges.next().unwrap().0;
+        }
+        if let Some(gesture) = button.update(level, now) {
+            gestures.push((gesture, now.wrapping_sub(start)));
+        }
+    }
+    gestures
+}
+
+fn names(gestures: &[(Gesture, u32)]) -> Vec<Gesture> {
+    gestures.iter().map(|(gesture, _)| *gesture).collect()
+}
+
+#[test]
+fn short_press_waits_for_a_second_one() {
+    let gestures = run(0, &[(true, 100), (false, 200)], 2_000);
+    // reported once the double click window after the release is over
+    assert_eq!(gestures, [(Gesture::Short, 200 + TIMING.double_ms)]);
+}
+
+#[test]
+fn bounces_are_ignored() {
+    let edges = [
+        (true, 100),
+        (false, 102),
+        (true, 105),
+        (false, 107),
+        (true, 110),
+        (false, 300),
+        (true, 303),
+        (false, 305),
+    ];
+    let gestures = run(0, &edges, 2_000);
+    // the release counts from the last bounce
+    assert_eq!(gestures, [(Gesture::Short, 305 + TIMING.double_ms)]);
+}
+
+#[test]
+fn glitch_shorter_than_debounce_is_no_press() {
+    assert_eq!(run(0, &[(true, 100), (false, 110)], 2_000), []);
+}
+
+#[test]
+fn long_press_is_reported_while_held() {
+    let gestures = run(0, &[(true, 100), (false, 800)], 2_000);
+    assert_eq!(gestures, [(Gesture::Long, 100 + TIMING.long_ms)]);
+}
+
+#[test]
+fn holding_repeats() {
+    let gestures = run(0, &[(true, 0), (false, 1_100)], 2_000);
+    let long = TIMING.long_ms;
+    let repeat = TIMING.repeat_ms;
+    assert_eq!(
+        gestures,
+        [
+            (Gesture::Long, long),
+            (Gesture::Repeat, long + repeat),
+            (Gesture::Repeat, long + 2 * repeat),
+            (Gesture::Repeat, long + 3 * repeat),
+        ]
+    );
+}
+
+#[test]
+fn double_click() {
+    let edges = [(true, 0), (false, 100), (true, 200), (false, 300)];
+    // once the release is debounced
+    let at = 300 + TIMING.debounce_ms;
+    assert_eq!(run(0, &edges, 2_000), [(Gesture::Double, at)]);
+}
+
+#[test]
+fn slow_second_click_is_two_short_presses() {
+    let edges = [(true, 0), (false, 100), (true, 500), (false, 600)];
+    assert_eq!(
+        names(&run(0, &edges, 2_000)),
+        [Gesture::Short, Gesture::Short]
+    );
+}
+
+#[test]
+fn click_then_hold_is_short_then_long() {
+    let edges = [(true, 0), (false, 100), (true, 200), (false, 900)];
+    let gestures = run(0, &edges, 2_000);
+    assert_eq!(
+        gestures,
+        [
+            (Gesture::Short, 200 + TIMING.long_ms),
+            (Gesture::Long, 200 + TIMING.long_ms)
+        ]
+    );
+}
+
+#[test]
+fn immediate_short_without_double_click() {
+    let timing = Timing {
+        double_ms: 0,
+        repeat_ms: 0,
+        ..TIMING
+    };
+    let mut button = Button::new(timing);
+    assert_eq!(button.update(true, 0), None);
+    assert_eq!(button.update(true, 20), None);
+    assert!(button.is_pressed());
+    assert_eq!(button.update(false, 100), None);
+    assert_eq!(button.update(false, 120), Some(Gesture::Short));
+    assert_eq!(button.deadline(), None);
+
+    // no repeats after a long press
+    button.update(true, 1_000);
+    button.update(true, 1_020);
+    assert_eq!(button.update(true, 1_600), Some(Gesture::Long));
+    assert_eq!(button.deadline(), None);
+}
+
+#[test]
+fn idle_button_has_no_deadline() {
+    let mut button = Button::new(TIMING);
+    assert_eq!(button.deadline(), None);
+    button.update(true, 10);
+    assert_eq!(button.deadline(), Some(10 + TIMING.debounce_ms));
+    button.update(true, 30);
+    assert_eq!(button.deadline(), Some(10 + TIMING.long_ms));
+}
+
+#[test]
+fn survives_the_millisecond_counter_wrapping() {
+    let start = u32::MAX - 150;
+    let edges = [(true, 0), (false, 100), (true, 200), (false, 300)];
+    let at = 300 + TIMING.debounce_ms;
+    assert_eq!(run(start, &edges, 2_000), [(Gesture::Double, at)]);
+
+    let gestures = run(start, &[(true, 100), (false, 800)], 2_000);
+    assert_eq!(gestures, [(Gesture::Long, 100 + TIMING.long_ms)]);
+}
+
+#[test]
+fn poll_is_scheduled_once_per_deadline() {
+    let mut button = Button::new(TIMING);
+    assert_eq!(button.poll_deadline(0), None);
+
+    button.update(true, 10);
+    assert_eq!(button.poll_deadline(10), Some(TIMING.debounce_ms));
+    // a bounce before the poll comes does not schedule another
+    button.update(true, 15);
+    assert_eq!(button.poll_deadline(15), None);
+
+    // the debounce poll, then the long press one
+    button.update(true, 30);
+    assert_eq!(button.poll_deadline(30), Some(TIMING.long_ms - 20));
+    assert_eq!(button.poll_deadline(40), None);
+}
+
+#[test]
+fn passed_deadline_is_polled_right_away() {
+    let mut button = Button::new(TIMING);
+    button.update(true, 0);
+    assert_eq!(button.poll_deadline(0), Some(TIMING.debounce_ms));
+    // the poll came late and has not been fed yet, still due
+    assert_eq!(button.poll_deadline(50), Some(0));
+    assert_eq!(button.poll_deadline(50), Some(0));
+
+    // across the wrap of the counter
+    let mut button = Button::new(TIMING);
+    let start = u32::MAX - 5;
+    button.update(true, start);
+    assert_eq!(button.poll_deadline(start), Some(TIMING.debounce_ms));
+    assert_eq!(button.poll_deadline(start.wrapping_add(30)), Some(0));
+}
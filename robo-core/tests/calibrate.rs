@@ -0,0 +1,166 @@
+use core::f32::consts::PI;
+
+use robo_core::calibrate::{AngleCorrection, Calibration, HARMONICS};
+use robo_core::characterize::SweepStep;
+use robo_core::sensor;
+
+const SETTLE: u32 = 50;
+const TIMEOUT: u32 = 5_000;
+
+/// Radians per tick at full duty.
+const GAIN: f32 = 0.02;
+
+/// Error of a magnet off the axis and slightly tilted.
+fn error(angle: f32) -> f32 {
+    0.03 * (angle + 0.5).sin() + 0.01 * (2.0 * angle).cos()
+}
+
+/// Turns a motor at constant speed under `calibration`, the sensor reading
+/// `delay` ticks late. Returns the shaft angle at the end.
+fn run(calibration: &mut Calibration, start: f32, duty: f32, delay: f32) -> (SweepStep, f32) {
+    let mut shaft = start;
+    let measure = |shaft: f32, speed: f32| {
+        let late = shaft - speed * delay;
+        sensor::wrap(late + error(late))
+    };
+    calibration.start(measure(shaft, 0.0), duty);
+    let mut duty = calibration.duty();
+    for _ in 0..4 * TIMEOUT {
+        let speed = duty * GAIN;
+        shaft += speed;
+        match calibration.tick(measure(shaft, speed)) {
+            SweepStep::Hold => {}
+            SweepStep::Duty(next) => duty = next,
+            SweepStep::Done => return (SweepStep::Done, shaft),
+        }
+    }
+    (SweepStep::Hold, shaft)
+}
+
+#[test]
+fn none_reads_the_sensor() {
+    let none = AngleCorrection::NONE;
+    assert_eq!(none.error(1.0), 0.0);
+    assert_eq!(none.apply(1.0), 1.0);
+    assert_eq!(none.peak_error(), 0.0);
+}
+
+#[test]
+fn corrects_a_known_error() {
+    let mut correction = AngleCorrection::NONE;
+    correction.sin[0] = 0.05;
+    assert!((correction.error(PI / 2.0) - 0.05).abs() < 1e-3);
+    assert!((correction.apply(PI / 2.0) - (PI / 2.0 - 0.05)).abs() < 1e-3);
+    assert!((correction.peak_error() - 0.05).abs() < 1e-3);
+
+    // the zero wraps with the angle
+    let zeroed = correction.with_zero_at(PI / 2.0);
+    assert!(zeroed.apply(PI / 2.0).abs() < 1e-3);
+    assert!((zeroed.apply(-PI / 2.0) - (-PI + 0.1)).abs() < 2e-3);
+}
+
+#[test]
+fn fits_the_error_and_zero() {
+    let mut calibration = Calibration::new(SETTLE, TIMEOUT);
+    let (step, _) = run(&mut calibration, 1.0, 0.3, 2.0);
+    assert_eq!(step, SweepStep::Done);
+    assert!(!calibration.is_running());
+    let correction = calibration.result().unwrap();
+
+    // left over error over a turn, 0.03 before
+    let mut worst = 0.0f32;
+    for n in 0..360 {
+        let shaft = sensor::radians(n as f32) - PI;
+        let corrected = correction.apply(sensor::wrap(shaft + error(shaft)));
+        let off = sensor::wrap(corrected - sensor::wrap(shaft - 1.0));
+        worst = worst.max(off.abs());
+    }
+    assert!(worst < 2e-3, "{}", worst);
+    assert!(correction.peak_error() > 0.03);
+    // higher harmonics stay small
+    assert!(correction.cos[HARMONICS - 1].abs() < 2e-3);
+}
+
+#[test]
+fn turns_one_way_then_the_other() {
+    let mut calibration = Calibration::new(SETTLE, TIMEOUT);
+    calibration.start(0.0, -0.4);
+    assert_eq!(calibration.duty(), 0.4);
+
+    // a turn clockwise in about 100 ticks after settling
+    let mut angle = 0.0;
+    let mut ticks = 0;
+    let step = loop {
+        angle = sensor::wrap(angle + 2.0 * PI / 100.0);
+        ticks += 1;
+        match calibration.tick(angle) {
+            SweepStep::Hold => {}
+            step => break step,
+        }
+    };
+    assert_eq!(step, SweepStep::Duty(0.0));
+    assert!((SETTLE + 99..=SETTLE + 101).contains(&ticks), "{}", ticks);
+    assert_eq!(calibration.progress(), (1, 0.0));
+
+    // stopped, then counter-clockwise
+    for _ in 1..SETTLE {
+        assert_eq!(calibration.tick(angle), SweepStep::Hold);
+    }
+    assert_eq!(calibration.tick(angle), SweepStep::Duty(-0.4));
+}
+
+#[test]
+fn a_stalled_motor_fails() {
+    let mut calibration = Calibration::new(SETTLE, TIMEOUT);
+    calibration.start(0.5, 0.1);
+    let mut ticks = 0;
+    while calibration.tick(0.5) != SweepStep::Done {
+        ticks += 1;
+        assert!(ticks <= SETTLE + TIMEOUT);
+    }
+    assert_eq!(calibration.result(), None);
+}
+
+#[test]
+fn a_cancelled_run_stops_driving() {
+    let mut calibration = Calibration::new(SETTLE, TIMEOUT);
+    calibration.start(0.0, 0.4);
+    let mut angle = 0.0;
+    for _ in 0..SETTLE + 20 {
+        angle = sensor::wrap(angle + 0.01);
+        assert_eq!(calibration.tick(angle), SweepStep::Hold);
+    }
+    calibration.cancel();
+    assert!(!calibration.is_running());
+
+    // the motor is left to the caller, no stop or next direction comes
+    for _ in 0..2 * (SETTLE + TIMEOUT) {
+        angle = sensor::wrap(angle + 0.01);
+        assert_eq!(calibration.tick(angle), SweepStep::Hold);
+    }
+    assert_eq!(calibration.result(), None);
+}
+
+#[test]
+fn failed_reads_are_skipped() {
+    let mut calibration = Calibration::new(SETTLE, TIMEOUT);
+    calibration.start(0.0, 0.5);
+    let mut angle = 0.0;
+    let mut n = 0;
+    loop {
+        n += 1;
+        let direction = if calibration.duty() > 0.0 { 1.0 } else { -1.0 };
+        angle = sensor::wrap(angle + direction * 0.01);
+        // every tenth read fails
+        let read = if n % 10 == 0 { f32::NAN } else { angle };
+        if calibration.tick(read) == SweepStep::Done {
+            break;
+        }
+    }
+    let correction = calibration.result().unwrap();
+    assert!(
+        correction.peak_error() < 1e-3,
+        "{}",
+        correction.peak_error()
+    );
+}
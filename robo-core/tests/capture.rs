@@ -0,0 +1,145 @@
+use robo_core::capture::{Capture, Edge, Error, State, Trigger};
+use robo_core::telemetry::{Channel, ChannelSet, Snapshot};
+
+/// Angle and duty, 20 records of 2 values
+type TestCapture = Capture<40>;
+
+fn channels() -> ChannelSet {
+    let mut channels = ChannelSet::EMPTY;
+    channels.insert(Channel::Angle);
+    channels.insert(Channel::Duty);
+    channels
+}
+
+fn trigger(edge: Edge, level: f32) -> Trigger {
+    Trigger {
+        channel: Channel::Angle,
+        edge,
+        level,
+    }
+}
+
+/// Angle `value` and duty twice it, stamped a millisecond per `n`.
+fn snapshot(n: u32, value: f32) -> Snapshot {
+    let mut snapshot = Snapshot {
+        timestamp_us: n * 1000,
+        ..Snapshot::default()
+    };
+    snapshot.set(Channel::Angle, value);
+    snapshot.set(Channel::Duty, 2.0 * value);
+    snapshot
+}
+
+fn feed(capture: &mut TestCapture, values: impl IntoIterator<Item = f32>) {
+    for (n, value) in values.into_iter().enumerate() {
+        capture.sample(&snapshot(n as u32, value));
+    }
+}
+
+/// Angles of the finished capture with their offsets.
+fn angles(capture: &TestCapture) -> Vec<(i32, f32)> {
+    capture
+        .records()
+        .map(|record| (record.offset, record.values[0]))
+        .collect()
+}
+
+#[test]
+fn rising_trigger_keeps_the_pretrigger_records() {
+    let mut capture = TestCapture::new();
+    capture
+        .arm(channels(), trigger(Edge::Rising, 30.0), 25)
+        .unwrap();
+    assert_eq!(capture.depth(), 20);
+    assert_eq!(capture.pretrigger(), 5);
+
+    // a ramp, the ring wraps twice before the capture ends
+    feed(&mut capture, (0..100).map(|n| n as f32));
+    assert_eq!(capture.state(), State::Done);
+    assert_eq!(capture.trigger_us(), 30_000);
+
+    let expected: Vec<_> = (0..20).map(|n| (n - 5, (25 + n) as f32)).collect();
+    assert_eq!(angles(&capture), expected);
+    for record in capture.records() {
+        assert_eq!(record.values[1], 2.0 * record.values[0]);
+    }
+}
+
+#[test]
+fn falling_trigger_ignores_rising_edges() {
+    let mut capture = TestCapture::new();
+    capture
+        .arm(channels(), trigger(Edge::Falling, 5.0), 50)
+        .unwrap();
+
+    // up through the level, then down onto it at the 15th sample
+    let values = (0..10).chain((0..10).rev()).chain(0..30);
+    feed(&mut capture, values.map(|n| n as f32));
+    assert_eq!(capture.state(), State::Done);
+    assert_eq!(capture.trigger_us(), 14_000);
+
+    let trigger = capture.record(capture.pretrigger()).unwrap();
+    assert_eq!((trigger.offset, trigger.values[0]), (0, 5.0));
+    let before = capture.record(capture.pretrigger() - 1).unwrap();
+    assert_eq!(before.values[0], 6.0);
+}
+
+#[test]
+fn trigger_waits_for_the_pretrigger_records() {
+    let mut capture = TestCapture::new();
+    capture
+        .arm(channels(), trigger(Edge::Rising, 1.0), 25)
+        .unwrap();
+
+    // crossing on the second sample, too early for 5 records before it
+    feed(&mut capture, [0.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    assert_eq!(capture.state(), State::Armed);
+    // a NaN from a failed sensor read does not cross either
+    feed(&mut capture, [f32::NAN, 2.0, 0.0, 3.0]);
+    assert_eq!(capture.state(), State::Triggered);
+    assert_eq!(capture.filled(), 10);
+}
+
+#[test]
+fn pretrigger_is_clamped_to_the_depth() {
+    let mut capture = TestCapture::new();
+    capture
+        .arm(channels(), trigger(Edge::Rising, 50.0), 200)
+        .unwrap();
+    // the trigger record is always kept
+    assert_eq!(capture.pretrigger(), 19);
+    feed(&mut capture, (0..60).map(|n| n as f32));
+    assert_eq!(capture.state(), State::Done);
+    assert_eq!(angles(&capture).last(), Some(&(0, 50.0)));
+
+    capture
+        .arm(channels(), trigger(Edge::Rising, 50.0), 0)
+        .unwrap();
+    assert_eq!(capture.pretrigger(), 0);
+    feed(&mut capture, (0..80).map(|n| n as f32));
+    assert_eq!(angles(&capture).first(), Some(&(0, 50.0)));
+    assert_eq!(angles(&capture).last(), Some(&(19, 69.0)));
+}
+
+#[test]
+fn records_only_from_a_finished_capture() {
+    let mut capture = TestCapture::new();
+    assert_eq!(
+        capture.arm(ChannelSet::EMPTY, trigger(Edge::Rising, 0.0), 0),
+        Err(Error::NoChannels)
+    );
+    assert_eq!(capture.state(), State::Idle);
+
+    capture
+        .arm(channels(), trigger(Edge::Rising, 10.0), 10)
+        .unwrap();
+    feed(&mut capture, (0..15).map(|n| n as f32));
+    assert_eq!(capture.state(), State::Triggered);
+    assert!(capture.record(0).is_none());
+
+    capture.stop();
+    assert_eq!(capture.state(), State::Idle);
+    feed(&mut capture, (0..100).map(|n| n as f32));
+    assert_eq!(capture.state(), State::Idle);
+    assert_eq!(capture.filled(), 0);
+}
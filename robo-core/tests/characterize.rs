@@ -0,0 +1,142 @@
+use robo_core::characterize::{
+    fit, Compensation, Curve, Sweep, SweepStep, CURVE_POINTS, SWEEP_STEPS,
+};
+
+const SETTLE: u32 = 2;
+const MEASURE: u32 = 3;
+
+/// Motor turning at 500 rpm per unit of duty beyond a 0.22 deadband.
+fn motor(duty: f32) -> f32 {
+    let magnitude = duty.abs();
+    let rpm = if magnitude > 0.22 {
+        (magnitude - 0.22) * 500.0
+    } else {
+        0.0
+    };
+    rpm.copysign(duty)
+}
+
+fn near(value: f32, expected: f32) -> bool {
+    (value - expected).abs() < 1e-3
+}
+
+/// Runs a sweep on `motor` to the end, returning the ticks it took.
+fn sweep(sweep: &mut Sweep, motor: impl Fn(f32) -> f32) -> u32 {
+    sweep.start();
+    let mut duty = sweep.duty();
+    for ticks in 1.. {
+        match sweep.tick(motor(duty)) {
+            SweepStep::Hold => {}
+            SweepStep::Duty(next) => duty = next,
+            SweepStep::Done => return ticks,
+        }
+        assert!(ticks < 10_000, "sweep does not end");
+    }
+    unreachable!()
+}
+
+fn fitted() -> Curve {
+    let mut speeds = [0.0; SWEEP_STEPS + 1];
+    for (step, speed) in speeds.iter_mut().enumerate() {
+        *speed = motor(step as f32 / SWEEP_STEPS as f32);
+    }
+    fit(&speeds).unwrap()
+}
+
+#[test]
+fn sweep_measures_both_directions() {
+    let mut sweeper = Sweep::new(SETTLE, MEASURE);
+    let ticks = sweep(&mut sweeper, motor);
+    assert!(!sweeper.is_running());
+
+    // each stop settles 4 times as long
+    let steps = SWEEP_STEPS as u32 * (SETTLE + MEASURE) + 4 * SETTLE + MEASURE;
+    assert_eq!(ticks, 2 * steps);
+    for step in 0..=SWEEP_STEPS {
+        let expected = motor(step as f32 / SWEEP_STEPS as f32);
+        // magnitudes in both directions
+        assert!(near(sweeper.speeds()[0][step], expected), "{}", step);
+        assert!(near(sweeper.speeds()[1][step], expected), "{}", step);
+    }
+}
+
+#[test]
+fn sweep_skips_lost_readings() {
+    let mut sweeper = Sweep::new(SETTLE, MEASURE);
+    // every reading lost at 0.5 and above 0.7 clockwise
+    sweep(&mut sweeper, |duty| {
+        if duty == 0.5 || duty > 0.7 {
+            f32::NAN
+        } else {
+            motor(duty)
+        }
+    });
+    assert!(sweeper.speeds()[0][10].is_nan());
+    assert!(sweeper.speeds()[0][SWEEP_STEPS].is_nan());
+    assert!(near(sweeper.speeds()[1][10], motor(0.5)));
+
+    // the fit bridges the gaps with the speed before them
+    let curve = fit(&sweeper.speeds()[0]).unwrap();
+    assert!(curve.is_valid());
+    assert!(curve.speeds.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert!(near(curve.speeds[CURVE_POINTS - 1], motor(0.7)));
+}
+
+#[test]
+fn fit_finds_the_deadband_and_gain() {
+    let curve = fitted();
+    // the first step turning faster than 5 rpm
+    assert!(near(curve.deadband, 0.25));
+    for n in 0..CURVE_POINTS {
+        assert!(near(curve.speeds[n], motor(curve.duty(n))), "{}", n);
+    }
+    assert!(near(curve.duty(CURVE_POINTS - 1), 1.0));
+}
+
+#[test]
+fn stalled_motor_has_no_curve() {
+    assert_eq!(fit(&[0.0; SWEEP_STEPS + 1]), None);
+    assert_eq!(fit(&[f32::NAN; SWEEP_STEPS + 1]), None);
+    // turning only at the stop, coasting from before the sweep
+    let mut coasting = [0.0; SWEEP_STEPS + 1];
+    coasting[0] = 200.0;
+    assert_eq!(fit(&coasting), None);
+}
+
+#[test]
+fn duty_for_inverts_the_curve() {
+    let curve = fitted();
+    for rpm in [15.0, 100.0, 250.0, 390.0] {
+        let duty = curve.duty_for(rpm).unwrap();
+        assert!(near(duty, 0.22 + rpm / 500.0), "{} {}", rpm, duty);
+    }
+    // out of reach
+    assert_eq!(curve.duty_for(391.0), None);
+}
+
+#[test]
+fn duty_for_at_and_below_the_deadband() {
+    let curve = fitted();
+    // slower than it turns at the deadband, the deadband keeps it turning
+    assert_eq!(curve.duty_for(curve.speeds[0]), Some(curve.deadband));
+    assert_eq!(curve.duty_for(1.0), Some(curve.deadband));
+    assert_eq!(curve.duty_for(0.0), Some(0.0));
+
+    assert_eq!(Curve::NONE.duty_for(10.0), None);
+}
+
+#[test]
+fn compensation_follows_the_direction() {
+    let compensation = Compensation {
+        cw: fitted(),
+        ccw: Curve::NONE,
+    };
+    assert!(near(compensation.duty_for(100.0).unwrap(), 0.42));
+    assert_eq!(compensation.duty_for(-100.0), None);
+
+    let both = Compensation {
+        cw: fitted(),
+        ccw: fitted(),
+    };
+    assert!(near(both.duty_for(-100.0).unwrap(), -0.42));
+}
@@ -0,0 +1,75 @@
+//! Helpers shared by the rendering tests.
+#![allow(dead_code)]
+
+use core::convert::Infallible;
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+
+use robo_core::dashboard::{draw, Status, HEIGHT, WIDTH};
+
+/// Simulated SSD1306 buffer, remembering pixels drawn off screen.
+pub struct Framebuffer {
+    pub pixels: [[bool; WIDTH as usize]; HEIGHT as usize],
+    pub outside: usize,
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        Framebuffer {
+            pixels: [[false; WIDTH as usize]; HEIGHT as usize],
+            outside: 0,
+        }
+    }
+
+    pub fn render(status: &Status) -> Self {
+        let mut framebuffer = Framebuffer::new();
+        draw(&mut framebuffer, status).unwrap();
+        framebuffer
+    }
+
+    pub fn get(&self, point: Point) -> bool {
+        self.pixels[point.y as usize][point.x as usize]
+    }
+
+    /// Lit pixels in rows `top..bottom`.
+    pub fn lit(&self, top: i32, bottom: i32) -> usize {
+        self.pixels[top as usize..bottom as usize]
+            .iter()
+            .map(|row| row.iter().filter(|pixel| **pixel).count())
+            .sum()
+    }
+
+    /// Rows holding a pixel that differs from `other`.
+    pub fn changed_rows(&self, other: &Framebuffer) -> Vec<usize> {
+        (0..HEIGHT as usize)
+            .filter(|y| self.pixels[*y] != other.pixels[*y])
+            .collect()
+    }
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Infallible>
+    where
+        I: IntoIterator<Item = Pixel<BinaryColor>>,
+    {
+        for Pixel(point, color) in pixels {
+            match (usize::try_from(point.x), usize::try_from(point.y)) {
+                (Ok(x), Ok(y)) if x < WIDTH as usize && y < HEIGHT as usize => {
+                    self.pixels[y][x] = color.is_on()
+                }
+                _ => self.outside += 1,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        Size::new(WIDTH, HEIGHT)
+    }
+}
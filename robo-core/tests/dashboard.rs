@@ -0,0 +1,160 @@
+mod common;
+
+use embedded_graphics::prelude::*;
+
+use common::Framebuffer;
+use robo_core::dashboard::{
+    draw, draw_menu, needle_end, Status, DIAL_CENTER, DIAL_DIAMETER, FAULT_BAR_Y, HEIGHT, WIDTH,
+};
+use robo_core::menu::{Item, Screen, Settings, ITEMS, SETPOINT_MAX};
+
+const IDLE: Status = Status {
+    state: "hard_brake",
+    duty: 0.0,
+    speed: 0.0,
+    angle: 0.0,
+    battery: 7.4,
+    faults: &[],
+};
+
+#[test]
+fn stays_on_screen() {
+    let busy = Status {
+        state: "ccw",
+        duty: -1.0,
+        speed: -12345.0,
+        angle: 359.9,
+        battery: 12.6,
+        faults: &["stack_low", "magnet_loss", "spi_crc", "overcurrent"],
+    };
+    for status in [IDLE, busy] {
+        assert_eq!(Framebuffer::render(&status).outside, 0);
+    }
+}
+
+#[test]
+fn clears_previous_frame() {
+    let mut framebuffer = Framebuffer::render(&Status {
+        faults: &["stack_low"],
+        ..IDLE
+    });
+    draw(&mut framebuffer, &IDLE).unwrap();
+    assert_eq!(framebuffer.changed_rows(&Framebuffer::render(&IDLE)), []);
+}
+
+#[test]
+fn needle_follows_angle() {
+    let radius = DIAL_DIAMETER as i32 / 2;
+    for (angle, direction) in [
+        (0.0, Point::new(0, -1)),
+        (90.0, Point::new(1, 0)),
+        (180.0, Point::new(0, 1)),
+        (270.0, Point::new(-1, 0)),
+        (-90.0, Point::new(-1, 0)),
+    ] {
+        let end = needle_end(angle);
+        let offset = end - DIAL_CENTER;
+        assert_eq!(offset.x.signum(), direction.x, "angle {}", angle);
+        assert_eq!(offset.y.signum(), direction.y, "angle {}", angle);
+        assert!(offset.x.abs().max(offset.y.abs()) < radius);
+
+        let framebuffer = Framebuffer::render(&Status { angle, ..IDLE });
+        assert!(framebuffer.get(end), "angle {}", angle);
+    }
+}
+
+#[test]
+fn unknown_angle_has_no_needle() {
+    let framebuffer = Framebuffer::render(&Status {
+        angle: f32::NAN,
+        ..IDLE
+    });
+    assert!(!framebuffer.get(needle_end(0.0)));
+    assert!(!framebuffer.get(DIAL_CENTER));
+}
+
+#[test]
+fn readings_only_change_their_line() {
+    let idle = Framebuffer::render(&IDLE);
+    let lines = [
+        (
+            Status {
+                state: "cw",
+                ..IDLE
+            },
+            0..11,
+        ),
+        (Status { duty: 0.45, ..IDLE }, 11..22),
+        (
+            Status {
+                speed: 1234.0,
+                ..IDLE
+            },
+            22..33,
+        ),
+        (
+            Status {
+                battery: f32::NAN,
+                ..IDLE
+            },
+            33..44,
+        ),
+    ];
+    for (status, rows) in lines {
+        let changed = Framebuffer::render(&status).changed_rows(&idle);
+        assert!(!changed.is_empty(), "{:?}", status);
+        assert!(changed.iter().all(|y| rows.contains(y)), "{:?}", status);
+    }
+}
+
+#[test]
+fn faults_invert_the_bar() {
+    let bar = FAULT_BAR_Y + 1;
+    let width = WIDTH as usize;
+
+    let ok = Framebuffer::render(&IDLE);
+    assert!(ok.lit(bar, HEIGHT as i32) < width);
+
+    let fault = Framebuffer::render(&Status {
+        faults: &["stack_low"],
+        ..IDLE
+    });
+    // mostly lit, the name is cut out of it
+    let lit = fault.lit(bar, HEIGHT as i32);
+    assert!(lit > width * 8 && lit < width * (HEIGHT as i32 - bar) as usize);
+    assert_eq!(fault.lit(0, FAULT_BAR_Y), ok.lit(0, FAULT_BAR_Y));
+}
+
+#[test]
+fn menu_screens_stay_on_screen() {
+    let settings = Settings {
+        setpoint_rpm: -SETPOINT_MAX,
+        pwm_hz: 20_000,
+        armed: true,
+    };
+    let mut screens = vec![Screen::Confirm {
+        item: Item::Calibrate,
+    }];
+    screens.extend((0..ITEMS.len()).map(|cursor| Screen::List { cursor }));
+    screens.extend([
+        Screen::Edit {
+            item: Item::Setpoint,
+            value: -SETPOINT_MAX,
+        },
+        Screen::Edit {
+            item: Item::PwmFrequency,
+            value: 5,
+        },
+    ]);
+    for screen in screens {
+        let mut blank = Framebuffer::new();
+        draw_menu(&mut blank, screen, &settings).unwrap();
+        assert_eq!(blank.outside, 0, "{:?}", screen);
+        assert!(blank.lit(0, HEIGHT as i32) > 0, "{:?}", screen);
+
+        // nothing of the dashboard is left
+        let mut framebuffer = Framebuffer::render(&IDLE);
+        draw_menu(&mut framebuffer, screen, &settings).unwrap();
+        assert_eq!(framebuffer.changed_rows(&blank), [], "{:?}", screen);
+    }
+}
@@ -0,0 +1,127 @@
+use robo_core::encoder::{Quadrature, SPEED_MIN_COUNTS, SPEED_SAMPLES};
+
+/// 1000 line encoder.
+const CPR: u32 = 4000;
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-3 * b.abs().max(1.0)
+}
+
+/// Turns at `counts_per_ms` for `ms`, updated every millisecond.
+fn spin(encoder: &mut Quadrature, raw: &mut u16, counts_per_ms: i32, ms: u32, start_us: u32) {
+    for n in 1..=ms {
+        *raw = raw.wrapping_add(counts_per_ms as u16);
+        encoder.update(*raw, start_us.wrapping_add(n * 1000));
+    }
+}
+
+#[test]
+fn extends_across_the_timer_wrapping() {
+    let mut encoder = Quadrature::new(CPR, 65_530);
+    encoder.update(4, 0);
+    assert_eq!(encoder.position(), 10);
+    encoder.update(65_500, 1000);
+    assert_eq!(encoder.position(), -30);
+
+    // many turns forward
+    let mut raw: u16 = 65_500;
+    for n in 0..100 {
+        raw = raw.wrapping_add(30_000);
+        encoder.update(raw, 2000 + n);
+    }
+    assert_eq!(encoder.position(), -30 + 3_000_000);
+}
+
+#[test]
+fn angle_from_the_start_until_an_index() {
+    let mut encoder = Quadrature::new(CPR, 100);
+    assert!(!encoder.is_referenced());
+    encoder.update(1100, 0);
+    assert!(close(encoder.angle(), 90.0));
+    encoder.update(2100, 0);
+    // half a turn is the bottom of the range
+    assert!(close(encoder.angle(), -180.0));
+    encoder.update(100u16.wrapping_sub(1000), 0);
+    assert!(close(encoder.angle(), -90.0));
+}
+
+#[test]
+fn index_sets_the_zero() {
+    let mut encoder = Quadrature::new(CPR, 0);
+    encoder.update(500, 0);
+    // latched before the update that follows
+    encoder.on_index(520);
+    encoder.update(1520, 0);
+    assert!(encoder.is_referenced());
+    assert!(close(encoder.angle(), 90.0));
+    assert_eq!(encoder.index_errors(), 0);
+}
+
+#[test]
+fn lost_counts_show_at_the_next_index() {
+    let mut encoder = Quadrature::new(CPR, 0);
+    encoder.on_index(0);
+    // a turn later, give or take a count
+    encoder.update(2000, 0);
+    encoder.on_index(4001);
+    encoder.update(6000, 0);
+    encoder.on_index(8000);
+    assert_eq!(encoder.index_errors(), 0);
+
+    // 20 counts short of a turn
+    encoder.update(10_000, 0);
+    encoder.on_index(11_979);
+    assert_eq!(encoder.index_errors(), 1);
+    encoder.update(11_979, 0);
+    assert!(close(encoder.angle(), 0.0));
+}
+
+#[test]
+fn speed_in_degrees_per_second() {
+    let mut encoder = Quadrature::new(CPR, 0);
+    assert_eq!(encoder.speed(), 0.0);
+    let mut raw = 0;
+    // 10 counts per ms, 2.5 turns per second
+    spin(&mut encoder, &mut raw, 10, 50, 0);
+    assert!(close(encoder.speed(), 900.0), "{}", encoder.speed());
+    spin(&mut encoder, &mut raw, -10, 50, 50_000);
+    assert!(close(encoder.speed(), -900.0), "{}", encoder.speed());
+}
+
+#[test]
+fn slow_speed_uses_a_longer_window() {
+    let mut encoder = Quadrature::new(CPR, 0);
+    let mut raw = 0;
+    // a count every other millisecond
+    for n in 1..=40u32 {
+        if n % 2 == 0 {
+            raw += 1;
+        }
+        encoder.update(raw, n * 1000);
+    }
+    let expected = 0.5 * 360.0 / CPR as f32 * 1000.0;
+    let speed = encoder.speed();
+    // a two sample window would read 0 or twice the speed
+    assert!(
+        (speed - expected).abs() < expected / SPEED_MIN_COUNTS as f32,
+        "{}",
+        speed
+    );
+}
+
+#[test]
+fn standing_still_is_zero() {
+    let mut encoder = Quadrature::new(CPR, 7);
+    for n in 0..SPEED_SAMPLES as u32 * 2 {
+        encoder.update(7, n * 1000);
+    }
+    assert_eq!(encoder.speed(), 0.0);
+}
+
+#[test]
+fn survives_the_microsecond_counter_wrapping() {
+    let mut encoder = Quadrature::new(CPR, 0);
+    let mut raw = 0;
+    spin(&mut encoder, &mut raw, 10, 20, u32::MAX - 5_000);
+    assert!(close(encoder.speed(), 900.0), "{}", encoder.speed());
+}
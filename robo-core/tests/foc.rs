@@ -0,0 +1,155 @@
+use core::f32::consts::PI;
+
+use robo_core::foc::{
+    self, AlphaBeta, CurrentLoop, Dq, ElectricalAngle, Pi, SinCos, MAX_MODULATION,
+};
+use robo_core::sensor;
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 2e-3
+}
+
+/// Balanced phase currents of amplitude `amplitude` at electrical angle
+/// `angle`, leading the flux by `lead`.
+fn phases(amplitude: f32, angle: f32, lead: f32) -> (f32, f32) {
+    let a = amplitude * (angle + lead).cos();
+    let b = amplitude * (angle + lead - 2.0 * PI / 3.0).cos();
+    (a, b)
+}
+
+#[test]
+fn clarke_round_trip() {
+    let (a, b) = phases(2.0, 0.7, 0.0);
+    let abc = foc::inverse_clarke(foc::clarke(a, b));
+    assert!(close(abc.a, a) && close(abc.b, b));
+    assert!(close(abc.a + abc.b + abc.c, 0.0));
+}
+
+#[test]
+fn clarke_keeps_the_amplitude() {
+    for n in 0..12 {
+        let (a, b) = phases(3.0, n as f32 * PI / 6.0, 0.0);
+        let stator = foc::clarke(a, b);
+        let length = (stator.alpha * stator.alpha + stator.beta * stator.beta).sqrt();
+        assert!(close(length, 3.0), "{}", length);
+    }
+}
+
+#[test]
+fn rotating_currents_are_constant_in_the_rotor_frame() {
+    // pure torque current, a quarter turn ahead of the flux
+    for n in 0..24 {
+        let angle = sensor::wrap(n as f32 * 0.5);
+        let (a, b) = phases(1.5, angle, PI / 2.0);
+        let rotor = foc::park(foc::clarke(a, b), SinCos::new(angle));
+        assert!(close(rotor.d, 0.0), "{:?}", rotor);
+        assert!(close(rotor.q, 1.5), "{:?}", rotor);
+    }
+}
+
+#[test]
+fn park_round_trip() {
+    let angle = SinCos::new(-2.2);
+    let rotor = Dq { d: 0.3, q: -1.2 };
+    let back = foc::park(foc::inverse_park(rotor, angle), angle);
+    assert!(close(back.d, rotor.d) && close(back.q, rotor.q));
+    assert!(close(rotor.magnitude(), (0.09f32 + 1.44).sqrt()));
+}
+
+#[test]
+fn svpwm_centres_the_duties() {
+    let bus = 12.0;
+    for n in 0..36 {
+        let angle = n as f32 * PI / 18.0;
+        let voltage = AlphaBeta {
+            alpha: 5.0 * angle.cos(),
+            beta: 5.0 * angle.sin(),
+        };
+        let duty = foc::svpwm(voltage, bus);
+        for phase in [duty.a, duty.b, duty.c] {
+            assert!((0.0..=1.0).contains(&phase), "{:?}", duty);
+        }
+        let high = duty.a.max(duty.b).max(duty.c);
+        let low = duty.a.min(duty.b).min(duty.c);
+        assert!(close(high + low, 1.0));
+
+        // the line voltages are what was asked for
+        let wanted = foc::inverse_clarke(voltage);
+        assert!(close((duty.a - duty.b) * bus, wanted.a - wanted.b));
+        assert!(close((duty.b - duty.c) * bus, wanted.b - wanted.c));
+    }
+}
+
+#[test]
+fn svpwm_limits_to_the_hexagon_circle() {
+    let duty = foc::svpwm(
+        AlphaBeta {
+            alpha: 0.0,
+            beta: 100.0,
+        },
+        10.0,
+    );
+    // full line voltage between b and c at the limit
+    assert!(close(duty.b - duty.c, MAX_MODULATION * 3f32.sqrt()));
+    assert!(close(duty.b, 1.0) && close(duty.c, 0.0));
+    // zero is all phases at half
+    let idle = foc::svpwm(AlphaBeta::default(), 10.0);
+    assert!(close(idle.a, 0.5) && close(idle.b, 0.5) && close(idle.c, 0.5));
+}
+
+#[test]
+fn pi_integrates_and_stops_at_the_limit() {
+    let mut pi = Pi::new(1.0, 0.1);
+    assert!(close(pi.update(1.0, 10.0), 1.1));
+    assert!(close(pi.update(1.0, 10.0), 1.2));
+
+    // held at the limit, the integral does not wind up
+    for _ in 0..1000 {
+        assert_eq!(pi.update(5.0, 2.0), 2.0);
+    }
+    assert!(pi.update(-1.0, 2.0) < 1.5);
+    pi.reset();
+    assert!(close(pi.update(0.0, 2.0), 0.0));
+}
+
+#[test]
+fn current_loop_settles_on_a_winding() {
+    // 1 ohm, 1 mH, 10 kHz, simulated by forward Euler
+    let (resistance, inductance, sample_hz) = (1.0, 1e-3, 10_000.0);
+    let mut current_loop = CurrentLoop::tuned(resistance, inductance, 500.0, sample_hz);
+    let target = Dq { d: 0.0, q: 2.0 };
+    let mut current = Dq::default();
+    for _ in 0..100 {
+        let voltage = current_loop.update(target, current, 24.0);
+        current.d += (voltage.d - resistance * current.d) / inductance / sample_hz;
+        current.q += (voltage.q - resistance * current.q) / inductance / sample_hz;
+    }
+    assert!((current.q - 2.0).abs() < 0.02, "{:?}", current);
+    assert!(current.d.abs() < 0.02, "{:?}", current);
+}
+
+#[test]
+fn current_loop_voltage_stays_reachable() {
+    let mut current_loop = CurrentLoop::tuned(1.0, 1e-3, 500.0, 10_000.0);
+    let voltage = current_loop.update(Dq { d: 50.0, q: 50.0 }, Dq::default(), 12.0);
+    assert!(voltage.magnitude() <= 12.0 * MAX_MODULATION + 1e-3);
+    // d takes what it needs first
+    assert!(close(voltage.d, 12.0 * MAX_MODULATION));
+    assert!(close(voltage.q, 0.0));
+}
+
+#[test]
+fn electrical_angle_from_the_alignment() {
+    let mut electrical = ElectricalAngle::new(7);
+    // rotor pulled to electrical zero settled at a shaft angle of 0.4
+    electrical.align(0.4);
+    assert!(close(electrical.electrical(0.4), 0.0));
+    // a pole pair further is the same electrical angle
+    assert!(close(electrical.electrical(0.4 + 2.0 * PI / 7.0), 0.0));
+    let quarter = 0.4 + PI / 2.0 / 7.0;
+    assert!(close(electrical.electrical(quarter), PI / 2.0));
+    assert!(close(
+        electrical.electrical(sensor::wrap(quarter + 2.0 * PI)),
+        PI / 2.0
+    ));
+}
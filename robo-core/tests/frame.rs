@@ -0,0 +1,85 @@
+mod common;
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+
+use common::Framebuffer;
+use robo_core::dashboard::{draw, Status, HEIGHT, WIDTH};
+use robo_core::frame::{Frame, FRAME_LEN, PAGE_COUNT, PAGE_LEN};
+
+fn set(frame: &mut Frame, x: i32, y: i32, color: BinaryColor) {
+    Pixel(Point::new(x, y), color).draw(frame).unwrap();
+}
+
+#[test]
+fn pages_hold_eight_rows() {
+    assert_eq!(PAGE_COUNT * 8, HEIGHT as usize);
+    assert_eq!(PAGE_LEN, WIDTH as usize);
+    assert_eq!(FRAME_LEN, 1024);
+
+    let mut frame = Frame::new();
+    set(&mut frame, 0, 0, BinaryColor::On);
+    set(&mut frame, 5, 7, BinaryColor::On);
+    set(&mut frame, 127, 63, BinaryColor::On);
+    set(&mut frame, 3, 9, BinaryColor::On);
+
+    assert_eq!(frame.page(0)[0], 0x01);
+    assert_eq!(frame.page(0)[5], 0x80);
+    assert_eq!(frame.page(1)[3], 0x02);
+    assert_eq!(frame.page(7)[127], 0x80);
+    assert_eq!(
+        frame.as_bytes().iter().filter(|byte| **byte != 0).count(),
+        4
+    );
+}
+
+#[test]
+fn off_clears_only_its_bit() {
+    let mut frame = Frame::new();
+    set(&mut frame, 10, 16, BinaryColor::On);
+    set(&mut frame, 10, 17, BinaryColor::On);
+    set(&mut frame, 10, 16, BinaryColor::Off);
+    assert_eq!(frame.page(2)[10], 0x02);
+    assert!(frame.get(Point::new(10, 17)));
+    assert!(!frame.get(Point::new(10, 16)));
+}
+
+#[test]
+fn off_screen_pixels_are_dropped() {
+    let mut frame = Frame::new();
+    for (x, y) in [(-1, 0), (0, -1), (128, 0), (0, 64)] {
+        set(&mut frame, x, y, BinaryColor::On);
+        assert!(!frame.get(Point::new(x, y)));
+    }
+    assert!(frame.as_bytes().iter().all(|byte| *byte == 0));
+}
+
+#[test]
+fn clear_fills_every_byte() {
+    let mut frame = Frame::new();
+    frame.clear(BinaryColor::On).unwrap();
+    assert!(frame.as_bytes().iter().all(|byte| *byte == 0xff));
+    frame.clear(BinaryColor::Off).unwrap();
+    assert!(frame.as_bytes().iter().all(|byte| *byte == 0));
+}
+
+#[test]
+fn renders_like_the_reference() {
+    let status = Status {
+        state: "cw",
+        duty: 0.45,
+        speed: 1234.0,
+        angle: 123.0,
+        battery: 7.4,
+        faults: &["stack_low"],
+    };
+    let reference = Framebuffer::render(&status);
+    let mut frame = Frame::new();
+    draw(&mut frame, &status).unwrap();
+    for y in 0..HEIGHT as i32 {
+        for x in 0..WIDTH as i32 {
+            let point = Point::new(x, y);
+            assert_eq!(frame.get(point), reference.get(point), "{:?}", point);
+        }
+    }
+}
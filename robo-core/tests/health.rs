@@ -0,0 +1,96 @@
+use robo_core::health::{Health, Stat, ATTEMPTS, FAIL_AFTER};
+use robo_core::sensor::{Error, Status};
+
+/// A read playing back `results`, the last one repeats.
+fn reads(results: &[Result<u16, Error>]) -> impl FnMut() -> Result<u16, Error> + '_ {
+    let mut n = 0;
+    move || {
+        let result = results[n.min(results.len() - 1)];
+        n += 1;
+        result
+    }
+}
+
+#[test]
+fn decodes_the_register() {
+    // read status bit and slave number set, magnet lost, overflow
+    let stat = Stat::from_register(0x8000 | 0x2000 | 1 << 7 | 1 << 5);
+    assert_eq!(stat, Stat::MAGNET_LOSS.union(Stat::OVERFLOW));
+    assert_eq!(
+        stat.names().collect::<Vec<_>>(),
+        ["overflow", "magnet_loss"]
+    );
+    assert_eq!(stat.status(), Status::SIGNAL_LOST.union(Status::OVERFLOW));
+}
+
+#[test]
+fn maps_onto_status() {
+    assert!(Stat::OK.status().is_ok());
+    // a reset is no fault
+    assert!(Stat::RESET.status().is_ok());
+    assert_eq!(Stat::XY_OUT_OF_LIMIT.status(), Status::SIGNAL_LOST);
+    assert_eq!(Stat::NO_GMR_ANGLE.status(), Status::DATA_LOST);
+    for fault in [
+        Stat::WATCHDOG,
+        Stat::VOLTAGE,
+        Stat::FUSE_CRC,
+        Stat::DSPU,
+        Stat::ADC_TEST,
+        Stat::ROM_CRC,
+    ] {
+        assert_eq!(fault.status(), Status::FAULT, "{:?}", fault);
+    }
+}
+
+#[test]
+fn transient_errors_are_retried() {
+    let mut health = Health::new();
+    let results = [Err(Error::Crc), Ok(7)];
+    assert_eq!(health.retry(reads(&results)), Ok(7));
+    assert_eq!(health.reads(), 1);
+    assert_eq!(health.retries(), 1);
+    assert_eq!(health.failures(), 0);
+    assert_eq!(health.errors(Error::Crc), 1);
+    assert!(!health.is_failing());
+}
+
+#[test]
+fn gives_up_after_the_attempts() {
+    let mut health = Health::new();
+    let mut tries = 0;
+    let result: Result<u16, Error> = health.retry(|| {
+        tries += 1;
+        Err(Error::Bus)
+    });
+    assert_eq!(result, Err(Error::Bus));
+    assert_eq!(tries, ATTEMPTS);
+    assert_eq!(health.errors(Error::Bus), ATTEMPTS);
+    assert_eq!(health.retries(), ATTEMPTS - 1);
+    assert_eq!(health.failures(), 1);
+}
+
+#[test]
+fn not_ready_is_not_retried() {
+    let mut health = Health::new();
+    let results = [Err(Error::NotReady), Ok(1)];
+    assert_eq!(health.retry(reads(&results)), Err(Error::NotReady));
+    assert_eq!(health.retries(), 0);
+    assert_eq!(health.errors(Error::NotReady), 1);
+}
+
+#[test]
+fn failing_after_reads_in_a_row() {
+    let mut health = Health::new();
+    for _ in 1..FAIL_AFTER {
+        health.retry(reads(&[Err(Error::Crc)])).ok();
+    }
+    assert!(!health.is_failing());
+    health.retry(reads(&[Err(Error::Crc)])).ok();
+    assert!(health.is_failing());
+
+    // one good read is enough to recover
+    health.retry(reads(&[Ok(0)])).ok();
+    assert!(!health.is_failing());
+    assert_eq!(health.failures(), FAIL_AFTER);
+    assert_eq!(health.reads(), FAIL_AFTER + 1);
+}
@@ -0,0 +1,120 @@
+use robo_core::led::{Layer, Led, Pattern, FULL};
+
+/// Lit stretches of `pattern` over `period_ms`, as `(start, length)`.
+fn flashes(pattern: Pattern, period_ms: u32) -> Vec<(u32, u32)> {
+    let mut flashes: Vec<(u32, u32)> = Vec::new();
+    let mut lit = false;
+    for t in 0..period_ms {
+        let now = pattern.level(t) == FULL;
+        match (lit, now) {
+            (false, true) => flashes.push((t, 1)),
+            (true, true) => flashes.last_mut().unwrap().1 += 1,
+            _ => {}
+        }
+        lit = now;
+    }
+    flashes
+}
+
+#[test]
+fn parses_names() {
+    assert_eq!(Pattern::parse("off"), Some(Pattern::Off));
+    assert_eq!(Pattern::parse("on"), Some(Pattern::Steady(100)));
+    assert_eq!(Pattern::parse("steady 40"), Some(Pattern::Steady(40)));
+    assert_eq!(Pattern::parse(" heartbeat "), Some(Pattern::Heartbeat));
+    assert_eq!(Pattern::parse("blink"), Some(Pattern::FastBlink));
+    assert_eq!(Pattern::parse("code 3"), Some(Pattern::Code(3)));
+    assert_eq!(Pattern::parse("breathe"), Some(Pattern::Breathing));
+
+    for bad in [
+        "",
+        "code",
+        "code 0",
+        "code 10",
+        "steady 101",
+        "on 1",
+        "disco",
+    ] {
+        assert_eq!(Pattern::parse(bad), None, "{:?}", bad);
+    }
+}
+
+#[test]
+fn heartbeat_flashes_twice() {
+    assert_eq!(flashes(Pattern::Heartbeat, 1200), [(0, 100), (250, 100)]);
+    assert_eq!(Pattern::Heartbeat.level(1200), FULL);
+}
+
+#[test]
+fn fast_blink_at_5_hz() {
+    assert_eq!(flashes(Pattern::FastBlink, 1000).len(), 5);
+}
+
+#[test]
+fn code_blinks_its_number_then_pauses() {
+    let blinks = flashes(Pattern::Code(3), 3000);
+    assert_eq!(blinks, [(0, 200), (500, 200), (1000, 200)]);
+    // and again after the pause
+    assert_eq!(Pattern::Code(3).level(3000), FULL);
+    assert_eq!(flashes(Pattern::Code(1), 2000), [(0, 200)]);
+}
+
+#[test]
+fn breathing_fades_in_and_out() {
+    let breathing = Pattern::Breathing;
+    assert_eq!(breathing.level(0), 0);
+    assert_eq!(breathing.level(1500), FULL);
+    assert_eq!(breathing.level(3000), 0);
+    assert!(breathing.level(750) < FULL / 2);
+    assert_eq!(breathing.level(750), breathing.level(2250));
+    let rising = (0..1500).map(|t| breathing.level(t)).collect::<Vec<_>>();
+    assert!(rising.windows(2).all(|pair| pair[0] <= pair[1]));
+}
+
+#[test]
+fn steady_and_off() {
+    assert_eq!(Pattern::Steady(40).level(123), 400);
+    assert_eq!(Pattern::Steady(100).level(0), FULL);
+    assert_eq!(Pattern::Off.level(0), 0);
+}
+
+#[test]
+fn higher_layers_win() {
+    let mut led = Led::new();
+    assert_eq!(led.active(), None);
+    assert_eq!(led.level(0), 0);
+
+    led.set(Layer::Status, Pattern::Steady(10), 0);
+    led.set(Layer::Fault, Pattern::Steady(90), 0);
+    led.set(Layer::User, Pattern::Steady(50), 0);
+    assert_eq!(led.active(), Some((Layer::Fault, Pattern::Steady(90))));
+    assert_eq!(led.level(0), 900);
+
+    led.clear(Layer::Fault);
+    assert_eq!(led.level(0), 500);
+    led.clear(Layer::User);
+    assert_eq!(led.active(), Some((Layer::Status, Pattern::Steady(10))));
+}
+
+#[test]
+fn patterns_play_from_when_they_were_set() {
+    let mut led = Led::new();
+    led.set(Layer::Status, Pattern::FastBlink, 50);
+    assert_eq!(led.level(50), FULL);
+    assert_eq!(led.level(150), 0);
+
+    // setting it again does not restart it
+    led.set(Layer::Status, Pattern::FastBlink, 150);
+    assert_eq!(led.level(150), 0);
+    led.set(Layer::Status, Pattern::Heartbeat, 150);
+    assert_eq!(led.level(150), FULL);
+}
+
+#[test]
+fn survives_the_millisecond_counter_wrapping() {
+    let mut led = Led::new();
+    let start = u32::MAX - 50;
+    led.set(Layer::Fault, Pattern::FastBlink, start);
+    assert_eq!(led.level(start.wrapping_add(100)), 0);
+    assert_eq!(led.level(start.wrapping_add(200)), FULL);
+}
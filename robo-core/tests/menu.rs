@@ -0,0 +1,159 @@
+use robo_core::button::Gesture;
+use robo_core::menu::{
+    Action, Input, Item, Menu, Screen, Settings, ITEMS, PWM_FREQUENCIES, SETPOINT_MAX,
+    SETPOINT_STEP,
+};
+
+const SETTINGS: Settings = Settings {
+    setpoint_rpm: 0,
+    pwm_hz: 500,
+    armed: false,
+};
+
+fn open(cursor: usize) -> Menu {
+    let mut menu = Menu::new();
+    menu.input(Input::Enter, &SETTINGS);
+    for _ in 0..cursor {
+        menu.input(Input::Next, &SETTINGS);
+    }
+    menu
+}
+
+#[test]
+fn single_button_gestures() {
+    assert_eq!(Input::from_gesture(Gesture::Short), Some(Input::Next));
+    assert_eq!(Input::from_gesture(Gesture::Long), Some(Input::Enter));
+    assert_eq!(Input::from_gesture(Gesture::Double), Some(Input::Back));
+    assert_eq!(Input::from_gesture(Gesture::Repeat), None);
+}
+
+#[test]
+fn status_only_opens_on_enter() {
+    let mut menu = Menu::new();
+    for input in [Input::Next, Input::Prev, Input::Back] {
+        assert_eq!(menu.input(input, &SETTINGS), None);
+        assert_eq!(menu.screen(), Screen::Status);
+    }
+    menu.input(Input::Enter, &SETTINGS);
+    assert_eq!(menu.screen(), Screen::List { cursor: 0 });
+}
+
+#[test]
+fn list_wraps_both_ways() {
+    let mut menu = open(0);
+    menu.input(Input::Prev, &SETTINGS);
+    assert_eq!(
+        menu.screen(),
+        Screen::List {
+            cursor: ITEMS.len() - 1
+        }
+    );
+    menu.input(Input::Next, &SETTINGS);
+    assert_eq!(menu.screen(), Screen::List { cursor: 0 });
+}
+
+#[test]
+fn back_closes_and_keeps_the_cursor() {
+    let mut menu = open(2);
+    menu.input(Input::Back, &SETTINGS);
+    assert_eq!(menu.screen(), Screen::Status);
+    menu.input(Input::Enter, &SETTINGS);
+    assert_eq!(menu.screen(), Screen::List { cursor: 2 });
+}
+
+#[test]
+fn edit_setpoint() {
+    let settings = Settings {
+        setpoint_rpm: SETPOINT_MAX - SETPOINT_STEP,
+        ..SETTINGS
+    };
+    let mut menu = open(0);
+    menu.input(Input::Enter, &settings);
+    assert_eq!(
+        menu.screen(),
+        Screen::Edit {
+            item: Item::Setpoint,
+            value: SETPOINT_MAX - SETPOINT_STEP
+        }
+    );
+
+    // wraps over to reverse
+    menu.input(Input::Next, &settings);
+    menu.input(Input::Next, &settings);
+    assert_eq!(
+        menu.input(Input::Enter, &settings),
+        Some(Action::SetSetpoint(-SETPOINT_MAX))
+    );
+    assert_eq!(menu.screen(), Screen::List { cursor: 0 });
+}
+
+#[test]
+fn edit_back_discards() {
+    let mut menu = open(0);
+    menu.input(Input::Enter, &SETTINGS);
+    menu.input(Input::Next, &SETTINGS);
+    assert_eq!(menu.input(Input::Back, &SETTINGS), None);
+    assert_eq!(menu.screen(), Screen::List { cursor: 0 });
+}
+
+#[test]
+fn edit_pwm_frequency() {
+    let settings = Settings {
+        pwm_hz: 2_000,
+        ..SETTINGS
+    };
+    let mut menu = open(1);
+    menu.input(Input::Enter, &settings);
+    let Screen::Edit { item, value } = menu.screen() else {
+        panic!("{:?}", menu.screen());
+    };
+    assert_eq!(item, Item::PwmFrequency);
+    assert_eq!(Menu::edit_value(item, value), 2_000);
+
+    menu.input(Input::Prev, &settings);
+    menu.input(Input::Prev, &settings);
+    menu.input(Input::Prev, &settings);
+    let last = PWM_FREQUENCIES[PWM_FREQUENCIES.len() - 1];
+    assert_eq!(
+        menu.input(Input::Enter, &settings),
+        Some(Action::SetPwmFrequency(last))
+    );
+}
+
+#[test]
+fn arm_toggles() {
+    let mut menu = open(2);
+    assert_eq!(menu.input(Input::Enter, &SETTINGS), Some(Action::Arm));
+    let armed = Settings {
+        armed: true,
+        ..SETTINGS
+    };
+    assert_eq!(menu.input(Input::Enter, &armed), Some(Action::Disarm));
+    assert_eq!(menu.screen(), Screen::List { cursor: 2 });
+}
+
+#[test]
+fn calibrate_needs_armed_motor_and_confirmation() {
+    let mut menu = open(3);
+    assert_eq!(menu.input(Input::Enter, &SETTINGS), None);
+    assert_eq!(menu.screen(), Screen::List { cursor: 3 });
+
+    let armed = Settings {
+        armed: true,
+        ..SETTINGS
+    };
+    menu.input(Input::Enter, &armed);
+    assert_eq!(
+        menu.screen(),
+        Screen::Confirm {
+            item: Item::Calibrate
+        }
+    );
+    assert_eq!(menu.input(Input::Next, &armed), None);
+    assert_eq!(menu.input(Input::Back, &armed), None);
+    assert_eq!(menu.screen(), Screen::List { cursor: 3 });
+
+    menu.input(Input::Enter, &armed);
+    assert_eq!(menu.input(Input::Enter, &armed), Some(Action::Calibrate));
+    assert_eq!(menu.screen(), Screen::Status);
+}
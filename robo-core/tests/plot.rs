@@ -0,0 +1,188 @@
+mod common;
+
+use embedded_graphics::prelude::*;
+
+use common::Framebuffer;
+use robo_core::dashboard::HEIGHT;
+use robo_core::plot::{Error, Pattern, Plot, COLUMNS, PLOT_TOP, TRACE_COUNT};
+use robo_core::telemetry::{Channel, ChannelSet, Snapshot};
+
+const RATE_HZ: u32 = 1_000;
+
+fn snapshot(speed: f32, setpoint: f32) -> Snapshot {
+    let mut snapshot = Snapshot::default();
+    snapshot.set(Channel::Speed, speed);
+    snapshot.set(Channel::Setpoint, setpoint);
+    snapshot
+}
+
+fn plot(channels: &str) -> Plot {
+    let mut plot = Plot::new(RATE_HZ);
+    plot.set_channels(ChannelSet::parse(channels).unwrap())
+        .unwrap();
+    plot
+}
+
+fn render(plot: &Plot) -> Framebuffer {
+    let mut framebuffer = Framebuffer::new();
+    plot.draw(&mut framebuffer).unwrap();
+    framebuffer
+}
+
+/// Lit rows of column `x` in the plot area, below the scale labels.
+fn column(framebuffer: &Framebuffer, x: i32) -> Vec<i32> {
+    (PLOT_TOP..HEIGHT as i32)
+        .filter(|y| framebuffer.get(Point::new(x, *y)))
+        .collect()
+}
+
+#[test]
+fn disabled_plot_ignores_samples() {
+    let mut plot = Plot::new(RATE_HZ);
+    assert!(!plot.is_enabled());
+    plot.sample(&snapshot(1.0, 2.0));
+    assert!(plot.is_empty());
+}
+
+#[test]
+fn at_most_three_traces() {
+    let mut plot = Plot::new(RATE_HZ);
+    let four = ChannelSet::parse("angle,speed,duty,setpoint").unwrap();
+    assert_eq!(plot.set_channels(four), Err(Error::TooManyTraces));
+    assert!(!plot.is_enabled());
+    assert_eq!(TRACE_COUNT, 3);
+}
+
+#[test]
+fn columns_average_decimated_samples() {
+    let mut plot = plot("speed,setpoint");
+    plot.set_span_ms(4 * COLUMNS as u32);
+    assert_eq!(plot.decimation(), 4);
+
+    for speed in [1.0, 2.0, 3.0] {
+        plot.sample(&snapshot(speed, f32::NAN));
+    }
+    assert!(plot.is_empty());
+    plot.sample(&snapshot(6.0, 5.0));
+    assert_eq!(plot.len(), 1);
+    assert_eq!(plot.value(0, 0), Some(3.0));
+    // NaN left out of the average
+    assert_eq!(plot.value(1, 0), Some(5.0));
+
+    for _ in 0..4 {
+        plot.sample(&snapshot(f32::NAN, f32::NAN));
+    }
+    assert_eq!(plot.value(0, 1), None);
+}
+
+#[test]
+fn time_base() {
+    let mut plot = plot("speed");
+    assert_eq!(plot.span_ms(), COLUMNS as u32);
+    plot.set_span_ms(1_000);
+    assert_eq!(plot.decimation(), 8);
+    assert_eq!(plot.span_ms(), 1_024);
+    // never faster than the samples come in
+    plot.set_span_ms(10);
+    assert_eq!(plot.decimation(), 1);
+}
+
+#[test]
+fn scrolls_oldest_out() {
+    let mut plot = plot("speed");
+    for n in 0..COLUMNS + 10 {
+        plot.sample(&snapshot(n as f32, 0.0));
+    }
+    assert_eq!(plot.len(), COLUMNS);
+    assert_eq!(plot.value(0, 0), Some(10.0));
+    assert_eq!(plot.value(0, COLUMNS - 1), Some((COLUMNS + 9) as f32));
+    assert_eq!(plot.value(0, COLUMNS), None);
+}
+
+#[test]
+fn autoscales_to_visible_values() {
+    let mut plot = plot("speed,setpoint");
+    assert_eq!(plot.range(), None);
+    plot.sample(&snapshot(-20.0, 100.0));
+    plot.sample(&snapshot(50.0, f32::NAN));
+    assert_eq!(plot.range(), Some((-20.0, 100.0)));
+
+    let mut flat = self::plot("speed");
+    flat.sample(&snapshot(7.0, 0.0));
+    assert_eq!(flat.range(), Some((6.0, 8.0)));
+}
+
+#[test]
+fn extremes_span_the_plot_area() {
+    let range = (-1.0, 1.0);
+    assert_eq!(Plot::row(1.0, range), PLOT_TOP);
+    assert_eq!(Plot::row(-1.0, range), HEIGHT as i32 - 1);
+}
+
+#[test]
+fn newest_column_on_the_right() {
+    let mut plot = plot("speed");
+    for speed in [0.0, 10.0, 5.0] {
+        plot.sample(&snapshot(speed, 0.0));
+    }
+    let framebuffer = render(&plot);
+    assert_eq!(framebuffer.outside, 0);
+
+    let right = COLUMNS as i32 - 1;
+    let middle = Plot::row(5.0, (0.0, 10.0));
+    assert_eq!(
+        column(&framebuffer, right),
+        (PLOT_TOP + 1..=middle).collect::<Vec<_>>()
+    );
+    assert!(column(&framebuffer, right - 3).is_empty());
+}
+
+#[test]
+fn steps_are_joined() {
+    let mut plot = plot("speed");
+    plot.sample(&snapshot(0.0, 0.0));
+    plot.sample(&snapshot(10.0, 0.0));
+    let framebuffer = render(&plot);
+    let x = COLUMNS as i32 - 1;
+    let rows = column(&framebuffer, x);
+    // from the top down to just above the previous point
+    assert_eq!(rows.first(), Some(&PLOT_TOP));
+    assert_eq!(rows.last(), Some(&(HEIGHT as i32 - 2)));
+}
+
+#[test]
+fn traces_differ_by_pattern() {
+    let lit = |pattern: Pattern| (0..12).filter(|n| pattern.lit(*n)).count();
+    assert_eq!(lit(Pattern::Solid), 12);
+    assert_eq!(lit(Pattern::Dashed), 6);
+    assert_eq!(lit(Pattern::Dotted), 6);
+
+    // a full step with both traces on the same values
+    let mut plot = plot("speed,setpoint");
+    plot.sample(&snapshot(0.0, 0.0));
+    plot.sample(&snapshot(1.0, 1.0));
+    let solid = render(&plot);
+
+    // the second trace alone
+    let mut dashed = self::plot("speed,setpoint");
+    dashed.sample(&snapshot(f32::NAN, 0.0));
+    dashed.sample(&snapshot(f32::NAN, 1.0));
+    let dashed = render(&dashed);
+
+    let x = COLUMNS as i32 - 1;
+    let step = (HEIGHT as i32 - PLOT_TOP - 1) as usize;
+    assert_eq!(column(&solid, x).len(), step);
+    assert!(column(&dashed, x).len() < step * 2 / 3);
+}
+
+#[test]
+fn gaps_are_not_joined() {
+    let mut plot = plot("speed");
+    plot.sample(&snapshot(0.0, 0.0));
+    plot.sample(&snapshot(f32::NAN, 0.0));
+    plot.sample(&snapshot(10.0, 0.0));
+    let framebuffer = render(&plot);
+    let x = COLUMNS as i32 - 1;
+    assert_eq!(column(&framebuffer, x), [PLOT_TOP]);
+    assert!(column(&framebuffer, x - 1).is_empty());
+}
@@ -0,0 +1,101 @@
+use core::f32::consts::PI;
+
+use robo_core::sensor::{self, Error, Reading, RotarySensor, Status};
+
+/// Plays back a list of readings.
+struct Mock {
+    readings: Vec<Result<(f32, f32), Error>>,
+    status: Status,
+}
+
+impl RotarySensor for Mock {
+    fn angle(&mut self) -> Result<f32, Error> {
+        self.readings[0].map(|(angle, _)| angle)
+    }
+
+    fn velocity(&mut self) -> Result<f32, Error> {
+        let reading = self.readings.remove(0);
+        reading.map(|(_, velocity)| velocity)
+    }
+
+    fn status(&mut self) -> Result<Status, Error> {
+        Ok(self.status)
+    }
+
+    fn resolution(&self) -> f32 {
+        2.0 * PI / 4096.0
+    }
+}
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-4
+}
+
+#[test]
+fn read_takes_both() {
+    let mut mock = Mock {
+        readings: vec![Ok((1.0, 2.0)), Err(Error::Crc)],
+        status: Status::OK,
+    };
+    assert_eq!(
+        mock.read(),
+        Ok(Reading {
+            angle: 1.0,
+            velocity: 2.0
+        })
+    );
+    assert_eq!(mock.read(), Err(Error::Crc));
+}
+
+#[test]
+fn sensors_are_interchangeable() {
+    fn describe(sensor: &mut dyn RotarySensor) -> (bool, f32) {
+        (sensor.status().unwrap().is_ok(), sensor.resolution())
+    }
+    let mut mock = Mock {
+        readings: Vec::new(),
+        status: Status::SIGNAL_LOST,
+    };
+    assert_eq!(describe(&mut mock), (false, 2.0 * PI / 4096.0));
+}
+
+#[test]
+fn status_flags() {
+    let mut status = Status::OK;
+    assert!(status.is_ok());
+    assert_eq!(status.names().count(), 0);
+
+    status.insert(Status::OVERFLOW);
+    let status = status.union(Status::FAULT);
+    assert!(!status.is_ok());
+    assert!(status.contains(Status::OVERFLOW));
+    assert!(!status.contains(Status::SIGNAL_LOST));
+    assert!(status.contains(Status::OVERFLOW.union(Status::FAULT)));
+    assert_eq!(status.names().collect::<Vec<_>>(), ["overflow", "fault"]);
+}
+
+#[test]
+fn wraps_into_one_turn() {
+    assert!(close(sensor::wrap(0.5), 0.5));
+    assert!(close(sensor::wrap(PI + 0.5), -PI + 0.5));
+    assert!(close(sensor::wrap(-PI - 0.5), PI - 0.5));
+    assert!(close(sensor::wrap(7.0 * 2.0 * PI + 1.0), 1.0));
+    assert!(close(sensor::wrap(-7.0 * 2.0 * PI - 1.0), -1.0));
+    // the top of the range belongs to the bottom
+    assert!(close(sensor::wrap(PI), -PI));
+}
+
+#[test]
+fn unit_conversions() {
+    assert!(close(sensor::degrees(PI), 180.0));
+    assert!(close(sensor::radians(-90.0), -PI / 2.0));
+    assert!(close(sensor::rpm(2.0 * PI), 60.0));
+    assert!(close(sensor::rpm(sensor::radians(6.0)), 1.0));
+}
+
+#[test]
+fn error_names() {
+    assert_eq!(Error::Bus.name(), "bus");
+    assert_eq!(Error::Crc.name(), "crc");
+    assert_eq!(Error::NotReady.name(), "not_ready");
+}
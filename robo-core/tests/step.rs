@@ -0,0 +1,142 @@
+use core::f32::consts::PI;
+
+use robo_core::step::{metrics, StepRecorder, SETTLING_BAND};
+
+/// 1 ms sampling over 2 s
+const PERIOD: f32 = 1e-3;
+const SAMPLES: usize = 2000;
+
+fn response(f: impl Fn(f32) -> f32) -> Vec<f32> {
+    (0..SAMPLES).map(|n| f(n as f32 * PERIOD)).collect()
+}
+
+fn near(value: f32, expected: f32, tolerance: f32) -> bool {
+    (value - expected).abs() <= tolerance
+}
+
+#[test]
+fn first_order_response() {
+    // 100 ms time constant, from 10 up to 40
+    let tau = 0.1;
+    let samples = response(|t| 10.0 + 30.0 * (1.0 - (-t / tau).exp()));
+    let metrics = metrics(&samples, PERIOD).unwrap();
+
+    assert_eq!(metrics.initial, 10.0);
+    assert!(near(metrics.steady_state, 40.0, 1e-3));
+    // 10 % to 90 % takes ln 9 time constants
+    let rise = metrics.rise_time.unwrap();
+    assert!(near(rise, tau * 9f32.ln(), 2.0 * PERIOD), "{}", rise);
+    assert_eq!(metrics.overshoot, 0.0);
+    // within the band after ln(1 / band) time constants
+    let settling = metrics.settling_time.unwrap();
+    assert!(
+        near(settling, tau * (1.0 / SETTLING_BAND).ln(), 2.0 * PERIOD),
+        "{}",
+        settling
+    );
+}
+
+#[test]
+fn second_order_response() {
+    // damping 0.5 at 2 Hz, a step down from 100 to 0
+    let (zeta, omega) = (0.5f32, 2.0 * PI * 2.0);
+    let damped = omega * (1.0 - zeta * zeta).sqrt();
+    let phase = zeta.acos();
+    let samples = response(|t| {
+        let decay = (-zeta * omega * t).exp() / (1.0 - zeta * zeta).sqrt();
+        100.0 * decay * (damped * t + phase).sin()
+    });
+    let metrics = metrics(&samples, PERIOD).unwrap();
+
+    assert!(near(metrics.initial, 100.0, 1e-3));
+    assert!(near(metrics.steady_state, 0.0, 0.01));
+    // exp(-π ζ / √(1 - ζ²))
+    let overshoot = 100.0 * (-PI * zeta / (1.0 - zeta * zeta).sqrt()).exp();
+    assert!(near(metrics.overshoot, overshoot, 0.2), "{:?}", metrics);
+
+    // the rise ends before the peak, settling between the peak and the
+    // time the envelope enters the band
+    let peak = PI / damped;
+    let envelope = (1.0 / (SETTLING_BAND * (1.0 - zeta * zeta).sqrt())).ln() / (zeta * omega);
+    let rise = metrics.rise_time.unwrap();
+    assert!(rise > 0.0 && rise < peak, "{:?}", metrics);
+    let settling = metrics.settling_time.unwrap();
+    assert!(settling > peak && settling <= envelope, "{:?}", metrics);
+}
+
+#[test]
+fn sustained_oscillation_never_settles() {
+    // 5 Hz around 50, the last tenth is a whole cycle
+    let samples = response(|t| match t {
+        t if t < 0.1 => 0.0,
+        t => 50.0 + 25.0 * (2.0 * PI * 5.0 * t).sin(),
+    });
+    let metrics = metrics(&samples, PERIOD).unwrap();
+
+    assert!(near(metrics.steady_state, 50.0, 0.1));
+    assert!(near(metrics.overshoot, 50.0, 0.5), "{:?}", metrics);
+    assert!(metrics.rise_time.is_some());
+    assert_eq!(metrics.settling_time, None);
+}
+
+#[test]
+fn no_change_or_no_readings() {
+    let flat = metrics(&[3.0; 100], PERIOD).unwrap();
+    assert_eq!(flat.steady_state, 3.0);
+    assert_eq!(flat.rise_time, None);
+    assert_eq!(flat.settling_time, None);
+
+    assert_eq!(metrics(&[f32::NAN; 100], PERIOD), None);
+    // sensor errors over the whole tail leave no steady state
+    let mut lost = [1.0; 100];
+    lost[90..].fill(f32::NAN);
+    assert_eq!(metrics(&lost, PERIOD), None);
+}
+
+#[test]
+fn read_errors_are_skipped() {
+    let tau = 0.1;
+    let mut samples = response(|t| 1.0 - (-t / tau).exp());
+    for n in (1..SAMPLES).step_by(7) {
+        samples[n] = f32::NAN;
+    }
+    let metrics = metrics(&samples, PERIOD).unwrap();
+    assert!(near(metrics.steady_state, 1.0, 1e-3));
+    assert!(near(
+        metrics.rise_time.unwrap(),
+        tau * 9f32.ln(),
+        3.0 * PERIOD
+    ));
+}
+
+#[test]
+fn recorder_decimates_to_fit() {
+    let mut recorder = StepRecorder::<100>::new();
+    recorder.start(450);
+    assert_eq!(recorder.decimation(), 5);
+
+    let done: Vec<_> = (0..450).map(|n| recorder.push(n as f32)).collect();
+    assert_eq!(done.iter().filter(|done| **done).count(), 1);
+    assert!(done[445]);
+    assert!(!recorder.is_running());
+    let expected: Vec<_> = (0..90).map(|n| (5 * n) as f32).collect();
+    assert_eq!(recorder.samples(), &expected[..]);
+
+    // once done, further ticks are dropped
+    assert!(!recorder.push(1.0));
+    assert_eq!(recorder.samples().len(), 90);
+}
+
+#[test]
+fn cancelled_recording_keeps_its_samples() {
+    let mut recorder = StepRecorder::<100>::new();
+    recorder.start(50);
+    assert_eq!(recorder.decimation(), 1);
+    for n in 0..20 {
+        recorder.push(n as f32);
+    }
+    recorder.cancel();
+    assert!(!recorder.is_running());
+    assert!(!recorder.push(99.0));
+    assert_eq!(recorder.samples().len(), 20);
+}
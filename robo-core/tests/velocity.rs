@@ -0,0 +1,146 @@
+use core::f32::consts::PI;
+
+use robo_core::sensor::{self, Error, Reading};
+use robo_core::velocity::{Estimator, Method, AVERAGE_MAX, BANDWIDTH_MIN, METHODS};
+
+const SAMPLE_HZ: u32 = 1000;
+
+/// Angle steps of the TLE5012, 15 bits a turn.
+const LSB: f32 = 2.0 * PI / 32768.0;
+
+/// Deterministic noise from -0.5 to 0.5.
+struct Noise(u32);
+
+impl Noise {
+    fn next(&mut self) -> f32 {
+        self.0 = self.0.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (self.0 >> 8) as f32 / (1 << 24) as f32 - 0.5
+    }
+}
+
+fn reading(angle: f32) -> Result<Reading, Error> {
+    Ok(Reading {
+        angle: sensor::wrap(angle),
+        velocity: f32::NAN,
+    })
+}
+
+/// Feeds `samples` of a shaft at `velocity` from `start`, quantized like the
+/// sensor with `noise` LSB on top. Returns the estimates.
+fn trace(
+    estimator: &mut Estimator,
+    start: f32,
+    velocity: f32,
+    samples: usize,
+    noise: f32,
+) -> Vec<f32> {
+    let mut random = Noise(7);
+    (0..samples)
+        .map(|n| {
+            let angle = start + velocity * n as f32 / SAMPLE_HZ as f32;
+            let read = ((angle / LSB).round() + noise * random.next()) * LSB;
+            estimator.update(reading(read))
+        })
+        .collect()
+}
+
+/// Mean and standard deviation of the estimates.
+fn stats(estimates: &[f32]) -> (f32, f32) {
+    let mean = estimates.iter().sum::<f32>() / estimates.len() as f32;
+    let variance = estimates
+        .iter()
+        .map(|estimate| (estimate - mean) * (estimate - mean))
+        .sum::<f32>()
+        / estimates.len() as f32;
+    (mean, variance.sqrt())
+}
+
+#[test]
+fn method_names() {
+    for method in METHODS {
+        assert_eq!(Method::from_name(method.name()), Some(method));
+    }
+    assert_eq!(Method::from_name("pll"), None);
+}
+
+#[test]
+fn every_method_finds_a_constant_speed() {
+    for method in [Method::Difference, Method::Average, Method::Tracking] {
+        let mut estimator = Estimator::new(method, SAMPLE_HZ, 20.0);
+        // 3 turns a second, across the wrap many times
+        let estimates = trace(&mut estimator, 3.0, 6.0 * PI, 2000, 0.0);
+        let (mean, _) = stats(&estimates[1000..]);
+        assert!((mean - 6.0 * PI).abs() < 0.01, "{:?} {}", method, mean);
+    }
+}
+
+#[test]
+fn filtering_cuts_the_noise_at_low_speed() {
+    // a tenth of a turn a second, 3 LSB a sample
+    let velocity = 0.2 * PI;
+    let spread = |method| {
+        let mut estimator = Estimator::new(method, SAMPLE_HZ, 5.0);
+        let estimates = trace(&mut estimator, 0.0, velocity, 3000, 2.0);
+        let (mean, deviation) = stats(&estimates[1000..]);
+        assert!(
+            (mean - velocity).abs() < 0.05 * velocity,
+            "{:?} {}",
+            method,
+            mean
+        );
+        deviation
+    };
+    let difference = spread(Method::Difference);
+    assert!(spread(Method::Average) < difference / 5.0);
+    assert!(spread(Method::Tracking) < difference / 5.0);
+}
+
+#[test]
+fn tracking_follows_a_speed_step() {
+    let mut estimator = Estimator::new(Method::Tracking, SAMPLE_HZ, 10.0);
+    trace(&mut estimator, 0.0, 0.0, 500, 0.0);
+    let estimates = trace(&mut estimator, 0.0, 10.0, 500, 0.0);
+    // settled well within the 500 ms, no lasting error on a ramp
+    assert!((estimates[499] - 10.0).abs() < 0.1, "{}", estimates[499]);
+    // a critically damped loop hardly overshoots
+    let peak = estimates.iter().fold(0.0f32, |peak, v| peak.max(*v));
+    assert!(peak < 10.0 * 1.15, "{}", peak);
+}
+
+#[test]
+fn bandwidth_sets_the_window() {
+    let mut estimator = Estimator::new(Method::Average, SAMPLE_HZ, 44.3);
+    assert_eq!(estimator.window(), 10);
+    estimator.set_bandwidth(1000.0);
+    assert_eq!(estimator.bandwidth(), estimator.max_bandwidth());
+    estimator.set_bandwidth(0.0);
+    assert_eq!(estimator.bandwidth(), BANDWIDTH_MIN);
+    assert_eq!(estimator.window(), AVERAGE_MAX);
+}
+
+#[test]
+fn failed_reads_are_bridged() {
+    for method in [Method::Difference, Method::Average, Method::Tracking] {
+        let mut estimator = Estimator::new(method, SAMPLE_HZ, 20.0);
+        trace(&mut estimator, 0.0, 5.0, 1000, 0.0);
+        let before = estimator.velocity();
+        // two periods lost, the next angle spans three
+        assert_eq!(estimator.update(Err(Error::Crc)), before);
+        assert_eq!(estimator.update(Err(Error::Crc)), before);
+        let after = estimator.update(reading(5.0 * 1.002));
+        assert!((after - 5.0).abs() < 0.05, "{:?} {}", method, after);
+    }
+}
+
+#[test]
+fn sensor_passes_through() {
+    let mut estimator = Estimator::new(Method::Sensor, SAMPLE_HZ, 20.0);
+    let velocity = estimator.update(Ok(Reading {
+        angle: 0.0,
+        velocity: 1.5,
+    }));
+    assert_eq!(velocity, 1.5);
+    estimator.set_method(Method::Difference);
+    assert_eq!(estimator.velocity(), 0.0);
+    assert_eq!(estimator.method(), Method::Difference);
+}